@@ -1,18 +1,43 @@
 use crate::{
-    config::Config,
+    config::{Config, ConfigError, Format, CONFIG_FILE},
+    home::{self, Home},
     ops::{
-        op::{Operate, OperationError},
-        AddChanges,
+        archive::{Export, Import},
+        backup::{self, Backups, Rollback},
+        bootstrap::Bootstrap,
+        convert::{Convert, Target},
+        diff::{Diff, DiffMode},
+        doctor::Doctor,
+        edit::Edit,
+        filter::{Filter, FilterError},
+        git::GitError,
+        init::Init,
+        log::Log,
+        migrate::{ImportFrom, Source},
+        op::{Operate, Operation, OperationError},
+        plan::{Apply, Step},
+        reformat::Reformat,
+        report::Report,
+        restore::Restore,
+        status::Status,
+        track::Track,
+        update_branch,
+        watch::Watch,
+        AddChanges, InstallPackages, UpdateLocal,
     },
     output::{
         logger::Logger,
         verbosity::{Verbose, Verbosity},
         TerminalLogger,
     },
+    settings::Settings,
 };
-use clap::{Parser, Subcommand};
+use clap::{ArgEnum, Parser, Subcommand};
 use indicatif::ProgressBar;
-use std::path::{Path, PathBuf};
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
 #[derive(Debug, Parser)]
 #[clap(about = "A tool for installing & managing ne/any-dots.")]
@@ -31,14 +56,49 @@ pub(crate) struct Args {
     /// Silences output.
     quiet: bool,
 
+    #[clap(short = 'n', long)]
+    /// Print what would be copied, committed, pushed & installed, without
+    /// changing anything.
+    dry_run: bool,
+
     #[clap(short, long)]
     /// Instead of sourcing the path from `nedots.json`, it can be passed
-    /// as an argument.
+    /// as an argument, or set with $NEDOTS_PATH.
     path: Option<String>,
 
+    #[clap(long)]
+    /// Manage this home directory instead of $HOME.
+    home: Option<String>,
+
+    #[clap(long, conflicts_with = "home")]
+    /// Manage this user's home directory, files written to it are given to
+    /// them.
+    user: Option<String>,
+
+    #[clap(long)]
+    /// Read this `nedots.json`, `.toml` or `.yaml` instead of searching for
+    /// one, or set $NEDOTS_CONFIG. Otherwise $XDG_CONFIG_HOME/nedots, the root
+    /// of the current repository, ~/.nedots & the current directory are
+    /// searched.
+    config: Option<String>,
+
+    #[clap(long, arg_enum)]
+    /// Read config files as this format unless their extension is `.json`,
+    /// `.toml`, `.yaml` or `.yml`.
+    config_format: Option<Format>,
+
+    #[clap(long, arg_enum, default_value = "human")]
+    /// Print results as text, or as a single line of JSON for scripts.
+    output: OutputFormat,
+
     #[clap(subcommand)]
     /// Operation to perform.
-    pub(crate) cmd: Command,
+    pub(crate) cmd: Invocation,
+
+    #[clap(skip)]
+    /// Flags layered over `$NEDOTS_*` variables, the user settings file &
+    /// defaults, see `settle`.
+    settings: Settings,
 }
 
 impl Args {
@@ -53,7 +113,7 @@ impl Args {
         } else if self.quiet {
             None
         } else {
-            match self.verbose {
+            match self.settings.verbose.unwrap_or_default() {
                 0 => None,
                 1 => Some(Verbosity::Low),
                 2 => Some(Verbosity::Medium),
@@ -62,19 +122,130 @@ impl Args {
         }
     }
 
-    /// Get the `nedots` repository path if it was passed as an argument,
-    /// & canonicalize it.
+    /// Set the home directory to manage, then layer flags over `$NEDOTS_*`
+    /// variables, over the user settings file, over defaults.
+    pub(crate) fn settle(&mut self) -> Result<(), ConfigError> {
+        // Before anything asks where home is, since settings are found there.
+        match (&self.home, &self.user) {
+            (Some(dir), _) => home::set(Home::at(&Path::new(dir).canonicalize()?)),
+            (_, Some(name)) => home::set(Home::of_user(name)?),
+            _ => {}
+        }
+        if let Some(format) = self.config_format {
+            Format::assume(format);
+        }
+
+        let cmd = match &self.cmd {
+            Invocation::Plan { cmd, .. } | Invocation::Run(cmd) => cmd,
+        };
+        let (remote, branch) = cmd.git_flags();
+        let flags = Settings {
+            config: self.config.as_ref().map(PathBuf::from),
+            path: self.path.as_ref().map(PathBuf::from),
+            remote,
+            branch,
+            verbose: (self.verbose > 0).then_some(self.verbose),
+        };
+        self.settings = Settings::load()?.layer(flags);
+
+        Ok(())
+    }
+
+    /// Get the `nedots` repository path if it was passed as an argument or
+    /// set elsewhere, & canonicalize it.
     pub(crate) fn path(&self) -> Result<Option<PathBuf>, std::io::Error> {
-        if let Some(s) = self.path.as_ref() {
-            return Ok(Some(Path::new(s).canonicalize()?));
+        if let Some(p) = self.settings.path.as_ref() {
+            return Ok(Some(p.canonicalize()?));
         }
 
         Ok(None)
     }
+
+    /// Find `nedots.json`.
+    pub(crate) fn config_path(&self) -> Result<PathBuf, ConfigError> {
+        self.settings.discover()
+    }
+
+    /// Remote to use.
+    pub(crate) fn remote(&self) -> &str {
+        self.settings.remote.as_deref().unwrap_or("origin")
+    }
+
+    /// Branch to use, `None` for the default in .gitconfig.
+    pub(crate) fn branch(&self) -> Option<String> {
+        self.settings.branch.clone()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ArgEnum)]
+/// How results are printed.
+pub(crate) enum OutputFormat {
+    Human,
+    Json,
+}
+
+#[derive(Debug, clap::Args)]
+/// Narrows the tracked files a command copies.
+pub(crate) struct FilterArgs {
+    #[clap(short, long)]
+    /// Only these files, by glob or path prefix relative to $HOME, e.g.
+    /// `.config/polybar` or `**/*.fish`. May be repeated.
+    only: Vec<String>,
+
+    #[clap(short, long)]
+    /// Leave out these files, by glob or path prefix. May be repeated.
+    except: Vec<String>,
+}
+
+impl FilterArgs {
+    /// Build the `Filter` these args describe.
+    fn filter(&self) -> Result<Filter, FilterError> {
+        Filter::new(&self.only, &self.except)
+    }
+}
+
+#[derive(Debug, Subcommand)]
+/// A `Command` to run, or to plan.
+pub(crate) enum Invocation {
+    /// Work out everything another command would copy, commit, push &
+    /// install, and write it to a plan file for `apply`, without changing
+    /// anything.
+    Plan {
+        #[clap(short, long, default_value = "plan.json")]
+        /// Write the plan here.
+        output: String,
+
+        #[clap(subcommand)]
+        /// Command to plan.
+        cmd: Command,
+    },
+
+    #[clap(flatten)]
+    Run(Command),
 }
 
 #[derive(Debug, Subcommand)]
 pub(crate) enum Command {
+    /// Create a new repository, or adopt an existing one, pick dotfiles in
+    /// $HOME to track and write a `nedots.json` for them.
+    Init {
+        #[clap(default_value = ".nedots")]
+        /// Path of the repository, relative paths are relative to $HOME.
+        path: String,
+
+        #[clap(short, long)]
+        /// Write `nedots.json` here instead of the root of the repository.
+        config: Option<String>,
+
+        #[clap(short, long)]
+        /// Track every candidate without prompting.
+        all: bool,
+
+        #[clap(long)]
+        /// Overwrite an existing `nedots.json`.
+        force: bool,
+    },
+
     /// Add changes to remote by commiting & pushing local changes to git
     /// repository. Conflicts are reported on, and it's expected that
     /// you handle them manually.
@@ -84,13 +255,40 @@ pub(crate) enum Command {
         push: bool,
 
         #[clap(short, long)]
-        #[clap(default_value_t = String::from("origin"))]
         /// Push to this remote instead of origin.
-        remote: String,
+        remote: Option<String>,
 
         #[clap(short, long)]
         /// Use this branch instead of default in .gitconfig.
         branch: Option<String>,
+
+        #[clap(flatten)]
+        filter: FilterArgs,
+    },
+
+    /// Watch tracked files in $HOME & commit changes to them as they happen,
+    /// once writes have settled. Runs until it receives SIGTERM or SIGINT, so
+    /// it can be run as a systemd user service, see `nedots-watch.service`.
+    Watch {
+        #[clap(short, long)]
+        /// Push changes to remote after each commit.
+        push: bool,
+
+        #[clap(short, long)]
+        /// Push to this remote instead of origin.
+        remote: Option<String>,
+
+        #[clap(short, long)]
+        /// Use this branch instead of default in .gitconfig.
+        branch: Option<String>,
+
+        #[clap(long, default_value_t = 2000)]
+        /// Milliseconds to wait after the last change before committing.
+        debounce: u64,
+
+        #[clap(long)]
+        /// Append to this file instead of writing to the terminal.
+        log_file: Option<String>,
     },
 
     /// Update config files by pulling changes from remote & applying
@@ -107,35 +305,286 @@ pub(crate) enum Command {
     /// --force/-f.
     UpdateLocal {
         #[clap(short, long)]
-        #[clap(default_value_t = String::from("origin"))]
         /// Pull from this remote instead of origin.
-        remote: String,
+        remote: Option<String>,
 
         #[clap(short, long)]
-        /// Use this branch instead of default in .gitconfig.
+        /// Pull this branch, which has to be the one checked out, since only
+        /// it is fast-forwarded.
         branch: Option<String>,
 
-        #[clap(short, long)]
-        /// Only update the folders specified.
-        only: Option<Vec<String>>,
+        #[clap(flatten)]
+        filter: FilterArgs,
 
         #[clap(long)]
         /// Overwrite local files.
         force: bool,
+
+        #[clap(short, long)]
+        /// Choose which changed files to update, previewing diffs first if
+        /// you like. Files you skip aren't offered again until they change in
+        /// the repository.
+        interactive: bool,
     },
 
     /// Installs packages from distributions' package manager, Flatpak, and
     /// performs other misc. install operations for supported distributions.
-    /// Fedora will configure & install rpmfusion related repositories.
+    /// Packages are installed by group, e.g. `core` or `dev`, after the groups
+    /// each needs. Packages listed for the distribution's `ID` in
+    /// /etc/os-release are installed, else those for its `ID_LIKE`. Fedora
+    /// will configure & install rpmfusion related repositories.
     InstallPackages {
         #[clap(short = 'y', long = "assumeyes")]
-        /// Translates to `sudo dnf install -y`.
+        /// Translates to `sudo dnf install -y`, or the equivalent for zypper,
+        /// pacman or apt-get.
         assume_yes: bool,
+
+        #[clap(short, long)]
+        /// Install this group of packages, after the groups it needs. May be
+        /// repeated. Defaults to `packages.default`, or every group.
+        group: Vec<String>,
+    },
+
+    /// Set up a fresh machine: clone the repository, copy every tracked file
+    /// into place & install packages.
+    Bootstrap {
+        /// Repository to clone, a URL, local path or file:// URL.
+        url: String,
+
+        #[clap(default_value = ".nedots")]
+        /// Clone into this path, relative paths are relative to $HOME.
+        path: String,
+
+        #[clap(short = 'y', long = "assumeyes")]
+        /// Translates to `sudo dnf install -y`, or the equivalent for zypper,
+        /// pacman or apt-get.
+        assume_yes: bool,
+
+        #[clap(long)]
+        /// Don't install packages.
+        skip_packages: bool,
+    },
+
+    /// Translate nedots.json into an Ansible playbook or a Nix home-manager
+    /// module, so other tools can provision from the same files & packages.
+    Convert {
+        #[clap(long, arg_enum)]
+        /// Format to convert to.
+        to: Target,
+
+        #[clap(short, long)]
+        /// Write here instead of printing.
+        output: Option<String>,
+    },
+
+    /// Write every tracked file in the repository, with its mode, and the
+    /// config to a single tar.zst archive, for machines without access to the
+    /// repository.
+    Export {
+        #[clap(default_value = "nedots.tar.zst")]
+        /// Write the archive here.
+        output: String,
+    },
+
+    /// Copy every file in an archive written by `export` into place, backing
+    /// up whatever it overwrites.
+    Import {
+        /// Archive to import.
+        archive: String,
+    },
+
+    /// Restore every file a run of nedots overwrote, and remove those it
+    /// created. Runs are listed by `backups list`.
+    Rollback {
+        /// Run to roll back, defaults to the latest.
+        run_id: Option<String>,
+    },
+
+    /// Manage backups of files nedots has overwritten, kept in
+    /// $XDG_STATE_HOME/nedots/backups.
+    Backups {
+        #[clap(subcommand)]
+        cmd: BackupsCommand,
+    },
+
+    /// Manage the config file itself.
+    Config {
+        #[clap(subcommand)]
+        cmd: ConfigCommand,
+    },
+
+    /// Start tracking paths, adding them to `nedots.json` & copying them into
+    /// the repository. Paths under $HOME are added to `user`, anything else
+    /// to `root`.
+    Track {
+        #[clap(required = true)]
+        /// Paths to track.
+        paths: Vec<String>,
+    },
+
+    /// Import files managed by GNU Stow, yadm or chezmoi: copy them into the
+    /// repository & track them. Templates, scripts & encrypted files are
+    /// skipped.
+    ImportFrom {
+        #[clap(arg_enum)]
+        /// Tool the files are managed by.
+        source: Source,
+
+        /// Stow directory, yadm repository or chezmoi source directory.
+        /// Defaults to where yadm & chezmoi keep theirs.
+        path: Option<String>,
+    },
+
+    /// Stop tracking paths, removing them from `nedots.json`.
+    Untrack {
+        #[clap(required = true)]
+        /// Paths to untrack.
+        paths: Vec<String>,
+
+        #[clap(long)]
+        /// Also remove the copy in the repository.
+        remove: bool,
+    },
+
+    /// Check that nedots.json, the repository, git & the tools nedots relies
+    /// on are all set up, with hints on fixing anything that isn't.
+    Doctor {
+        #[clap(short, long)]
+        /// Expect this remote instead of origin.
+        remote: Option<String>,
+    },
+
+    /// Take exactly the steps in a plan file written by `plan`. Refuses if
+    /// any file to be copied, or branch to be moved, changed since planning.
+    Apply {
+        /// Plan file to apply.
+        plan: String,
+    },
+
+    /// Report how each tracked file compares to its copy in the repository,
+    /// and how the repository compares to the remote.
+    Status {
+        #[clap(short, long)]
+        /// Fetch from remote before comparing.
+        fetch: bool,
+
+        #[clap(short, long)]
+        /// Compare with this remote instead of origin.
+        remote: Option<String>,
+
+        #[clap(short, long)]
+        /// Use this branch instead of default in .gitconfig.
+        branch: Option<String>,
+    },
+
+    /// Show the commits that changed a tracked path, newest first, with a
+    /// summary of what each changed.
+    Log {
+        /// Live path, e.g. ~/.config/polybar/config.ini.
+        path: String,
+
+        #[clap(short, long)]
+        /// Show the change each commit made.
+        patch: bool,
+    },
+
+    /// Write an older version of a tracked file from the repository to its
+    /// live location, backing up what's there first.
+    Restore {
+        /// Live path, e.g. ~/.config/polybar/config.ini.
+        path: String,
+
+        #[clap(short, long, default_value = "HEAD")]
+        /// Revision to restore from, e.g. HEAD~2 or a commit hash.
+        rev: String,
+
+        #[clap(long, conflicts_with = "rev")]
+        /// Restore the version from this many changes to the file ago.
+        back: Option<usize>,
+    },
+
+    /// Open a tracked file in $VISUAL or $EDITOR, then show what changed &
+    /// offer to commit just that file.
+    Edit {
+        /// Live path, e.g. ~/.config/sxhkd/sxhkdrc.
+        path: String,
+
+        #[clap(short, long)]
+        /// Push changes to remote after committing.
+        push: bool,
+
+        #[clap(short, long)]
+        /// Push to this remote instead of origin.
+        remote: Option<String>,
+
+        #[clap(short = 'y', long = "assumeyes")]
+        /// Commit without asking.
+        assume_yes: bool,
+    },
+
+    /// Show differences between tracked files & their copies in the
+    /// repository, as a unified diff from the repository to the live file.
+    Diff {
+        /// Only show differences for these paths.
+        paths: Vec<String>,
+
+        #[clap(long, conflicts_with = "name-only")]
+        /// Show a summary of lines inserted & deleted per file.
+        stat: bool,
+
+        #[clap(long)]
+        /// Only show the names of files that differ.
+        name_only: bool,
+
+        #[clap(short = 'R', long)]
+        /// Diff from the live file to the repository instead, showing what
+        /// update-local would write.
+        reverse: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub(crate) enum BackupsCommand {
+    /// List backup runs, oldest first.
+    List,
+
+    /// Remove all but the latest backup runs.
+    Prune {
+        #[clap(short, long, default_value_t = 10)]
+        /// Number of runs to keep.
+        keep: usize,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub(crate) enum ConfigCommand {
+    /// Rewrite the config in another format, e.g. as `nedots.toml` so it can
+    /// be commented, replacing the original.
+    Convert {
+        #[clap(long, arg_enum)]
+        /// Format to convert to.
+        to: Format,
+
+        #[clap(short, long)]
+        /// Write here instead of next to the original.
+        output: Option<String>,
+
+        #[clap(long)]
+        /// Keep the original.
+        keep: bool,
+
+        #[clap(long)]
+        /// Overwrite an existing config in the new format.
+        force: bool,
     },
 }
 
 impl Operate for AddChanges<'_> {
-    fn operate(&self) -> Result<usize, OperationError> {
+    fn operate(&mut self) -> Result<usize, OperationError> {
+        if self.parent_op.is_dry_run() {
+            return self.plan();
+        }
+
         let bar =
             ProgressBar::new(self.copy_ops.len().try_into().unwrap()).with_prefix("Copying...");
         for op in &self.copy_ops {
@@ -144,33 +593,175 @@ impl Operate for AddChanges<'_> {
                 op.from.as_ref().unwrap().display(),
                 op.to.as_ref().unwrap().display()
             ));
+            for step in op.plan()? {
+                if let Step::Copy { from, .. } = step {
+                    self.parent_op.report().path(&from, "copied");
+                }
+            }
             op.copy()?;
             bar.inc(1);
         }
+        bar.finish_and_clear();
+        self.parent_op.insert_result("copy", Ok(()));
 
+        let go = self.git_op.take().ok_or(GitError::NoRepo)?;
+        let (mut go, tree_id) = match self.filtered {
+            true => {
+                let paths = self.destinations(go.path()?)?;
+                go.add_these(&paths)?
+            }
+            false => go.add_changes()?,
+        };
+        self.parent_op.insert_result("git_add", Ok(()));
+        if !go.has_changes(tree_id)? {
+            crate::output::term("Nothing to commit.");
+            self.git_op = Some(go);
+            return Ok(0);
+        }
+
+        let (mut go, oid) = go.commit(tree_id)?;
+        self.parent_op.insert_result("git_commit", Ok(()));
+        self.parent_op.report().commits.push(oid.to_string());
+        self.parent_op.log(&format!("Committed {}", oid)).ok();
+
+        if self.push {
+            let remote = go.remote().to_string();
+            let branch = match &self.branch {
+                Some(b) => b.to_string(),
+                None => go.branch()?,
+            };
+            go.push(&remote, &branch)?;
+            self.parent_op.insert_result("git_push", Ok(()));
+            self.parent_op
+                .log(&format!("Pushed to {}/{}", remote, branch))
+                .ok();
+        }
+
+        self.git_op = Some(go);
         Ok(0)
     }
 
-    fn exit_code(&self) -> usize {
-        if let Err(e) = self.operate() {
-            match e {
-                OperationError::Git(g) => match g {
-                    crate::ops::git::GitError::NoPath => return 1,
-                    crate::ops::git::GitError::NoRepo => return 1,
-                    crate::ops::git::GitError::PushRejected => return 1,
-                    crate::ops::git::GitError::Git2(_) => return 1,
-                    crate::ops::git::GitError::Unknown => return 1,
-                },
-                OperationError::Copy(c) => match c {
-                    crate::ops::fs::CopyError::NoFromPath => return 1,
-                    crate::ops::fs::CopyError::NoToPath => return 1,
-                    crate::ops::fs::CopyError::InvalidFileName { path: _ } => return 1,
-                    crate::ops::fs::CopyError::IoError(_) => return 1,
-                },
+    fn exit_code(&mut self) -> usize {
+        let result = self.operate();
+        self.parent_op.finish(result)
+    }
+}
+
+impl Operate for UpdateLocal<'_> {
+    fn operate(&mut self) -> Result<usize, OperationError> {
+        if self.parent_op.is_dry_run() {
+            return self.plan();
+        }
+
+        let go = self.git_op.as_mut().ok_or(GitError::NoRepo)?;
+        let local = update_branch(self.branch.as_ref(), go.branch()?)?;
+        let remote = go.remote().to_string();
+
+        let repo = go.path()?.to_path_buf();
+        let head = go.head()?;
+        let oid = go.fetch(&local, &remote)?;
+        self.parent_op.insert_result("git_fetch", Ok(()));
+        let changed: Vec<PathBuf> = go
+            .changed(head, oid)?
+            .iter()
+            .map(|p| repo.join(p))
+            .collect();
+
+        // Anything that differs from the repository before we fast-forward is
+        // a local change that would be lost. When choosing files they're
+        // offered unselected instead.
+        let mut local_changes = Vec::new();
+        if !self.force {
+            for op in &self.copy_ops {
+                local_changes.append(&mut op.would_overwrite()?);
             }
+
+            if !self.interactive && !local_changes.is_empty() {
+                return Err(OperationError::LocalChanges {
+                    paths: local_changes,
+                });
+            }
+        }
+
+        if go.fast_forward(&local, oid)? {
+            self.parent_op
+                .log(&format!("Fast-forwarded {} to {}", local, oid))
+                .ok();
+        } else {
+            crate::output::term("Already up to date.");
+        }
+        self.parent_op.insert_result("git_fast_forward", Ok(()));
+
+        if self.interactive {
+            self.copy_ops = self.choose(&changed, &local_changes)?;
         }
 
-        0
+        // Tracked, but not in the repository even after the fast-forward.
+        let parent_op = &self.parent_op;
+        self.copy_ops.retain(|op| {
+            let from = op.from.as_ref().unwrap();
+            if !from.exists() {
+                parent_op
+                    .log(&format!(
+                        "{} isn't in the repository, skipping",
+                        op.to.as_ref().unwrap().display()
+                    ))
+                    .ok();
+            }
+            from.exists()
+        });
+
+        let bar =
+            ProgressBar::new(self.copy_ops.len().try_into().unwrap()).with_prefix("Copying...");
+        for op in &self.copy_ops {
+            bar.println(format!(
+                "{} -> {}",
+                op.from.as_ref().unwrap().display(),
+                op.to.as_ref().unwrap().display()
+            ));
+            for step in op.plan()? {
+                if let Step::Copy { to, .. } = step {
+                    self.parent_op.report().path(&to, "updated");
+                }
+            }
+            op.copy()?;
+            bar.inc(1);
+        }
+        bar.finish_and_clear();
+        self.parent_op.insert_result("copy", Ok(()));
+        backup::report();
+
+        Ok(0)
+    }
+
+    fn exit_code(&mut self) -> usize {
+        let result = self.operate();
+        self.parent_op.finish(result)
+    }
+}
+
+impl Operate for InstallPackages {
+    fn operate(&mut self) -> Result<usize, OperationError> {
+        if self.parent_op.is_dry_run() {
+            return self.plan();
+        }
+
+        for op in &self.install_ops {
+            let result = op.install();
+            self.parent_op
+                .report()
+                .packages
+                .push(op.report(result.is_ok()));
+            result?;
+        }
+        self.parent_op.insert_result("install", Ok(()));
+
+        Ok(0)
+    }
+
+    fn exit_code(&mut self) -> usize {
+        let result = self.operate();
+        self.parent_op.finish(result)
     }
 }
 
@@ -180,30 +771,296 @@ fn exit(msg: &str, code: usize) -> ! {
     std::process::exit(code.try_into().unwrap())
 }
 
+impl Command {
+    /// `--remote` & `--branch`, for commands that have them.
+    fn git_flags(&self) -> (Option<String>, Option<String>) {
+        match self {
+            Command::AddChanges { remote, branch, .. }
+            | Command::Watch { remote, branch, .. }
+            | Command::UpdateLocal { remote, branch, .. }
+            | Command::Status { remote, branch, .. } => (remote.clone(), branch.clone()),
+            Command::Doctor { remote } | Command::Edit { remote, .. } => (remote.clone(), None),
+            _ => (None, None),
+        }
+    }
+}
+
+/// Construct the operation requested by `cmd`. Operations that create,
+/// check or edit `nedots.json` are built before a `Config` is loaded, since
+/// one might not exist or resolve yet.
+fn build<'a>(
+    args: &'a Args,
+    cmd: &'a Command,
+    op: Operation<TerminalLogger>,
+) -> Result<Box<dyn Operate + 'a>, OperationError> {
+    match cmd {
+        Command::Apply { plan } => return Ok(Box::new(Apply::new(op).from(Path::new(plan))?)),
+        Command::Init {
+            path,
+            config,
+            all,
+            force,
+        } => {
+            return Ok(Box::new(
+                Init::new(op)
+                    .at(Path::new(path))
+                    .config_to(config.as_ref().map(PathBuf::from))
+                    .all(*all)
+                    .force(*force),
+            ))
+        }
+        Command::Bootstrap {
+            url,
+            path,
+            assume_yes,
+            skip_packages,
+        } => {
+            return Ok(Box::new(
+                Bootstrap::new(op)
+                    .from(url)
+                    .to(Path::new(path))
+                    .assume_yes(*assume_yes)
+                    .skip_packages(*skip_packages)
+                    .home(home::dir()),
+            ))
+        }
+        Command::Import { archive } => return Ok(Box::new(Import::new(op).from(archive)?)),
+        Command::Rollback { run_id } => return Ok(Box::new(Rollback::new(op).run(run_id.clone()))),
+        Command::Backups { cmd } => {
+            return Ok(Box::new(Backups::new(op).prune(match cmd {
+                BackupsCommand::List => None,
+                BackupsCommand::Prune { keep } => Some(*keep),
+            })))
+        }
+        Command::Doctor { .. } => {
+            return Ok(Box::new(
+                Doctor::new(op)
+                    .config_at(
+                        &args
+                            .config_path()
+                            .unwrap_or_else(|_| PathBuf::from(CONFIG_FILE)),
+                    )
+                    .of(args.path().map_err(ConfigError::from)?)
+                    .with_remote(args.remote()),
+            ))
+        }
+        Command::Config {
+            cmd:
+                ConfigCommand::Convert {
+                    to,
+                    output,
+                    keep,
+                    force,
+                },
+        } => {
+            return Ok(Box::new(
+                Reformat::new(op)
+                    .config_at(&args.config_path()?)
+                    .to(*to)
+                    .write_to(output.as_ref().map(PathBuf::from))
+                    .keep(*keep)
+                    .force(*force),
+            ))
+        }
+        // Tracked paths don't need to exist to be untracked, so only `path`
+        // is resolved.
+        Command::Track { paths } | Command::Untrack { paths, .. } => {
+            let config_path = &args.config_path()?;
+            let path = match args.path().map_err(ConfigError::from)? {
+                Some(p) => p,
+                None => Config::read(Some(config_path))?.resolve_path()?.path,
+            };
+
+            return Ok(Box::new(
+                Track::new(op)
+                    .of(path)
+                    .config_at(config_path)
+                    .these(paths)?
+                    .untrack(matches!(cmd, Command::Untrack { .. }))
+                    .remove(matches!(cmd, Command::Untrack { remove: true, .. })),
+            ));
+        }
+        Command::ImportFrom { source, path: from } => {
+            let config_path = &args.config_path()?;
+            let path = match args.path().map_err(ConfigError::from)? {
+                Some(p) => p,
+                None => Config::read(Some(config_path))?.resolve_path()?.path,
+            };
+
+            return Ok(Box::new(
+                ImportFrom::new(op)
+                    .of(path)
+                    .config_at(config_path)
+                    .from(*source, from.as_deref())?,
+            ));
+        }
+        _ => {}
+    }
+
+    let config = Config::new(&args.config_path()?)?;
+    op.log(&format!("Settings: {:#?}", config)).ok();
+
+    let path = args
+        .path()
+        .map_err(ConfigError::from)?
+        .unwrap_or_else(|| config.path.clone());
+
+    Ok(match cmd {
+        Command::AddChanges { push, filter, .. } => Box::new(
+            AddChanges::new(op)
+                .to(path)?
+                .with_remote(args.remote())?
+                .on_branch(args.branch())
+                .push(*push)
+                .copy_these(config.user.clone())?
+                .copy_these(config.root.clone())?
+                .filter(&filter.filter()?)?,
+        ),
+        Command::Watch {
+            push,
+            debounce,
+            log_file,
+            ..
+        } => Box::new(
+            Watch::new(op)
+                .of(path)
+                .with_remote(args.remote())
+                .on_branch(args.branch())
+                .push(*push)
+                .watch_these(config.user.clone())
+                .debounce(Duration::from_millis(*debounce))
+                .log_to(log_file.as_ref().map(PathBuf::from)),
+        ),
+        Command::UpdateLocal {
+            force,
+            interactive,
+            filter,
+            ..
+        } => Box::new(
+            UpdateLocal::new(op)
+                .from(path)?
+                .with_remote(args.remote())
+                .on_branch(args.branch())
+                .force(*force)
+                .interactive(*interactive)
+                .copy_these(config.user.clone())?
+                .copy_these_as_root(config.root.clone())?
+                .filter(&filter.filter()?)?,
+        ),
+        Command::InstallPackages { assume_yes, group } => {
+            Box::new(InstallPackages::new(op).install_these(&config.pkgs, group, *assume_yes)?)
+        }
+        Command::Status { fetch, .. } => Box::new(
+            Status::new(op)
+                .of(path)?
+                .with_remote(args.remote())
+                .on_branch(args.branch())
+                .fetch(*fetch)
+                .compare_these(config.user.clone())
+                .compare_these(config.root.clone()),
+        ),
+        Command::Diff {
+            paths,
+            stat,
+            name_only,
+            reverse,
+        } => Box::new(
+            Diff::new(op)
+                .of(path)
+                .compare_these(config.user.clone())
+                .compare_these(config.root.clone())
+                .only(paths)?
+                .mode(match (stat, name_only) {
+                    (true, _) => DiffMode::Stat,
+                    (_, true) => DiffMode::NameOnly,
+                    _ => DiffMode::Patch,
+                })
+                .reverse(*reverse),
+        ),
+        Command::Log { path: live, patch } => {
+            Box::new(Log::new(op).of(path).at(live)?.patch(*patch))
+        }
+        Command::Restore {
+            path: live,
+            rev,
+            back,
+        } => Box::new(Restore::new(op).of(path).at(live)?.at_rev(rev).back(*back)),
+        Command::Edit {
+            path: live,
+            push,
+            assume_yes,
+            ..
+        } => Box::new(
+            Edit::new(op)
+                .of(path)
+                .tracking(config.user.clone())
+                .tracking(config.root.clone())
+                .at(live)?
+                .with_remote(args.remote())
+                .push(*push)
+                .assume_yes(*assume_yes),
+        ),
+        Command::Convert { to, output } => Box::new(
+            Convert::new(op)
+                .with_config(config.clone())
+                .to(*to)
+                .write_to(output.as_ref().map(PathBuf::from)),
+        ),
+        Command::Export { output } => Box::new(
+            Export::new(op)
+                .of(path)
+                .with_config(config.clone())
+                .to(output)?,
+        ),
+        _ => unreachable!(),
+    })
+}
+
 /// Parse args & run operations.
 pub(super) fn run() -> Result<(), std::io::Error> {
-    let args = Args::parse();
+    let mut args = Args::parse();
+    let json = args.output == OutputFormat::Json;
+    crate::output::machine_readable(json);
+
+    if let Err(e) = args.settle() {
+        let e = OperationError::from(e);
+        if json {
+            Report::failed(&e).print()?;
+        }
+        exit(&e.to_string(), 1)
+    }
     let logger = TerminalLogger::new().with_verbosity(args.verbosity());
 
     logger.log(&format!("Args: {:#?}", args))?;
     logger.log(&format!("Verbosity: {:#?}", logger.verbosity()))?;
 
-    let config = match Config::new() {
-        Ok(s) => {
-            logger.log(format!("Settings: {:#?}", s).as_str())?;
-            s
+    let op = Operation::new()
+        .with_logging(logger)
+        .dry_run(args.dry_run)
+        .json(json);
+    let built = match &args.cmd {
+        Invocation::Plan { output, cmd } => build(&args, cmd, op.plan_to(PathBuf::from(output))),
+        Invocation::Run(cmd) => build(&args, cmd, op),
+    };
+    let code = match built {
+        Ok(mut o) => o.exit_code(),
+        Err(e) => {
+            if json {
+                Report::failed(&e).print()?;
+            }
+            exit(&e.to_string(), 1)
         }
-        Err(e) => exit(format!("{}", e).as_str(), 1),
     };
 
-    todo!()
+    std::process::exit(code.try_into().unwrap())
 }
 
 #[cfg(test)]
 mod tests {
     use super::Args;
-    use crate::config::Config;
+    use crate::config::{Config, CONFIG_FILE};
     use clap::IntoApp;
+    use std::path::Path;
 
     #[test]
     fn verify() {
@@ -212,8 +1069,8 @@ mod tests {
 
     #[test]
     fn read_settings() {
-        if let Err(e) = Config::new() {
-            assert!(false, "{}", e);
+        if let Err(e) = Config::new(Path::new(CONFIG_FILE)) {
+            panic!("{}", e);
         }
     }
 }
@@ -1,17 +1,83 @@
+use clap::ArgEnum;
+use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
 use std::{
-    fs::File,
     path::{Path, PathBuf},
+    sync::OnceLock,
 };
 use thiserror::Error;
+use toml_edit::{Array, Item, TableLike, Value};
+
+/// Name of the file `Config` is read from, unless it's written in another
+/// `Format`.
+pub(crate) const CONFIG_FILE: &str = "nedots.json";
+
+/// Names `Config` is looked for by in a directory, in order.
+pub(crate) const CONFIG_FILES: [&str; 4] =
+    ["nedots.json", "nedots.toml", "nedots.yaml", "nedots.yml"];
+
+/// Format of config files without a known extension, set by `--config-format`.
+static FORMAT: OnceLock<Format> = OnceLock::new();
+
+/// The first of `CONFIG_FILES` in `dir`.
+pub(crate) fn find_in(dir: &Path) -> Option<PathBuf> {
+    CONFIG_FILES
+        .iter()
+        .map(|name| dir.join(name))
+        .find(|p| p.exists())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ArgEnum)]
+/// Formats `Config` can be read from & written to.
+pub(crate) enum Format {
+    Json,
+    Toml,
+    Yaml,
+}
+
+impl Format {
+    /// Read config files without a known extension as `format`, for the rest
+    /// of this run.
+    pub(crate) fn assume(format: Format) {
+        FORMAT.set(format).ok();
+    }
+
+    /// Format of `path`, by its extension, else whatever was assumed, else
+    /// JSON.
+    pub(crate) fn of(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("json") => Self::Json,
+            Some("toml") => Self::Toml,
+            Some("yaml" | "yml") => Self::Yaml,
+            _ => FORMAT.get().copied().unwrap_or(Self::Json),
+        }
+    }
+
+    /// Extension of files in this format.
+    pub(crate) fn extension(&self) -> &'static str {
+        match self {
+            Self::Json => "json",
+            Self::Toml => "toml",
+            Self::Yaml => "yaml",
+        }
+    }
+}
 
 #[derive(Debug, Error)]
 /// Errors thrown during `Config` creation, involving fs operations,
 /// serialization and path resolution.
 pub(crate) enum ConfigError {
-    #[error("Failed to deserialize `nedots.json`, is it badly formatted?")]
+    #[error("Failed to deserialize {path:?}, is it badly formatted? {msg}")]
     /// Serde error.
-    DeserializeError,
+    DeserializeError { path: PathBuf, msg: String },
+
+    #[error("Failed to deserialize {path:?}, is it badly formatted?")]
+    /// Serde error, reading the user settings file.
+    BadSettings { path: PathBuf },
+
+    #[error("Failed to serialize config: {msg}")]
+    /// Serde error.
+    SerializeError { msg: String },
 
     #[error("Could not resolve path: {path:?}")]
     /// Failed to resolve `nedots` path in settings, which is required for
@@ -22,6 +88,34 @@ pub(crate) enum ConfigError {
     /// Failed to resolve paths in settings, required for managing dotfiles.
     BadPaths { paths: Vec<PathBuf> },
 
+    #[error("{path:?} already exists, use --force to overwrite.")]
+    /// Refused to overwrite an existing `nedots.json`.
+    Exists { path: PathBuf },
+
+    #[error("No `nedots.json`, `.toml` or `.yaml` found, pass --config or run `nedots init`. Searched: {searched:#?}")]
+    /// No config was anywhere it's searched for.
+    NotFound { searched: Vec<PathBuf> },
+
+    #[error("No user named `{name}`.")]
+    /// `--user` named a user that doesn't exist.
+    NoSuchUser { name: String },
+
+    #[error("No package group named `{name}`.")]
+    /// Asked for, or needed by, a group that isn't in `packages`.
+    NoSuchGroup { name: String },
+
+    #[error("Package groups need each other: {}", groups.join(" -> "))]
+    /// Groups' `needs` form a cycle.
+    GroupCycle { groups: Vec<String> },
+
+    #[error("{path:?} is not tracked, start tracking it with `nedots track`.")]
+    /// Asked to untrack a path that isn't in `root` or `user`.
+    NotTracked { path: PathBuf },
+
+    #[error("{path:?} has comments that rewriting it would lose, make this change by hand.")]
+    /// Refused to rewrite a config whose comments can't be kept.
+    Commented { path: PathBuf },
+
     #[error(transparent)]
     /// A wrapper around IO errors.
     IoError(#[from] std::io::Error),
@@ -44,23 +138,143 @@ pub(crate) struct Config {
 }
 
 impl Config {
-    /// Create a new `Config` and resolve paths.
-    pub(crate) fn new() -> Result<Self, ConfigError> {
-        Self::read(None)?
+    /// Read the `Config` at `path` and resolve paths.
+    pub(crate) fn new(path: &Path) -> Result<Self, ConfigError> {
+        Self::read(Some(path))?
             .resolve_path()?
             .resolve_root_paths()?
             .resolve_user_paths()
     }
 
-    /// Read & deserialize `nedots.json`.
+    /// Read & deserialize the config at `path`, `nedots.json` by default, in
+    /// the `Format` of `path`.
     ///
     /// ### Errors
     /// Returns `std::io::Error` when the file does not exist, or
-    /// `SettingsError::DeserializeError` if `serde` fails to deserialize.
+    /// `ConfigError::DeserializeError` if `serde` fails to deserialize.
     pub(crate) fn read(path: Option<&Path>) -> Result<Self, ConfigError> {
-        let path = path.or(Some(Path::new("nedots.json"))).unwrap();
-        serde_json::from_reader::<File, Config>(File::open(path)?)
-            .or(Err(ConfigError::DeserializeError))
+        let path = path.unwrap_or(Path::new(CONFIG_FILE));
+        let content = std::fs::read_to_string(path)?;
+        let bad = |msg: String| ConfigError::DeserializeError {
+            path: path.to_path_buf(),
+            msg,
+        };
+
+        match Format::of(path) {
+            Format::Json => serde_json::from_str(&content).map_err(|e| bad(e.to_string())),
+            Format::Toml => toml::from_str(&content).map_err(|e| bad(e.to_string())),
+            Format::Yaml => serde_yaml::from_str(&content).map_err(|e| bad(e.to_string())),
+        }
+    }
+
+    /// Serialize & write the config to `path`, in the `Format` of `path`.
+    /// JSON is indented the same way as `nedots.example.json`, see `render`
+    /// for what happens to a file that's already there.
+    ///
+    /// ### Errors
+    /// Returns `std::io::Error` when the file can't be written, or any error
+    /// `render` returns.
+    pub(crate) fn write(&self, path: &Path) -> Result<(), ConfigError> {
+        Ok(std::fs::write(path, self.render(path, Format::of(path))?)?)
+    }
+
+    /// The config in `format`, to replace whatever's at `path`. TOML there is
+    /// edited in place so its comments & the order of its entries survive,
+    /// YAML is only rewritten if it has no comments to lose.
+    ///
+    /// ### Errors
+    /// Returns `ConfigError::Commented` if the comments at `path` can't be
+    /// kept, or `ConfigError::SerializeError` if `serde` fails to serialize.
+    pub(crate) fn render(&self, path: &Path, format: Format) -> Result<String, ConfigError> {
+        let content = self.to_string_as(format)?;
+        let old = match std::fs::read_to_string(path) {
+            Ok(old) => old,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(content),
+            Err(e) => return Err(e.into()),
+        };
+        let commented = || ConfigError::Commented {
+            path: path.to_path_buf(),
+        };
+
+        match format {
+            Format::Json => Ok(content),
+            Format::Toml => match edit_toml(&old, &content) {
+                Some(edited) if self.same_as(toml::from_str(&edited).ok()) => Ok(edited),
+                _ if has_comments(&old) => Err(commented()),
+                _ => Ok(content),
+            },
+            Format::Yaml if has_comments(&old) => Err(commented()),
+            Format::Yaml => Ok(content),
+        }
+    }
+
+    /// Whether `other` holds the same config as this.
+    fn same_as(&self, other: Option<Config>) -> bool {
+        let json = |c: &Config| c.to_string_as(Format::Json).ok();
+        other.is_some_and(|other| json(&other) == json(self))
+    }
+
+    /// Serialize the config in `format`.
+    ///
+    /// ### Errors
+    /// Returns `ConfigError::SerializeError` if `serde` fails to serialize.
+    pub(crate) fn to_string_as(&self, format: Format) -> Result<String, ConfigError> {
+        let bad = |msg: String| ConfigError::SerializeError { msg };
+        match format {
+            Format::Json => {
+                let mut buf = Vec::new();
+                let fmt = serde_json::ser::PrettyFormatter::with_indent(b"    ");
+                self.serialize(&mut serde_json::Serializer::with_formatter(&mut buf, fmt))
+                    .map_err(|e| bad(e.to_string()))?;
+                buf.push(b'\n');
+                String::from_utf8(buf).map_err(|e| bad(e.to_string()))
+            }
+            // Through `Value`, which puts values before tables as TOML needs,
+            // e.g. when `flatpak` is empty & written as `flatpak = []`.
+            Format::Toml => toml::Value::try_from(self)
+                .and_then(|v| toml::to_string_pretty(&v))
+                .map_err(|e| bad(e.to_string())),
+            Format::Yaml => serde_yaml::to_string(self).map_err(|e| bad(e.to_string())),
+        }
+    }
+
+    /// The entry `path` would have in `user` or `root`. Paths under `home`
+    /// belong in `user` and are relative to `home`, anything else belongs in
+    /// `root`.
+    fn entry<'a>(&'a mut self, home: &Path, path: &Path) -> (&'a mut Vec<PathBuf>, PathBuf) {
+        match path.strip_prefix(home) {
+            Ok(rel) => (&mut self.user, rel.to_path_buf()),
+            Err(_) => (&mut self.root, path.to_path_buf()),
+        }
+    }
+
+    /// Append `path` to `user` or `root`, leaving existing entries in place.
+    /// Returns `false` if `path` is already tracked.
+    pub(crate) fn track(&mut self, home: &Path, path: &Path) -> bool {
+        let (paths, entry) = self.entry(home, path);
+        if paths.contains(&entry) {
+            return false;
+        }
+
+        paths.push(entry);
+        true
+    }
+
+    /// Remove `path` from `user` or `root`.
+    ///
+    /// ### Errors
+    /// Returns `ConfigError::NotTracked` if `path` isn't tracked.
+    pub(crate) fn untrack(&mut self, home: &Path, path: &Path) -> Result<(), ConfigError> {
+        let (paths, entry) = self.entry(home, path);
+        match paths.iter().position(|p| p == &entry) {
+            Some(i) => {
+                paths.remove(i);
+                Ok(())
+            }
+            None => Err(ConfigError::NotTracked {
+                path: path.to_path_buf(),
+            }),
+        }
     }
 
     /// Resolve `settings.path`, if it doesn't exist, prepend $HOME.
@@ -69,7 +283,7 @@ impl Config {
     /// Returns `SettingsError::BadPath` if `canonicalize` fails.
     pub(crate) fn resolve_path(mut self) -> Result<Self, ConfigError> {
         if !self.path.exists() {
-            self.path = match Path::new(env!("HOME")).join(&self.path).canonicalize() {
+            self.path = match crate::home::dir().join(&self.path).canonicalize() {
                 Ok(pb) => pb,
                 Err(_) => return Err(ConfigError::BadPath { path: self.path }),
             };
@@ -91,7 +305,7 @@ impl Config {
             }
         }
 
-        if bad_paths.len() > 0 {
+        if !bad_paths.is_empty() {
             return Err(ConfigError::BadPaths { paths: bad_paths });
         }
 
@@ -108,18 +322,16 @@ impl Config {
         self.user = self
             .user
             .into_iter()
-            .map(
-                |pb| match Path::new(env!("HOME")).join(&pb).canonicalize() {
-                    Ok(pb) => pb,
-                    Err(_) => {
-                        bad_paths.push(pb.to_owned());
-                        pb
-                    }
-                },
-            )
+            .map(|pb| match crate::home::dir().join(&pb).canonicalize() {
+                Ok(pb) => pb,
+                Err(_) => {
+                    bad_paths.push(pb.to_owned());
+                    pb
+                }
+            })
             .collect();
 
-        if bad_paths.len() > 0 {
+        if !bad_paths.is_empty() {
             return Err(ConfigError::BadPaths { paths: bad_paths });
         }
 
@@ -129,35 +341,117 @@ impl Config {
 
 #[derive(Debug, Default, Serialize, Deserialize, Clone)]
 pub(crate) struct Packages {
-    #[serde(rename = "core")]
-    pub(crate) core_pkgs: CorePackages,
-    #[serde(rename = "x11")]
-    pub(crate) x11_pkgs: X11Packages,
-    #[serde(rename = "wayland")]
-    pub(crate) wayland_pkgs: WaylandPackages,
+    #[serde(rename = "default", default, skip_serializing_if = "Vec::is_empty")]
+    /// Groups installed when none are asked for, every group if empty.
+    pub(crate) default_groups: Vec<String>,
+
+    #[serde(flatten)]
+    /// Named groups of packages, e.g. `core`, `dev` or `laptop`, in the order
+    /// they're written.
+    pub(crate) groups: IndexMap<String, PackageGroup>,
+
     #[serde(rename = "flatpak")]
     pub(crate) flatpaks: Vec<FlatpakRemote>,
 }
 
-#[derive(Debug, Default, Serialize, Deserialize, Clone)]
-#[serde(rename = "core")]
-pub(crate) struct CorePackages {
-    #[serde(rename = "fedora")]
-    pub(crate) fedora_pkgs: Vec<String>,
+impl Packages {
+    /// Every group needed to install `names`, or the default groups if
+    /// `names` is empty, with the groups each needs before it.
+    ///
+    /// ### Errors
+    /// Returns `ConfigError::NoSuchGroup` if a group isn't defined, or
+    /// `ConfigError::GroupCycle` if groups need each other.
+    pub(crate) fn resolve(
+        &self,
+        names: &[String],
+    ) -> Result<Vec<(&str, &PackageGroup)>, ConfigError> {
+        let names: Vec<&String> = match (names.is_empty(), self.default_groups.is_empty()) {
+            (false, _) => names.iter().collect(),
+            (true, false) => self.default_groups.iter().collect(),
+            (true, true) => self.groups.keys().collect(),
+        };
+
+        let mut order = Vec::new();
+        for name in names {
+            self.visit(name, &mut Vec::new(), &mut order)?;
+        }
+
+        Ok(order
+            .into_iter()
+            .map(|name| (name, &self.groups[name]))
+            .collect())
+    }
+
+    /// Append `name` to `order` after everything it needs, `path` being the
+    /// groups that led to it.
+    fn visit<'a>(
+        &'a self,
+        name: &str,
+        path: &mut Vec<&'a str>,
+        order: &mut Vec<&'a str>,
+    ) -> Result<(), ConfigError> {
+        if order.contains(&name) {
+            return Ok(());
+        }
+        if path.contains(&name) {
+            let mut groups: Vec<String> = path.iter().map(|g| g.to_string()).collect();
+            groups.push(name.to_string());
+            return Err(ConfigError::GroupCycle { groups });
+        }
+
+        let (name, group) =
+            self.groups
+                .get_key_value(name)
+                .ok_or_else(|| ConfigError::NoSuchGroup {
+                    name: name.to_string(),
+                })?;
+        path.push(name);
+        for need in &group.needs {
+            self.visit(need, path, order)?;
+        }
+        path.pop();
+        order.push(name);
+
+        Ok(())
+    }
 }
 
 #[derive(Debug, Default, Serialize, Deserialize, Clone)]
-#[serde(rename = "x11")]
-pub(crate) struct X11Packages {
-    #[serde(rename = "fedora")]
-    pub(crate) fedora_pkgs: Vec<String>,
+/// A named group of packages, installed after the groups it `needs`.
+pub(crate) struct PackageGroup {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    /// Groups to install before this one.
+    pub(crate) needs: Vec<String>,
+
+    #[serde(flatten)]
+    pub(crate) pkgs: DistroPackages,
 }
 
 #[derive(Debug, Default, Serialize, Deserialize, Clone)]
-#[serde(rename = "wayland")]
-pub(crate) struct WaylandPackages {
-    #[serde(rename = "fedora")]
-    pub(crate) fedora_pkgs: Vec<String>,
+#[serde(transparent)]
+/// Package names keyed by distribution, the `ID` in `/etc/os-release`, e.g.
+/// `fedora`, `opensuse-tumbleweed`, `arch`, `debian` or `ubuntu`.
+pub(crate) struct DistroPackages(pub(crate) IndexMap<String, Vec<String>>);
+
+impl DistroPackages {
+    /// Packages for `id`.
+    pub(crate) fn get(&self, id: &str) -> &[String] {
+        self.0.get(id).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    /// Packages for the first of `ids` there are any listed for, e.g. a
+    /// distribution's `ID` then its `ID_LIKE`.
+    pub(crate) fn for_ids<'a>(&self, ids: impl IntoIterator<Item = &'a str>) -> &[String] {
+        ids.into_iter()
+            .find_map(|id| self.0.get(id))
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// Whether there are no packages for any distribution.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.0.values().all(Vec::is_empty)
+    }
 }
 
 #[derive(Debug, Default, Serialize, Deserialize, Clone)]
@@ -169,11 +463,165 @@ pub(crate) struct FlatpakRemote {
     pub(crate) pkgs: Vec<String>,
 }
 
+/// `old` TOML edited in place to hold the entries in `new`, keeping the
+/// comments, formatting & order of whatever `old` still has.
+fn edit_toml(old: &str, new: &str) -> Option<String> {
+    let mut doc: toml_edit::Document = old.parse().ok()?;
+    let new: toml_edit::Document = new.parse().ok()?;
+    sync_table(doc.as_table_mut(), new.as_table());
+    Some(doc.to_string())
+}
+
+/// Make `old` hold the entries in `new`, dropping keys `new` doesn't have &
+/// appending ones `old` doesn't.
+fn sync_table(old: &mut dyn TableLike, new: &dyn TableLike) {
+    let gone: Vec<String> = old
+        .iter()
+        .map(|(key, _)| key.to_string())
+        .filter(|key| !new.contains_key(key))
+        .collect();
+    for key in gone {
+        old.remove(&key);
+    }
+
+    for (key, item) in new.iter() {
+        match old.get_mut(key) {
+            Some(o) => sync_item(o, item),
+            None => {
+                old.insert(key, item.clone());
+            }
+        }
+    }
+}
+
+/// Make `old` hold `new`, keeping the decor of `old` where it can.
+fn sync_item(old: &mut Item, new: &Item) {
+    if let (Some(o), Some(n)) = (old.as_table_like_mut(), new.as_table_like()) {
+        return sync_table(o, n);
+    }
+
+    match (old, new) {
+        (Item::Value(Value::Array(o)), Item::Value(Value::Array(n))) => sync_array(o, n),
+        (Item::ArrayOfTables(o), Item::ArrayOfTables(n)) => {
+            while o.len() > n.len() {
+                o.remove(o.len() - 1);
+            }
+            for (i, t) in n.iter().enumerate() {
+                match o.get_mut(i) {
+                    Some(ot) => sync_table(ot, t),
+                    None => o.push(t.clone()),
+                }
+            }
+        }
+        (Item::Value(o), Item::Value(n)) if same(o, n) => {}
+        (Item::Value(o), Item::Value(n)) => {
+            let decor = o.decor().clone();
+            *o = fresh(n);
+            *o.decor_mut() = decor;
+        }
+        (old, new) => *old = new.clone(),
+    }
+}
+
+/// Make `old` hold the values in `new`, in the order of `new`. Values `old`
+/// already has keep their decor, e.g. a comment above them, new ones are
+/// indented like the last value of `old`.
+fn sync_array(old: &mut Array, new: &Array) {
+    let prefix = |v: &Value| {
+        v.decor()
+            .prefix()
+            .and_then(|p| p.as_str())
+            .unwrap_or("")
+            .to_string()
+    };
+    // Only the whitespace after the last line break, not comments above it.
+    let indent = old.iter().last().map(|v| {
+        let p = prefix(v);
+        match p.rfind('\n') {
+            Some(i) => format!("\n{}", &p[i + 1..]),
+            None => p,
+        }
+    });
+    let first = old.get(0).map(prefix).filter(|p| !p.contains('\n'));
+
+    let mut pool: Vec<Option<Value>> = old.iter().cloned().map(Some).collect();
+    let values: Vec<Value> = new
+        .iter()
+        .map(|n| {
+            let kept = pool
+                .iter_mut()
+                .find(|o| o.as_ref().is_some_and(|o| same(o, n)))
+                .and_then(Option::take);
+            kept.unwrap_or_else(|| {
+                let mut v = fresh(n);
+                if let Some(indent) = &indent {
+                    v.decor_mut().set_prefix(indent.as_str());
+                }
+                v
+            })
+        })
+        .collect();
+
+    old.clear();
+    for v in values {
+        old.push_formatted(v);
+    }
+    if let (Some(first), Some(v)) = (first, old.get_mut(0)) {
+        v.decor_mut().set_prefix(first);
+    }
+}
+
+/// `v` without decor, strings quoted with `"` rather than the `'` `toml`
+/// writes them with.
+fn fresh(v: &Value) -> Value {
+    match v.as_str() {
+        Some(s) => Value::from(s),
+        None => {
+            let mut v = v.clone();
+            v.decor_mut().clear();
+            v
+        }
+    }
+}
+
+/// Whether two values are the same, ignoring their decor & how strings are
+/// quoted.
+fn same(a: &Value, b: &Value) -> bool {
+    match (a.as_str(), b.as_str()) {
+        (Some(a), Some(b)) => a == b,
+        _ => {
+            let (mut a, mut b) = (a.clone(), b.clone());
+            a.decor_mut().clear();
+            b.decor_mut().clear();
+            a.to_string() == b.to_string()
+        }
+    }
+}
+
+/// Whether `text` has `#` comments, a `#` starting a line or following
+/// whitespace outside of quotes, as in TOML & YAML.
+fn has_comments(text: &str) -> bool {
+    text.lines().any(|line| {
+        let (mut quote, mut prev) = (None, ' ');
+        for c in line.chars() {
+            match (quote, c) {
+                (None, '#') if prev.is_whitespace() => return true,
+                (None, '"' | '\'') => quote = Some(c),
+                (Some('"'), _) if prev == '\\' => {}
+                (Some(q), c) if c == q => quote = None,
+                _ => {}
+            }
+            prev = c;
+        }
+        false
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use std::path::Path;
 
-    use super::Config;
+    use super::{find_in, Config, ConfigError, Format, PackageGroup, Packages};
     use crate::_TESTS_DIR;
     use serde_json::{json, Value};
 
@@ -238,7 +686,7 @@ mod tests {
     /// Simple deserialization test.
     fn deserialize() {
         if let Err(e) = deserialize_test_data() {
-            assert!(false, "{}", e)
+            panic!("{}", e)
         }
     }
 
@@ -248,11 +696,223 @@ mod tests {
         match deserialize_test_data() {
             Ok(s) => {
                 if let Err(e) = serde_json::to_string::<Config>(&s) {
-                    assert!(false, "{}", e)
+                    panic!("{}", e)
                 }
             }
-            Err(e) => assert!(false, "{}", e),
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    #[test]
+    /// Expects packages for a distribution's `ID` to be preferred over those
+    /// for its `ID_LIKE`, and nothing for distributions not listed.
+    fn distro_packages() {
+        let mut c = deserialize_test_data().expect("Failed to deserialize!");
+        assert_eq!(c.pkgs.groups["x11"].pkgs.get("fedora").len(), 4);
+
+        let core = &mut c.pkgs.groups.get_mut("core").unwrap().pkgs;
+        core.0
+            .insert(String::from("opensuse"), vec![String::from("fish")]);
+        core.0.insert(
+            String::from("opensuse-tumbleweed"),
+            vec![String::from("MozillaFirefox")],
+        );
+        assert_eq!(
+            core.for_ids(["opensuse-tumbleweed", "opensuse", "suse"]),
+            ["MozillaFirefox"]
+        );
+        assert_eq!(core.for_ids(["opensuse-leap", "opensuse"]), ["fish"]);
+        assert!(core.for_ids(["arch"]).is_empty());
+        assert!(!core.is_empty());
+        assert!(c.pkgs.groups["wayland"].pkgs.for_ids(["debian"]).is_empty());
+    }
+
+    #[test]
+    /// Expects groups to come after the groups they need, once each, the
+    /// default groups when none are asked for, and every group when there
+    /// are no defaults.
+    fn package_groups() {
+        let mut c = deserialize_test_data().expect("Failed to deserialize!");
+        let names = |pkgs: &Packages, asked: &[&str]| -> Result<Vec<String>, ConfigError> {
+            let asked: Vec<String> = asked.iter().map(|g| g.to_string()).collect();
+            Ok(pkgs
+                .resolve(&asked)?
+                .into_iter()
+                .map(|(name, _)| name.to_string())
+                .collect())
+        };
+        assert_eq!(names(&c.pkgs, &[]).unwrap(), ["core", "wayland", "x11"]);
+
+        let dev = PackageGroup {
+            needs: vec![String::from("x11"), String::from("core")],
+            ..Default::default()
+        };
+        c.pkgs.groups.insert(String::from("dev"), dev);
+        c.pkgs.groups.get_mut("x11").unwrap().needs = vec![String::from("core")];
+        assert_eq!(
+            names(&c.pkgs, &["dev", "x11"]).unwrap(),
+            ["core", "x11", "dev"]
+        );
+
+        c.pkgs.default_groups = vec![String::from("x11")];
+        assert_eq!(names(&c.pkgs, &[]).unwrap(), ["core", "x11"]);
+        assert!(matches!(
+            names(&c.pkgs, &["gaming"]),
+            Err(ConfigError::NoSuchGroup { .. })
+        ));
+
+        c.pkgs.groups.get_mut("core").unwrap().needs = vec![String::from("dev")];
+        assert_eq!(
+            names(&c.pkgs, &["dev"]).unwrap_err().to_string(),
+            "Package groups need each other: dev -> x11 -> core -> dev"
+        );
+
+        // Groups & their `needs` survive a round trip.
+        let json = serde_json::to_value(&c).unwrap();
+        assert_eq!(json["packages"]["default"][0], "x11");
+        assert_eq!(json["packages"]["x11"]["needs"][0], "core");
+        assert_eq!(json["packages"]["x11"]["fedora"][0], "x11_pkg0");
+        let read: Config = serde_json::from_value(json).unwrap();
+        assert_eq!(read.pkgs.groups["dev"].needs, ["x11", "core"]);
+    }
+
+    #[test]
+    /// Expects package groups in `nedots.example.json` to be written back in
+    /// the order they're written in, not sorted.
+    fn group_order() {
+        let dir = Path::new(_TESTS_DIR).join("group_order");
+        std::fs::create_dir_all(&dir).expect("Failed to create group_order dir!");
+        let c = Config::read(Some(Path::new("nedots.example.json"))).expect("Failed to read!");
+
+        let path = dir.join("nedots.json");
+        c.write(&path).expect("Failed to write!");
+        let read = Config::read(Some(&path)).expect("Failed to read back!");
+        let names: Vec<&str> = read.pkgs.groups.keys().map(String::as_str).collect();
+        assert_eq!(names, ["core", "x11", "dev", "wayland"]);
+        assert_eq!(
+            read.pkgs.groups["core"].pkgs.0.keys().collect::<Vec<_>>(),
+            ["fedora", "opensuse-tumbleweed"]
+        );
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            std::fs::read_to_string("nedots.example.json").unwrap()
+        );
+
+        std::fs::remove_dir_all(&dir).expect("Failed to remove group_order dir!");
+    }
+
+    #[test]
+    /// Expects paths under $HOME to be tracked in `user` & everything else in
+    /// `root`, without disturbing the order of existing entries.
+    fn track_untrack() {
+        let home = Path::new("/home/me");
+        let mut c = deserialize_test_data().expect("Failed to deserialize!");
+
+        assert!(c.track(home, &home.join(".bashrc")));
+        assert!(!c.track(home, &home.join(".bashrc")));
+        assert!(c.track(home, Path::new("/etc/hosts")));
+        assert_eq!(
+            c.user.last().map(|p| p.as_path()),
+            Some(Path::new(".bashrc"))
+        );
+        assert_eq!(
+            c.root.last().map(|p| p.as_path()),
+            Some(Path::new("/etc/hosts"))
+        );
+
+        c.untrack(home, &home.join("another/fake/home/path"))
+            .expect("Failed to untrack!");
+        assert_eq!(
+            c.user,
+            [
+                Path::new("a/fake/home/path"),
+                Path::new("notice/there/is/no/prefix"),
+                Path::new(".bashrc")
+            ]
+        );
+        assert!(c.untrack(home, Path::new("/not/tracked")).is_err());
+    }
+
+    #[test]
+    /// Expects a config to come back the same from TOML & YAML, by extension,
+    /// and comments to be allowed in both.
+    fn formats() {
+        let dir = Path::new(_TESTS_DIR).join("formats");
+        std::fs::create_dir_all(&dir).expect("Failed to create formats dir!");
+        let c = deserialize_test_data().expect("Failed to deserialize!");
+        let json = c.to_string_as(Format::Json).unwrap();
+
+        for name in ["nedots.toml", "nedots.yaml", "nedots.yml"] {
+            let path = dir.join(name);
+            c.write(&path).expect("Failed to write!");
+            let read = Config::read(Some(&path)).expect("Failed to read!");
+            assert_eq!(read.to_string_as(Format::Json).unwrap(), json);
         }
+        assert_eq!(find_in(&dir), Some(dir.join("nedots.toml")));
+        assert!(Config::default().to_string_as(Format::Toml).is_ok());
+
+        let path = dir.join("commented.toml");
+        std::fs::write(
+            &path,
+            "# Dotfiles.\npath = \".nedots\"\nroot = []\nuser = []\n\n[packages.core]\n\
+             fedora = [\n    \"fish\", # Login shell.\n]\n[packages.x11]\nfedora = []\n\
+             [packages.wayland]\nfedora = []\n[packages]\nflatpak = []\n",
+        )
+        .unwrap();
+        let read = Config::read(Some(&path)).expect("Failed to read commented TOML!");
+        assert_eq!(read.pkgs.groups["core"].pkgs.get("fedora"), ["fish"]);
+
+        std::fs::write(dir.join("bad.toml"), "path = [").unwrap();
+        assert!(Config::read(Some(&dir.join("bad.toml"))).is_err());
+
+        std::fs::remove_dir_all(&dir).expect("Failed to remove formats dir!");
+    }
+
+    #[test]
+    /// Expects rewriting a commented TOML config to keep its comments & the
+    /// order of its entries, and a commented YAML config to be refused.
+    fn keeps_comments() {
+        let dir = Path::new(_TESTS_DIR).join("keeps_comments");
+        std::fs::create_dir_all(&dir).expect("Failed to create keeps_comments dir!");
+        let home = Path::new("/home/me");
+
+        let path = dir.join("nedots.toml");
+        std::fs::write(
+            &path,
+            "# Dotfiles.\npath = \".nedots\"\nuser = [\n    # Shell.\n    \".bashrc\",\n    \".vimrc\",\n]\n\
+             root = []\n\n[packages]\ndefault = [\"x11\", \"core\"]\nflatpak = []\n\n\
+             [packages.x11]\nfedora = [\"xorg\"] # Still needed.\n\n\
+             [packages.core]\nfedora = [\"fish\"]\n",
+        )
+        .unwrap();
+        let mut c = Config::read(Some(&path)).expect("Failed to read!");
+        assert!(c.track(home, &home.join(".gitconfig")));
+        c.untrack(home, &home.join(".vimrc"))
+            .expect("Failed to untrack!");
+        c.write(&path).expect("Failed to write!");
+
+        let written = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(
+            written,
+            "# Dotfiles.\npath = \".nedots\"\nuser = [\n    # Shell.\n    \".bashrc\",\n    \".gitconfig\",\n]\n\
+             root = []\n\n[packages]\ndefault = [\"x11\", \"core\"]\nflatpak = []\n\n\
+             [packages.x11]\nfedora = [\"xorg\"] # Still needed.\n\n\
+             [packages.core]\nfedora = [\"fish\"]\n",
+        );
+        assert!(c.same_as(Config::read(Some(&path)).ok()));
+
+        let path = dir.join("nedots.yaml");
+        std::fs::write(&path, c.to_string_as(Format::Yaml).unwrap()).unwrap();
+        c.write(&path)
+            .expect("Failed to rewrite YAML without comments!");
+        std::fs::write(
+            &path,
+            format!("# Dotfiles.\n{}", std::fs::read_to_string(&path).unwrap()),
+        )
+        .unwrap();
+        assert!(matches!(c.write(&path), Err(ConfigError::Commented { .. })));
+
+        std::fs::remove_dir_all(&dir).expect("Failed to remove keeps_comments dir!");
     }
 
     #[test]
@@ -269,7 +929,7 @@ mod tests {
                     )
                 }
             }
-            Err(e) => assert!(false, "{}", e),
+            Err(e) => panic!("{}", e),
         }
     }
 
@@ -287,7 +947,7 @@ mod tests {
                     )
                 }
             }
-            Err(e) => assert!(false, "{}", e),
+            Err(e) => panic!("{}", e),
         }
     }
 }
@@ -0,0 +1,105 @@
+use crate::config::ConfigError;
+use nix::unistd::{chown, getuid, Gid, Uid, User};
+use std::{
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+/// The home directory being managed, set once per run by `set`.
+static HOME: OnceLock<Home> = OnceLock::new();
+
+#[derive(Debug, Clone)]
+/// A home directory nedots manages, and who should own what's written to it
+/// when that isn't whoever's running nedots.
+pub(crate) struct Home {
+    pub(crate) dir: PathBuf,
+    pub(crate) owner: Option<(Uid, Gid)>,
+}
+
+impl Home {
+    /// The current user's home, `$HOME`, falling back to their passwd entry.
+    pub(crate) fn current() -> Self {
+        let dir = match std::env::var_os("HOME") {
+            Some(h) if !h.is_empty() => PathBuf::from(h),
+            _ => User::from_uid(getuid())
+                .ok()
+                .flatten()
+                .map(|u| u.dir)
+                .unwrap_or_else(|| PathBuf::from("/")),
+        };
+
+        Self { dir, owner: None }
+    }
+
+    /// Another directory, owned by whoever's running nedots.
+    pub(crate) fn at(dir: &Path) -> Self {
+        Self {
+            dir: dir.to_path_buf(),
+            owner: None,
+        }
+    }
+
+    /// `name`'s home, from their passwd entry. Files written to it are given
+    /// to them.
+    ///
+    /// ### Errors
+    /// Returns `ConfigError::NoSuchUser` if there's no such user.
+    pub(crate) fn of_user(name: &str) -> Result<Self, ConfigError> {
+        match User::from_name(name).ok().flatten() {
+            Some(u) => Ok(Self {
+                dir: u.dir,
+                owner: Some((u.uid, u.gid)),
+            }),
+            None => Err(ConfigError::NoSuchUser {
+                name: name.to_string(),
+            }),
+        }
+    }
+}
+
+/// Manage `home` for the rest of this run, ignored once `dir` has been asked
+/// for.
+pub(crate) fn set(home: Home) {
+    HOME.set(home).ok();
+}
+
+fn get() -> &'static Home {
+    HOME.get_or_init(Home::current)
+}
+
+/// The home directory being managed.
+pub(crate) fn dir() -> &'static Path {
+    &get().dir
+}
+
+/// Give `path`, & any directories above it in the managed home, to the
+/// owner of the home, if it has one other than whoever's running nedots.
+pub(crate) fn give(path: &Path) -> Result<(), std::io::Error> {
+    let home = get();
+    let (uid, gid) = match home.owner {
+        Some(owner) if owner.0 != getuid() => owner,
+        _ => return Ok(()),
+    };
+
+    for p in path
+        .ancestors()
+        .take_while(|p| p.starts_with(&home.dir) && *p != home.dir)
+    {
+        chown(p, Some(uid), Some(gid))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Home;
+
+    #[test]
+    /// Expects users to be looked up in passwd, and to own their home.
+    fn of_user() {
+        let root = Home::of_user("root").expect("Failed to find root!");
+        assert!(root.owner.unwrap().0.is_root());
+        assert!(Home::of_user("no-such-user-nedots").is_err());
+    }
+}
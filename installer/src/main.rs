@@ -1,10 +1,12 @@
 mod cli;
 mod config;
+mod home;
 mod ops;
 mod output;
+mod settings;
 
 fn main() -> Result<(), std::io::Error> {
     cli::run()
 }
 
-pub const _TESTS_DIR: &'static str = "tests";
+pub const _TESTS_DIR: &str = "tests";
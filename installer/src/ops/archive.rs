@@ -0,0 +1,360 @@
+use super::{
+    backup,
+    bootstrap::deploy_ops,
+    fs,
+    op::{Operate, Operation, OperationError},
+    plan::Step,
+};
+use crate::{
+    config::{Config, CONFIG_FILE},
+    output::TerminalLogger,
+};
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+/// zstd compression level, the zstd CLI's default.
+const LEVEL: i32 = 3;
+
+/// Directory in an archive that files under $HOME are stored in, relative to
+/// $HOME, so they can be imported into any home.
+const USER_DIR: &str = "user";
+
+#[derive(Debug, Error)]
+/// Errors thrown while writing or reading an archive.
+pub(crate) enum ArchiveError {
+    #[error("Failed to read archive {path:?}: {msg}")]
+    /// Missing, or not a tar.zst archive.
+    BadArchive { path: PathBuf, msg: String },
+
+    #[error("{path:?} has no `nedots.json`, is it an archive written by `nedots export`?")]
+    /// The archive unpacked, but there's no config to deploy it with.
+    NoConfig { path: PathBuf },
+
+    #[error("Failed to serialize `nedots.json`.")]
+    /// Serde error.
+    SerializeError,
+
+    #[error(transparent)]
+    /// A wrapper around IO errors.
+    IoError(#[from] std::io::Error),
+}
+
+/// Write `config` as `nedots.json`, and `files`, relative to `repo`, to a
+/// tar.zst archive at `out`. Files keep their mode, & their path in the
+/// repository unless they're under `home`, then they're stored under
+/// `USER_DIR` relative to it.
+pub(crate) fn pack(
+    repo: &Path,
+    config: &Config,
+    files: &[PathBuf],
+    home: &Path,
+    out: &Path,
+) -> Result<(), ArchiveError> {
+    let json = serde_json::to_vec_pretty(config).or(Err(ArchiveError::SerializeError))?;
+    let encoder = zstd::Encoder::new(File::create(out)?, LEVEL)?;
+    let mut tar = tar::Builder::new(encoder);
+
+    let mut header = tar::Header::new_gnu();
+    header.set_size(json.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(chrono::Local::now().timestamp() as u64);
+    header.set_cksum();
+    tar.append_data(&mut header, CONFIG_FILE, json.as_slice())?;
+
+    let home = home.strip_prefix("/").unwrap_or(home);
+    for f in files {
+        let name = match f.strip_prefix(home) {
+            Ok(rel) => Path::new(USER_DIR).join(rel),
+            Err(_) => f.to_path_buf(),
+        };
+        tar.append_path_with_name(repo.join(f), name)?;
+    }
+
+    tar.into_inner()?.finish()?;
+    Ok(())
+}
+
+/// Unpack the tar.zst archive at `path` into `dir`, returning the config it
+/// was exported with. Files stored under `USER_DIR` are moved to where they'd
+/// be in a repository for `home`.
+pub(crate) fn unpack(path: &Path, dir: &Path, home: &Path) -> Result<Config, OperationError> {
+    let bad = |e: std::io::Error| ArchiveError::BadArchive {
+        path: path.to_path_buf(),
+        msg: e.to_string(),
+    };
+    let decoder = zstd::Decoder::new(File::open(path).map_err(bad)?).map_err(bad)?;
+    let mut tar = tar::Archive::new(decoder);
+    tar.set_preserve_permissions(true);
+    std::fs::create_dir_all(dir).map_err(ArchiveError::from)?;
+    if let Err(e) = tar.unpack(dir) {
+        std::fs::remove_dir_all(dir).ok();
+        return Err(bad(e).into());
+    }
+
+    let config = dir.join(CONFIG_FILE);
+    if !config.exists() {
+        return Err(ArchiveError::NoConfig {
+            path: path.to_path_buf(),
+        }
+        .into());
+    }
+
+    let user = dir.join(USER_DIR);
+    if user.is_dir() {
+        let to = fs::repo_path(dir, home);
+        if let Some(parent) = to.parent() {
+            std::fs::create_dir_all(parent).map_err(ArchiveError::from)?;
+        }
+        std::fs::rename(&user, &to).map_err(ArchiveError::from)?;
+    }
+
+    Ok(Config::read(Some(&config))?)
+}
+
+/// Writes every tracked file in the repository, and the config, to a single
+/// archive that can be imported without git.
+pub(crate) struct Export {
+    pub(crate) parent_op: Operation<TerminalLogger>,
+
+    /// Path of the repository.
+    pub(crate) repo: PathBuf,
+
+    /// Config in use, with resolved paths.
+    pub(crate) config: Config,
+
+    /// Write the archive here.
+    pub(crate) out: PathBuf,
+}
+
+impl Export {
+    pub(crate) fn new(op: Operation<TerminalLogger>) -> Self {
+        Self {
+            parent_op: op,
+            repo: PathBuf::new(),
+            config: Config::default(),
+            out: PathBuf::new(),
+        }
+    }
+
+    /// Assign `repo`.
+    pub(crate) fn of(mut self, repo: PathBuf) -> Self {
+        self.repo = repo;
+        self
+    }
+
+    /// Assign `config`, `user` paths are made relative to $HOME again so the
+    /// archived config works for any user.
+    pub(crate) fn with_config(mut self, mut config: Config) -> Self {
+        config.user = config
+            .user
+            .iter()
+            .map(|p| {
+                p.strip_prefix(crate::home::dir())
+                    .unwrap_or(p)
+                    .to_path_buf()
+            })
+            .collect();
+        self.config = config;
+        self
+    }
+
+    /// Assign `out`, relative paths are relative to the current directory.
+    pub(crate) fn to(mut self, out: &str) -> Result<Self, OperationError> {
+        self.out = std::env::current_dir()
+            .map_err(ArchiveError::from)?
+            .join(out);
+        Ok(self)
+    }
+
+    /// Every tracked file in the repository, relative to it.
+    fn files(&self) -> Result<Vec<PathBuf>, OperationError> {
+        let mut files = Vec::new();
+        for op in deploy_ops(
+            &self.parent_op,
+            &self.repo,
+            &self.config,
+            crate::home::dir(),
+        ) {
+            for (from, _) in op.pairs()? {
+                files.push(from.strip_prefix(&self.repo).unwrap_or(&from).to_path_buf());
+            }
+        }
+
+        Ok(files)
+    }
+}
+
+impl Operate for Export {
+    fn operate(&mut self) -> Result<usize, OperationError> {
+        let files = self.files()?;
+        if self.parent_op.is_dry_run() {
+            self.parent_op.record([Step::Archive {
+                repo: self.repo.to_path_buf(),
+                files,
+                config: Box::new(self.config.clone()),
+                path: self.out.to_path_buf(),
+            }]);
+            return Ok(0);
+        }
+
+        pack(
+            &self.repo,
+            &self.config,
+            &files,
+            crate::home::dir(),
+            &self.out,
+        )?;
+        for f in &files {
+            self.parent_op.report().path(f, "exported");
+        }
+        self.parent_op.insert_result("export", Ok(()));
+        crate::output::term(&format!(
+            "Exported {} file(s) to {}",
+            files.len(),
+            self.out.display()
+        ));
+
+        Ok(0)
+    }
+
+    fn exit_code(&mut self) -> usize {
+        let result = self.operate();
+        self.parent_op.finish(result)
+    }
+}
+
+/// Deploys an archive written by `Export`, copying every file in it into
+/// place, for machines that can't reach the repository.
+pub(crate) struct Import {
+    pub(crate) parent_op: Operation<TerminalLogger>,
+
+    /// Archive to import.
+    pub(crate) path: PathBuf,
+}
+
+impl Import {
+    pub(crate) fn new(op: Operation<TerminalLogger>) -> Self {
+        Self {
+            parent_op: op,
+            path: PathBuf::new(),
+        }
+    }
+
+    /// Assign `path`, relative paths are relative to the current directory.
+    pub(crate) fn from(mut self, path: &str) -> Result<Self, OperationError> {
+        self.path = std::env::current_dir()
+            .map_err(ArchiveError::from)?
+            .join(path);
+        Ok(self)
+    }
+}
+
+impl Operate for Import {
+    fn operate(&mut self) -> Result<usize, OperationError> {
+        // Unpacked next to backups, rather than a temporary directory, so a
+        // plan of the import can still be applied later.
+        let dir = backup::state_dir().join("imports").join(backup::run_id());
+        let config = unpack(&self.path, &dir, crate::home::dir())?;
+        let copy_ops = deploy_ops(&self.parent_op, &dir, &config, crate::home::dir());
+
+        if self.parent_op.is_dry_run() {
+            for op in &copy_ops {
+                self.parent_op.record(op.plan()?);
+            }
+            return Ok(0);
+        }
+
+        for op in &copy_ops {
+            op.copy()?;
+            for (_, to) in op.pairs()? {
+                self.parent_op.report().path(&to, "deployed");
+            }
+        }
+        self.parent_op.insert_result("copy", Ok(()));
+        backup::report();
+        std::fs::remove_dir_all(&dir).map_err(ArchiveError::from)?;
+
+        crate::output::term(&format!(
+            "Deployed {} of {} tracked path(s) from {}, {} as root",
+            copy_ops.len(),
+            config.user.len() + config.root.len(),
+            self.path.display(),
+            copy_ops.iter().filter(|op| op.sudo).count()
+        ));
+
+        Ok(0)
+    }
+
+    fn exit_code(&mut self) -> usize {
+        let result = self.operate();
+        self.parent_op.finish(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{pack, unpack};
+    use crate::{config::Config, ops::fs, _TESTS_DIR};
+    use std::{
+        os::unix::fs::PermissionsExt,
+        path::{Path, PathBuf},
+    };
+
+    #[test]
+    /// Expects files to keep their path & mode through an archive, files
+    /// under $HOME to move to the importing home, and the config to come back
+    /// out.
+    fn round_trip() {
+        let dir = Path::new(_TESTS_DIR).join("archive");
+        let repo = dir.join("repo");
+        let script = Path::new("etc").join("profile.d").join("nedots.sh");
+        std::fs::create_dir_all(repo.join(&script).parent().unwrap())
+            .expect("Failed to create repo!");
+        std::fs::write(repo.join(&script), "export NEDOTS=1\n").expect("Failed to write file!");
+        std::fs::set_permissions(repo.join(&script), PermissionsExt::from_mode(0o755))
+            .expect("Failed to set mode!");
+
+        let (exporter, importer) = (Path::new("/home/exporter"), Path::new("/home/importer"));
+        let bashrc = fs::repo_path(&repo, &exporter.join(".bashrc"));
+        std::fs::create_dir_all(bashrc.parent().unwrap()).expect("Failed to create home!");
+        std::fs::write(&bashrc, "set -o vi\n").expect("Failed to write .bashrc!");
+
+        let config = Config {
+            root: vec![Path::new("/").join(&script)],
+            user: vec![PathBuf::from(".bashrc")],
+            ..Default::default()
+        };
+        let files = [
+            script.to_path_buf(),
+            bashrc.strip_prefix(&repo).unwrap().to_path_buf(),
+        ];
+        let out = dir.join("dots.tar.zst");
+        pack(&repo, &config, &files, exporter, &out).expect("Failed to pack!");
+
+        let unpacked = dir.join("unpacked");
+        let read = unpack(&out, &unpacked, importer).expect("Failed to unpack!");
+        assert_eq!(read.root, config.root);
+        assert_eq!(read.user, config.user);
+        assert_eq!(
+            std::fs::read_to_string(unpacked.join(&script)).unwrap(),
+            "export NEDOTS=1\n"
+        );
+        let mode = std::fs::metadata(unpacked.join(&script))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o755);
+
+        assert_eq!(
+            std::fs::read_to_string(fs::repo_path(&unpacked, &importer.join(".bashrc"))).unwrap(),
+            "set -o vi\n"
+        );
+        assert!(!fs::repo_path(&unpacked, exporter).exists());
+        assert!(!unpacked.join("user").exists());
+
+        std::fs::remove_dir_all(&dir).expect("Failed to remove archive dir!");
+    }
+}
@@ -0,0 +1,437 @@
+use super::{
+    fs::{CopyError, CopyOp},
+    op::{Operate, Operation, OperationError},
+    plan::{self, Step},
+};
+use crate::output::TerminalLogger;
+use chrono::Local;
+use nix::unistd::{geteuid, getgid, getuid};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+    process::Command,
+    sync::OnceLock,
+};
+use thiserror::Error;
+
+/// Name of the manifest in every backup run directory.
+const MANIFEST: &str = "manifest.json";
+
+#[derive(Debug, Error)]
+/// Errors thrown while saving, listing or restoring backups.
+pub(crate) enum BackupError {
+    #[error("Failed to deserialize {path:?}, is it badly formatted?")]
+    /// Serde error.
+    DeserializeError { path: PathBuf },
+
+    #[error("Failed to serialize backup manifest.")]
+    /// Serde error.
+    SerializeError,
+
+    #[error("Failed to back up {path:?} with sudo.")]
+    /// `sudo cp` failed, or was refused.
+    Sudo { path: PathBuf },
+
+    #[error("No backups in {path:?}")]
+    /// Nothing to roll back to.
+    NoBackups { path: PathBuf },
+
+    #[error("No backup named `{id}`, see `nedots backups list`.")]
+    /// Asked for a run that doesn't exist.
+    NoRun { id: String },
+
+    #[error(transparent)]
+    /// A wrapper around IO errors.
+    IoError(#[from] std::io::Error),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// A single file a run overwrote or created.
+pub(crate) struct Entry {
+    /// Where the file lives.
+    pub(crate) path: PathBuf,
+
+    /// The old content, relative to the run directory. `None` when the run
+    /// created the file.
+    pub(crate) backup: Option<PathBuf>,
+
+    /// The file is owned by root.
+    pub(crate) sudo: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Every file a single run of nedots overwrote or created.
+pub(crate) struct Manifest {
+    pub(crate) id: String,
+
+    /// When the run started.
+    pub(crate) created: String,
+
+    pub(crate) entries: Vec<Entry>,
+}
+
+impl Manifest {
+    /// Read the manifest in run directory `dir`, or start a new one if there
+    /// isn't one yet.
+    pub(crate) fn open(dir: &Path) -> Result<Self, BackupError> {
+        let path = dir.join(MANIFEST);
+        if !path.exists() {
+            return Ok(Self {
+                id: dir
+                    .file_name()
+                    .map(|n| n.to_string_lossy().to_string())
+                    .unwrap_or_default(),
+                created: Local::now().to_rfc3339(),
+                entries: Vec::new(),
+            });
+        }
+
+        serde_json::from_reader(File::open(&path)?).or(Err(BackupError::DeserializeError { path }))
+    }
+
+    /// Write the manifest into run directory `dir`.
+    pub(crate) fn write(&self, dir: &Path) -> Result<(), BackupError> {
+        std::fs::create_dir_all(dir)?;
+        let json = serde_json::to_string_pretty(self).or(Err(BackupError::SerializeError))?;
+        Ok(std::fs::write(dir.join(MANIFEST), json + "\n")?)
+    }
+}
+
+/// Where nedots keeps state, `$XDG_STATE_HOME/nedots`, falling back to
+/// `~/.local/state/nedots`.
+pub(crate) fn state_dir() -> PathBuf {
+    let state = match std::env::var_os("XDG_STATE_HOME") {
+        Some(s) if !s.is_empty() => PathBuf::from(s),
+        _ => crate::home::dir().join(".local").join("state"),
+    };
+
+    state.join("nedots")
+}
+
+/// Where backups are kept, `backups` in the `state_dir`.
+pub(crate) fn store() -> PathBuf {
+    state_dir().join("backups")
+}
+
+/// Identifies this run of nedots, the same for every backup it takes.
+pub(crate) fn run_id() -> &'static str {
+    static ID: OnceLock<String> = OnceLock::new();
+    ID.get_or_init(|| Local::now().format("%Y%m%dT%H%M%S%.3f").to_string())
+}
+
+/// Directory this run's backups are saved into.
+pub(crate) fn run_dir() -> PathBuf {
+    store().join(run_id())
+}
+
+/// Save whatever is at `path` into run directory `dir` before it's
+/// overwritten, and add it to the manifest. Only the first save of a path is
+/// kept, since that's what was there before the run.
+pub(crate) fn save(dir: &Path, path: &Path, sudo: bool) -> Result<(), BackupError> {
+    let mut manifest = Manifest::open(dir)?;
+    if manifest.entries.iter().any(|e| e.path == path) {
+        return Ok(());
+    }
+
+    let backup = match path.exists() {
+        true => {
+            let rel = path.strip_prefix("/").unwrap_or(path).to_path_buf();
+            if let Some(parent) = dir.join(&rel).parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            copy(path, &dir.join(&rel), sudo)?;
+            Some(rel)
+        }
+        false => None,
+    };
+
+    manifest.entries.push(Entry {
+        path: path.to_path_buf(),
+        backup,
+        sudo,
+    });
+    manifest.write(dir)
+}
+
+/// Tell the user how to undo this run, if it backed anything up.
+pub(crate) fn report() {
+    if let Ok(m) = Manifest::open(&run_dir()) {
+        if !m.entries.is_empty() {
+            crate::output::term(&format!(
+                "Backed up {} file(s), undo with `nedots rollback {}`.",
+                m.entries.len(),
+                m.id
+            ));
+        }
+    }
+}
+
+/// Every run in `store`, oldest first.
+pub(crate) fn runs(store: &Path) -> Result<Vec<Manifest>, BackupError> {
+    let mut runs = Vec::new();
+    if !store.is_dir() {
+        return Ok(runs);
+    }
+
+    for e in std::fs::read_dir(store)? {
+        let path = e?.path();
+        if path.join(MANIFEST).is_file() {
+            runs.push(Manifest::open(&path)?);
+        }
+    }
+    runs.sort_by(|a, b| a.id.cmp(&b.id));
+
+    Ok(runs)
+}
+
+/// The `Step`s that put every entry of run `dir` back how it was.
+pub(crate) fn restore_plan(dir: &Path, manifest: &Manifest) -> Result<Vec<Step>, CopyError> {
+    let mut steps = Vec::new();
+    for e in manifest.entries.iter().rev() {
+        match &e.backup {
+            Some(b) => steps.push(Step::Copy {
+                from: dir.join(b),
+                to: e.path.to_path_buf(),
+                sudo: e.sudo,
+                hash: plan::hash(&dir.join(b))?,
+            }),
+            None => steps.push(Step::Remove {
+                path: e.path.to_path_buf(),
+            }),
+        }
+    }
+
+    Ok(steps)
+}
+
+/// Copy `from` to `to`, with `sudo` when `sudo` is set & we aren't root. The
+/// copy is then given to whoever's running nedots, like the repository's
+/// copy of the file, so a rollback can read it back.
+fn copy(from: &Path, to: &Path, sudo: bool) -> Result<(), BackupError> {
+    if sudo && !geteuid().is_root() {
+        let owner = format!("{}:{}", getuid(), getgid());
+        let mut cp = Command::new("sudo");
+        cp.arg("cp").arg(from).arg(to);
+        let mut chown = Command::new("sudo");
+        chown.arg("chown").arg(owner).arg(to);
+
+        for mut cmd in [cp, chown] {
+            if !cmd.status()?.success() {
+                return Err(BackupError::Sudo {
+                    path: from.to_path_buf(),
+                });
+            }
+        }
+        return Ok(());
+    }
+
+    std::fs::copy(from, to)?;
+    Ok(())
+}
+
+/// Remove `path`, with `sudo` when `sudo` is set & we aren't root.
+fn remove(path: &Path, sudo: bool) -> Result<(), CopyError> {
+    if !path.exists() {
+        return Ok(());
+    }
+
+    if sudo && !geteuid().is_root() {
+        if !Command::new("sudo").arg("rm").arg(path).status()?.success() {
+            return Err(CopyError::Sudo {
+                path: path.to_path_buf(),
+            });
+        }
+        return Ok(());
+    }
+
+    Ok(std::fs::remove_file(path)?)
+}
+
+/// Restores every file saved by a run.
+pub(crate) struct Rollback {
+    pub(crate) parent_op: Operation<TerminalLogger>,
+
+    /// Where backups are kept.
+    pub(crate) store: PathBuf,
+
+    /// Run to restore, the latest when `None`.
+    pub(crate) id: Option<String>,
+}
+
+impl Rollback {
+    pub(crate) fn new(op: Operation<TerminalLogger>) -> Self {
+        Self {
+            parent_op: op,
+            store: store(),
+            id: None,
+        }
+    }
+
+    /// Assign `id`.
+    pub(crate) fn run(mut self, id: Option<String>) -> Self {
+        self.id = id;
+        self
+    }
+}
+
+impl Operate for Rollback {
+    fn operate(&mut self) -> Result<usize, OperationError> {
+        let runs = runs(&self.store)?;
+        let manifest = match &self.id {
+            Some(id) => runs
+                .into_iter()
+                .find(|m| &m.id == id)
+                .ok_or(BackupError::NoRun { id: id.to_string() })?,
+            None => runs.into_iter().last().ok_or(BackupError::NoBackups {
+                path: self.store.to_path_buf(),
+            })?,
+        };
+
+        let dir = self.store.join(&manifest.id);
+        let steps = restore_plan(&dir, &manifest)?;
+        if self.parent_op.is_dry_run() {
+            self.parent_op.record(steps);
+            return Ok(0);
+        }
+
+        for step in &steps {
+            match step {
+                Step::Copy { from, to, sudo, .. } => {
+                    CopyOp::new().from(from).to(to).with_sudo(*sudo).copy()?;
+                    self.parent_op.report().path(to, "restored");
+                }
+                Step::Remove { path } => {
+                    let sudo = manifest.entries.iter().any(|e| &e.path == path && e.sudo);
+                    remove(path, sudo)?;
+                    self.parent_op.report().path(path, "removed");
+                }
+                _ => unreachable!(),
+            }
+            crate::output::term(&step.to_string());
+        }
+        crate::output::term(&format!("Rolled back {}", manifest.id));
+        self.parent_op
+            .report()
+            .details
+            .insert("run", json!(manifest.id));
+
+        Ok(0)
+    }
+
+    fn exit_code(&mut self) -> usize {
+        let result = self.operate();
+        self.parent_op.finish(result)
+    }
+}
+
+/// Lists backup runs, or prunes all but the latest few.
+pub(crate) struct Backups {
+    pub(crate) parent_op: Operation<TerminalLogger>,
+
+    /// Where backups are kept.
+    pub(crate) store: PathBuf,
+
+    /// Remove all but this many of the latest runs, list runs when `None`.
+    pub(crate) keep: Option<usize>,
+}
+
+impl Backups {
+    pub(crate) fn new(op: Operation<TerminalLogger>) -> Self {
+        Self {
+            parent_op: op,
+            store: store(),
+            keep: None,
+        }
+    }
+
+    /// Assign `keep`.
+    pub(crate) fn prune(mut self, keep: Option<usize>) -> Self {
+        self.keep = keep;
+        self
+    }
+}
+
+impl Operate for Backups {
+    fn operate(&mut self) -> Result<usize, OperationError> {
+        let runs = runs(&self.store)?;
+        let keep = match self.keep {
+            Some(keep) => keep,
+            None => {
+                for m in &runs {
+                    crate::output::term(&format!(
+                        "{}  {}  {} file(s)",
+                        m.id,
+                        m.created,
+                        m.entries.len()
+                    ));
+                }
+                self.parent_op.report().details.insert("runs", json!(runs));
+                return Ok(0);
+            }
+        };
+
+        let stale = runs.len().saturating_sub(keep);
+        for m in &runs[..stale] {
+            let dir = self.store.join(&m.id);
+            if self.parent_op.is_dry_run() {
+                self.parent_op.record([Step::Remove { path: dir }]);
+                continue;
+            }
+
+            std::fs::remove_dir_all(&dir).map_err(BackupError::from)?;
+            crate::output::term(&format!("Removed {}", m.id));
+            self.parent_op.report().path(&dir, "removed");
+        }
+
+        Ok(0)
+    }
+
+    fn exit_code(&mut self) -> usize {
+        let result = self.operate();
+        self.parent_op.finish(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{restore_plan, runs, save, Manifest};
+    use crate::{ops::plan::Step, _TESTS_DIR};
+    use std::path::Path;
+
+    #[test]
+    /// Expects overwritten files to be saved once, created files to be
+    /// noted, and restoring to undo both in reverse order.
+    fn save_and_restore() {
+        let base_path = Path::new(_TESTS_DIR).join("backup");
+        std::fs::create_dir_all(&base_path).expect("Failed to make backup dir!");
+        let base_path = base_path.canonicalize().unwrap();
+        let store = base_path.join("store");
+        let dir = store.join("run");
+        let old = base_path.join("old");
+        std::fs::write(&old, "before").expect("Failed to write old!");
+
+        save(&dir, &old, false).expect("Failed to save old!");
+        std::fs::write(&old, "during").expect("Failed to overwrite old!");
+        save(&dir, &old, false).expect("Failed to save old again!");
+        save(&dir, &base_path.join("new"), false).expect("Failed to save new!");
+
+        let manifest = Manifest::open(&dir).expect("Failed to open manifest!");
+        assert_eq!(manifest.id, "run");
+        assert_eq!(manifest.entries.len(), 2);
+        assert_eq!(runs(&store).expect("Failed to list runs!").len(), 1);
+
+        let steps = restore_plan(&dir, &manifest).expect("Failed to plan restore!");
+        assert!(matches!(&steps[0], Step::Remove { path } if path == &base_path.join("new")));
+        match &steps[1] {
+            Step::Copy { from, to, .. } => {
+                assert_eq!(to, &old);
+                assert_eq!(std::fs::read_to_string(from).unwrap(), "before");
+            }
+            s => panic!("Unexpected step: {}", s),
+        }
+
+        std::fs::remove_dir_all(&base_path).expect("Failed to remove backup dir!");
+    }
+}
@@ -0,0 +1,254 @@
+use super::{
+    backup,
+    fs::{self, CopyOp},
+    git::GitOp,
+    op::{Operate, Operation, OperationError},
+    pkg,
+    plan::Step,
+};
+use crate::{
+    config::{self, Config, CONFIG_FILE},
+    output::{logger::Logger, verbosity::MinVerbosity, TerminalLogger},
+};
+use std::path::{Path, PathBuf};
+
+/// Sets up a fresh machine: clones the repository, deploys every tracked file
+/// & installs packages.
+pub(crate) struct Bootstrap {
+    pub(crate) parent_op: Operation<TerminalLogger>,
+
+    /// Repository to clone, a URL, local path or `file://` URL.
+    pub(crate) url: String,
+
+    /// Clone into this path.
+    pub(crate) path: PathBuf,
+
+    /// Answer yes to package manager prompts.
+    pub(crate) assume_yes: bool,
+
+    /// Skip installing packages.
+    pub(crate) skip_packages: bool,
+
+    /// Deploy `user` paths under this home, the managed home by default.
+    pub(crate) home: PathBuf,
+}
+
+impl Bootstrap {
+    pub(crate) fn new(op: Operation<TerminalLogger>) -> Self {
+        Self {
+            parent_op: op,
+            url: String::new(),
+            path: PathBuf::new(),
+            assume_yes: false,
+            skip_packages: false,
+            home: crate::home::dir().to_path_buf(),
+        }
+    }
+
+    /// Assign `url`.
+    pub(crate) fn from(mut self, url: &str) -> Self {
+        self.url = url.to_string();
+        self
+    }
+
+    /// Assign `path`, relative paths are relative to $HOME.
+    pub(crate) fn to(mut self, path: &Path) -> Self {
+        self.path = crate::home::dir().join(path);
+        self
+    }
+
+    /// Assign `assume_yes`.
+    pub(crate) fn assume_yes(mut self, assume_yes: bool) -> Self {
+        self.assume_yes = assume_yes;
+        self
+    }
+
+    /// Assign `skip_packages`.
+    pub(crate) fn skip_packages(mut self, skip_packages: bool) -> Self {
+        self.skip_packages = skip_packages;
+        self
+    }
+
+    /// Assign `home`.
+    pub(crate) fn home(mut self, home: &Path) -> Self {
+        self.home = home.to_path_buf();
+        self
+    }
+}
+
+/// A `CopyOp` out of `repo` for every path `config` tracks that's in it,
+/// `user` paths under `home` & `root` paths copying with `sudo`.
+pub(crate) fn deploy_ops(
+    op: &Operation<TerminalLogger>,
+    repo: &Path,
+    config: &Config,
+    home: &Path,
+) -> Vec<CopyOp> {
+    let user = config.user.iter().map(|p| (home.join(p), false));
+    let root = config.root.iter().map(|p| (p.to_path_buf(), true));
+
+    let mut ops = Vec::new();
+    for (live, sudo) in user.chain(root) {
+        let from = fs::repo_path(repo, &live);
+        if !from.exists() {
+            op.log(&format!(
+                "{} isn't in the repository, skipping",
+                live.display()
+            ))
+            .ok();
+            continue;
+        }
+
+        ops.push(
+            CopyOp::new()
+                .from(&from)
+                .to(&live)
+                .with_sudo(sudo)
+                .backup_to(&backup::run_dir()),
+        );
+    }
+
+    ops
+}
+
+impl Operate for Bootstrap {
+    fn operate(&mut self) -> Result<usize, OperationError> {
+        // Everything else is read from the clone, so there's nothing more to
+        // plan until it exists.
+        if self.parent_op.is_dry_run() {
+            self.parent_op.record([Step::Clone {
+                url: self.url.to_string(),
+                path: self.path.to_path_buf(),
+            }]);
+            return Ok(0);
+        }
+
+        GitOp::new().clone(&self.url, &self.path)?;
+        self.parent_op.insert_result("git_clone", Ok(()));
+
+        let config_path =
+            config::find_in(&self.path).unwrap_or_else(|| self.path.join(CONFIG_FILE));
+        let config = Config::read(Some(&config_path))?;
+        let copy_ops = deploy_ops(&self.parent_op, &self.path, &config, &self.home);
+        for op in &copy_ops {
+            op.copy()?;
+            self.parent_op
+                .report()
+                .path(op.to.as_ref().unwrap(), "deployed");
+        }
+        self.parent_op.insert_result("copy", Ok(()));
+        backup::report();
+
+        let mut installed = 0;
+        if !self.skip_packages {
+            let quiet = !self.parent_op.verbose_enough();
+            for op in pkg::install_ops(&config.pkgs, &[], self.assume_yes, quiet)? {
+                let result = op.install();
+                self.parent_op
+                    .report()
+                    .packages
+                    .push(op.report(result.is_ok()));
+                result?;
+                installed += op.pkgs.len();
+            }
+            self.parent_op.insert_result("install", Ok(()));
+        }
+
+        let deployed = copy_ops.len();
+        let tracked = config.user.len() + config.root.len();
+        let as_root = copy_ops.iter().filter(|op| op.sudo).count();
+        let details = &mut self.parent_op.report().details;
+        details.insert("deployed", deployed.into());
+        details.insert("tracked", tracked.into());
+        details.insert("as_root", as_root.into());
+        details.insert("installed", installed.into());
+
+        crate::output::term(&format!("Cloned {} to {}", self.url, self.path.display()));
+        crate::output::term(&format!(
+            "Deployed {} of {} tracked path(s), {} as root",
+            deployed, tracked, as_root
+        ));
+        match self.skip_packages {
+            true => crate::output::term("Skipped packages"),
+            false => crate::output::term(&format!("Installed {} package(s)", installed)),
+        }
+
+        Ok(0)
+    }
+
+    fn exit_code(&mut self) -> usize {
+        let result = self.operate();
+        self.parent_op.finish(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Bootstrap;
+    use crate::{
+        config::Config,
+        ops::{fs, op::Operate, op::Operation},
+        output::TerminalLogger,
+        _TESTS_DIR,
+    };
+    use git2::{Repository, Signature};
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    /// Expects a repository to be cloned from a `file://` URL & the files it
+    /// tracks to be deployed into the home they're asked for, skipping
+    /// packages.
+    fn file_url() {
+        let base_path = std::env::current_dir()
+            .unwrap()
+            .join(_TESTS_DIR)
+            .join("bootstrap");
+        let (origin, clone, home) = (
+            base_path.join("origin"),
+            base_path.join("clone"),
+            base_path.join("home"),
+        );
+
+        let bashrc = home.join(".bashrc");
+        let tracked = fs::repo_path(&origin, &bashrc);
+        std::fs::create_dir_all(tracked.parent().unwrap()).expect("Failed to create origin!");
+        std::fs::write(&tracked, "set -o vi\n").expect("Failed to write .bashrc!");
+        let config = Config {
+            path: clone.to_path_buf(),
+            user: vec![PathBuf::from(".bashrc"), PathBuf::from(".vimrc")],
+            ..Default::default()
+        };
+        config
+            .write(&origin.join("nedots.json"))
+            .expect("Failed to write config!");
+
+        let repo = Repository::init(&origin).expect("Failed to init repository!");
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("nedots.json")).unwrap();
+        index
+            .add_path(tracked.strip_prefix(&origin).unwrap())
+            .unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let sig = Signature::now("nedots", "nedots@localhost").unwrap();
+        repo.commit(Some("HEAD"), &sig, &sig, "dots", &tree, &[])
+            .expect("Failed to commit!");
+
+        let op = Operation::new().with_logging(TerminalLogger::new());
+        let mut bootstrap = Bootstrap::new(op)
+            .from(&format!("file://{}", origin.display()))
+            .to(&clone)
+            .home(&home)
+            .skip_packages(true);
+        bootstrap.operate().expect("Failed to bootstrap!");
+
+        assert_eq!(std::fs::read_to_string(&bashrc).unwrap(), "set -o vi\n");
+        assert!(!home.join(".vimrc").exists());
+        let details = &bootstrap.parent_op.report().details;
+        assert_eq!(details["deployed"], 1);
+        assert_eq!(details["tracked"], 2);
+        assert_eq!(details["as_root"], 0);
+        assert_eq!(details["installed"], 0);
+
+        std::fs::remove_dir_all(&base_path).expect("Failed to remove bootstrap dir!");
+    }
+}
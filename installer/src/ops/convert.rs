@@ -0,0 +1,446 @@
+use super::{
+    fs,
+    op::{Operate, Operation, OperationError},
+    pkg::Distro,
+    plan::Step,
+};
+use crate::{
+    config::{Config, ConfigError},
+    output::TerminalLogger,
+};
+use clap::ArgEnum;
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ArgEnum)]
+/// Formats `Config` can be converted to.
+pub(crate) enum Target {
+    /// An Ansible playbook.
+    Ansible,
+
+    /// A Nix home-manager module.
+    HomeManager,
+}
+
+/// First line of everything `convert` writes.
+const GENERATED: &str = "Generated by `nedots convert` from nedots.json, edits will be lost.";
+
+/// Quote `s` for YAML, JSON strings are valid YAML.
+fn yaml(s: &str) -> String {
+    serde_json::Value::from(s).to_string()
+}
+
+/// Where the repository keeps `live`, with a trailing `/` for directories so
+/// their contents are copied rather than the directory itself.
+fn source(repo: &Path, live: &Path) -> String {
+    let src = fs::repo_path(repo, live);
+    match src.is_dir() {
+        true => format!("{}/", src.display()),
+        false => src.display().to_string(),
+    }
+}
+
+/// An Ansible condition that holds on the distribution with os-release `ID`
+/// `id`, by the name Ansible gives it.
+fn ansible_when(id: &str) -> String {
+    let name = match id {
+        "fedora" => "Fedora",
+        "rhel" => "RedHat",
+        "centos" => "CentOS",
+        "opensuse-tumbleweed" => "openSUSE Tumbleweed",
+        "opensuse-leap" => "openSUSE Leap",
+        "arch" => "Archlinux",
+        "debian" => "Debian",
+        "ubuntu" => "Ubuntu",
+        _ => return format!("ansible_distribution | lower == {}", yaml(id)),
+    };
+    format!("ansible_distribution == {}", yaml(name))
+}
+
+/// A playbook installing the packages in `config` & copying its files out of
+/// the repository.
+fn ansible(config: &Config) -> String {
+    let mut out = format!("# {}\n", GENERATED);
+    out.push_str("- name: Deploy files & packages tracked by nedots\n");
+    out.push_str("  hosts: all\n");
+    out.push_str("  tasks:\n");
+
+    let pkgs = &config.pkgs;
+    for (group, g) in &pkgs.groups {
+        for (id, names) in &g.pkgs.0 {
+            if names.is_empty() {
+                continue;
+            }
+            out.push_str(&format!(
+                "    - name: Install {} packages on {}\n",
+                group, id
+            ));
+            out.push_str("      become: true\n");
+            out.push_str("      ansible.builtin.package:\n");
+            out.push_str("        state: present\n");
+            out.push_str("        name:\n");
+            for n in names {
+                out.push_str(&format!("          - {}\n", yaml(n)));
+            }
+            out.push_str(&format!("      when: {}\n", ansible_when(id)));
+        }
+    }
+
+    for fp in &pkgs.flatpaks {
+        out.push_str(&format!("    - name: Add Flatpak remote {}\n", fp.remote));
+        out.push_str("      become: true\n");
+        out.push_str("      community.general.flatpak_remote:\n");
+        out.push_str(&format!("        name: {}\n", yaml(&fp.remote)));
+        out.push_str(&format!("        flatpakrepo_url: {}\n", yaml(&fp.url)));
+        out.push_str("        state: present\n");
+        if fp.pkgs.is_empty() {
+            continue;
+        }
+        out.push_str(&format!(
+            "    - name: Install Flatpaks from {}\n",
+            fp.remote
+        ));
+        out.push_str("      become: true\n");
+        out.push_str("      community.general.flatpak:\n");
+        out.push_str(&format!("        remote: {}\n", yaml(&fp.remote)));
+        out.push_str("        state: present\n");
+        out.push_str("        name:\n");
+        for n in &fp.pkgs {
+            out.push_str(&format!("          - {}\n", yaml(n)));
+        }
+    }
+
+    let home = crate::home::dir();
+    let user: Vec<_> = config
+        .user
+        .iter()
+        .map(|p| {
+            let rel = p.strip_prefix(home).unwrap_or(p);
+            let dest = format!("{{{{ ansible_env.HOME }}}}/{}", rel.display());
+            (source(&config.path, &home.join(p)), dest)
+        })
+        .collect();
+    let root: Vec<_> = config
+        .root
+        .iter()
+        .map(|p| (source(&config.path, p), p.display().to_string()))
+        .collect();
+    for (files, whose, sudo) in [(user, "user", false), (root, "root", true)] {
+        if files.is_empty() {
+            continue;
+        }
+        out.push_str(&format!("    - name: Copy {} files\n", whose));
+        if sudo {
+            out.push_str("      become: true\n");
+        }
+        out.push_str("      ansible.builtin.copy:\n");
+        out.push_str("        src: \"{{ item.src }}\"\n");
+        out.push_str("        dest: \"{{ item.dest }}\"\n");
+        out.push_str("        mode: preserve\n");
+        out.push_str("      loop:\n");
+        for (src, dest) in files {
+            out.push_str(&format!(
+                "        - {{ src: {}, dest: {} }}\n",
+                yaml(&src),
+                yaml(&dest)
+            ));
+        }
+    }
+
+    out
+}
+
+/// Package `name` as an element of `with pkgs; [ ... ]`. Names that aren't
+/// plain identifiers are quoted, and a quoted name alone is a string rather
+/// than the package, so those are selected from `pkgs` explicitly.
+fn nix_pkg(name: &str) -> String {
+    let plain = matches!(name.chars().next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "_-'".contains(c));
+    match plain {
+        true => name.to_string(),
+        false => format!("pkgs.{}", serde_json::Value::from(name)),
+    }
+}
+
+/// `path` as a Nix path, falling back to a string appended to `/.` when it
+/// can't be written as a literal.
+fn nix_path(path: &str) -> String {
+    let path = path.trim_end_matches('/');
+    match path
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || "._-+/".contains(c))
+    {
+        true => path.to_string(),
+        false => format!("/. + {}", serde_json::Value::from(path)),
+    }
+}
+
+/// The distribution whose package lists stand in for nixpkgs names,
+/// `distro` if any group lists packages for it, else the first one listed.
+fn nix_distro<'a>(config: &'a Config, distro: &Distro) -> Option<&'a str> {
+    let listed: Vec<&String> = config
+        .pkgs
+        .groups
+        .values()
+        .flat_map(|g| g.pkgs.0.keys())
+        .collect();
+    distro
+        .ids()
+        .find_map(|id| listed.iter().find(|l| l.as_str() == id))
+        .or_else(|| listed.first())
+        .map(|id| id.as_str())
+}
+
+/// A home-manager module installing the packages in `config` & linking its
+/// user files out of the repository. Packages are named as they are for
+/// `distro`, or whichever distribution's are listed. Neither root files nor
+/// Flatpaks can be managed by home-manager, so they're left as comments.
+fn home_manager(config: &Config, distro: &Distro) -> String {
+    // Blocks of the module, separated by blank lines.
+    let mut blocks = Vec::new();
+
+    let pkgs = &config.pkgs;
+    if let Some(id) = nix_distro(config, distro) {
+        let names: Vec<_> = pkgs.groups.values().flat_map(|g| g.pkgs.get(id)).collect();
+        if !names.is_empty() {
+            let mut out = String::new();
+            out.push_str(&format!(
+                "  # Package names from the `{}` lists, some may differ in nixpkgs.\n",
+                id
+            ));
+            out.push_str("  home.packages = with pkgs; [\n");
+            for n in names {
+                out.push_str(&format!("    {}\n", nix_pkg(n)));
+            }
+            out.push_str("  ];\n");
+            blocks.push(out);
+        }
+    }
+
+    let home = crate::home::dir();
+    if !config.user.is_empty() {
+        let mut out = String::from("  home.file = {\n");
+        for p in &config.user {
+            let rel = p.strip_prefix(home).unwrap_or(p).display().to_string();
+            let src = source(&config.path, &home.join(p));
+            match src.ends_with('/') {
+                true => out.push_str(&format!(
+                    "    {} = {{ source = {}; recursive = true; }};\n",
+                    serde_json::Value::from(rel),
+                    nix_path(&src)
+                )),
+                false => out.push_str(&format!(
+                    "    {}.source = {};\n",
+                    serde_json::Value::from(rel),
+                    nix_path(&src)
+                )),
+            }
+        }
+        out.push_str("  };\n");
+        blocks.push(out);
+    }
+
+    if !config.root.is_empty() {
+        let mut out = String::from(
+            "  # Root files belong in a NixOS configuration, e.g. `environment.etc`:\n",
+        );
+        for p in &config.root {
+            out.push_str(&format!("  #   {}\n", p.display()));
+        }
+        blocks.push(out);
+    }
+
+    for fp in &pkgs.flatpaks {
+        let mut out = format!(
+            "  # Flatpaks from {} ({}) aren't managed by home-manager:\n",
+            fp.remote, fp.url
+        );
+        for n in &fp.pkgs {
+            out.push_str(&format!("  #   {}\n", n));
+        }
+        blocks.push(out);
+    }
+
+    format!(
+        "# {}\n{{ pkgs, ... }}:\n\n{{\n{}}}\n",
+        GENERATED,
+        blocks.join("\n")
+    )
+}
+
+/// Translates `Config` into another provisioning tool's format.
+pub(crate) struct Convert {
+    pub(crate) parent_op: Operation<TerminalLogger>,
+
+    /// Config in use, with resolved paths.
+    pub(crate) config: Config,
+
+    /// Format to convert to.
+    pub(crate) target: Target,
+
+    /// Write here instead of stdout.
+    pub(crate) out: Option<PathBuf>,
+}
+
+impl Convert {
+    pub(crate) fn new(op: Operation<TerminalLogger>) -> Self {
+        Self {
+            parent_op: op,
+            config: Config::default(),
+            target: Target::Ansible,
+            out: None,
+        }
+    }
+
+    /// Assign `config`.
+    pub(crate) fn with_config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Assign `target`.
+    pub(crate) fn to(mut self, target: Target) -> Self {
+        self.target = target;
+        self
+    }
+
+    /// Assign `out`.
+    pub(crate) fn write_to(mut self, out: Option<PathBuf>) -> Self {
+        self.out = out;
+        self
+    }
+}
+
+impl Operate for Convert {
+    fn operate(&mut self) -> Result<usize, OperationError> {
+        let converted = match self.target {
+            Target::Ansible => ansible(&self.config),
+            Target::HomeManager => {
+                home_manager(&self.config, &Distro::current().unwrap_or_default())
+            }
+        };
+
+        match &self.out {
+            Some(path) if self.parent_op.is_dry_run() => {
+                self.parent_op.record([Step::WriteFile {
+                    path: path.to_path_buf(),
+                    content: converted,
+                }]);
+            }
+            Some(path) => {
+                std::fs::write(path, converted).map_err(ConfigError::from)?;
+                self.parent_op.insert_result("write", Ok(()));
+                crate::output::term(&format!("Wrote {}", path.display()));
+            }
+            None if crate::output::is_machine_readable() => {
+                self.parent_op
+                    .report()
+                    .details
+                    .insert("converted", converted.into());
+            }
+            None => std::io::stdout()
+                .write_all(converted.as_bytes())
+                .map_err(ConfigError::from)?,
+        }
+
+        Ok(0)
+    }
+
+    fn exit_code(&mut self) -> usize {
+        let result = self.operate();
+        self.parent_op.finish(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ansible, home_manager, nix_path, nix_pkg};
+    use crate::{
+        config::{Config, FlatpakRemote, PackageGroup},
+        ops::pkg::Distro,
+    };
+    use std::path::PathBuf;
+
+    fn config() -> Config {
+        let mut config = Config {
+            path: PathBuf::from("/nonexistent/nedots"),
+            root: vec![PathBuf::from("/etc/hosts")],
+            user: vec![PathBuf::from(".bashrc")],
+            ..Default::default()
+        };
+        let mut core = PackageGroup::default();
+        core.pkgs
+            .0
+            .insert(String::from("fedora"), vec![String::from("fish")]);
+        core.pkgs
+            .0
+            .insert(String::from("arch"), vec![String::from("fish-shell")]);
+        config.pkgs.groups.insert(String::from("core"), core);
+        let mut desktop = PackageGroup::default();
+        desktop
+            .pkgs
+            .0
+            .insert(String::from("fedora"), vec![String::from("gtk+3")]);
+        config.pkgs.groups.insert(String::from("desktop"), desktop);
+        config.pkgs.flatpaks = vec![FlatpakRemote {
+            remote: String::from("flathub"),
+            url: String::from("https://flathub.org/repo/flathub.flatpakrepo"),
+            pkgs: vec![String::from("org.gimp.GIMP")],
+        }];
+        config
+    }
+
+    #[test]
+    /// Expects packages for each distribution, Flatpaks & both kinds of file
+    /// in the playbook.
+    fn to_ansible() {
+        let playbook = ansible(&config());
+        assert!(playbook.contains(
+            "    - name: Install core packages on fedora\n      become: true\n      \
+             ansible.builtin.package:\n        state: present\n        name:\n          \
+             - \"fish\"\n      when: ansible_distribution == \"Fedora\"\n"
+        ));
+        assert!(playbook.contains(
+            "          - \"fish-shell\"\n      when: ansible_distribution == \"Archlinux\"\n"
+        ));
+        assert!(playbook.contains("community.general.flatpak_remote:"));
+        assert!(playbook.contains("          - \"org.gimp.GIMP\"\n"));
+        assert!(playbook.contains("dest: \"{{ ansible_env.HOME }}/.bashrc\""));
+        assert!(
+            playbook.contains("- { src: \"/nonexistent/nedots/etc/hosts\", dest: \"/etc/hosts\" }")
+        );
+        assert!(!playbook.contains("wayland"));
+    }
+
+    #[test]
+    /// Expects packages, named for this distribution if it's listed, & user
+    /// files in the module, and everything else left as comments.
+    fn to_home_manager() {
+        let module = home_manager(&config(), &Distro::default());
+        assert!(module.contains(
+            "  # Package names from the `fedora` lists, some may differ in nixpkgs.\n  \
+             home.packages = with pkgs; [\n    fish\n    pkgs.\"gtk+3\"\n  ];"
+        ));
+        let arch = Distro {
+            id: String::from("manjaro"),
+            like: vec![String::from("arch")],
+            ..Default::default()
+        };
+        assert!(home_manager(&config(), &arch).contains("the `arch` lists"));
+        assert!(module.contains(&format!(
+            "    \".bashrc\".source = /nonexistent/nedots{}/.bashrc;",
+            crate::home::dir().display()
+        )));
+        assert!(module.contains("  #   /etc/hosts\n"));
+        assert!(module.contains("  #   org.gimp.GIMP\n"));
+        assert!(module.ends_with("}\n"));
+
+        assert_eq!(nix_pkg("python3"), "python3");
+        assert_eq!(nix_pkg("gtk+3"), "pkgs.\"gtk+3\"");
+        assert_eq!(nix_path("/a b"), "/. + \"/a b\"");
+    }
+}
@@ -0,0 +1,352 @@
+use super::{
+    fs,
+    git::GitOp,
+    op::{Operate, Operation, OperationError},
+    status::{self, FileState, FileStatus},
+};
+use crate::output::TerminalLogger;
+use console::{style, Term};
+use similar::{ChangeTag, TextDiff};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// How much of each difference to render.
+pub(crate) enum DiffMode {
+    /// Full unified diff.
+    Patch,
+
+    /// Count of lines inserted & deleted per file.
+    Stat,
+
+    /// Only the names of files that differ.
+    NameOnly,
+}
+
+/// Renders differences between tracked files & their copies in the
+/// repository.
+pub(crate) struct Diff {
+    pub(crate) parent_op: Operation<TerminalLogger>,
+
+    /// Path of the repository.
+    pub(crate) repo: PathBuf,
+
+    /// Tracked paths on this system.
+    pub(crate) paths: Vec<PathBuf>,
+
+    /// Only render files under these paths, all files when empty.
+    pub(crate) only: Vec<PathBuf>,
+
+    pub(crate) mode: DiffMode,
+
+    /// Diff from the live file to the repository, showing what `update-local`
+    /// would write, instead of what `add-changes` would write.
+    pub(crate) reverse: bool,
+}
+
+impl Diff {
+    pub(crate) fn new(op: Operation<TerminalLogger>) -> Self {
+        Self {
+            parent_op: op,
+            repo: PathBuf::new(),
+            paths: Vec::new(),
+            only: Vec::new(),
+            mode: DiffMode::Patch,
+            reverse: false,
+        }
+    }
+
+    /// Assign `repo`.
+    pub(crate) fn of(mut self, repo: PathBuf) -> Self {
+        self.repo = repo;
+        self
+    }
+
+    /// Compare `paths`, `paths` without a leading `/` are relative to $HOME.
+    pub(crate) fn compare_these(mut self, paths: Vec<PathBuf>) -> Self {
+        self.paths
+            .extend(paths.iter().map(|p| crate::home::dir().join(p)));
+        self
+    }
+
+    /// Only render files under `paths`, relative paths are relative to the
+    /// current directory.
+    pub(crate) fn only(mut self, paths: &[String]) -> Result<Self, OperationError> {
+        let cwd = std::env::current_dir().map_err(fs::CopyError::from)?;
+        self.only.extend(paths.iter().map(|p| cwd.join(p)));
+        Ok(self)
+    }
+
+    /// Assign `mode`.
+    pub(crate) fn mode(mut self, mode: DiffMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Assign `reverse`.
+    pub(crate) fn reverse(mut self, reverse: bool) -> Self {
+        self.reverse = reverse;
+        self
+    }
+
+    /// `FileStatus` of every tracked file that differs & passes `only`.
+    pub(crate) fn files(&self) -> Result<Vec<FileStatus>, OperationError> {
+        // Without a repository every difference is a local one.
+        let mut go = GitOp::new().at_path(&self.repo).ok();
+        let mut statuses = Vec::new();
+        for live in &self.paths {
+            let committed = |repo: &Path| match go.as_mut() {
+                Some(go) => status::committed(go, &self.repo, repo),
+                None => Vec::new(),
+            };
+            for fs in FileStatus::walk(live, &fs::repo_path(&self.repo, live), committed)? {
+                if !matches!(fs.state, FileState::Unchanged | FileState::Missing)
+                    && (self.only.is_empty() || self.only.iter().any(|o| fs.live.starts_with(o)))
+                {
+                    statuses.push(fs);
+                }
+            }
+        }
+
+        Ok(statuses)
+    }
+}
+
+impl Operate for Diff {
+    fn operate(&mut self) -> Result<usize, OperationError> {
+        // `--stat` lines are rendered together, so they line up.
+        let mut rows = Vec::new();
+        for fs in self.files()? {
+            let (old, new) = if self.reverse {
+                (&fs.live, &fs.repo)
+            } else {
+                (&fs.repo, &fs.live)
+            };
+
+            let old_buf = read(old)?;
+            let new_buf = read(new)?;
+            let counts = counts(&old_buf, &new_buf);
+            let report = self
+                .parent_op
+                .report()
+                .path(&fs.live, &fs.state.to_string());
+            report.insertions = counts.map(|(i, _)| i);
+            report.deletions = counts.map(|(_, d)| d);
+
+            let rendered = match self.mode {
+                DiffMode::NameOnly => fs.live.display().to_string(),
+                DiffMode::Stat => {
+                    rows.push((fs.live.display().to_string(), counts));
+                    continue;
+                }
+                DiffMode::Patch => patch(old, new, &old_buf, &new_buf),
+            };
+
+            crate::output::term(rendered.trim_end());
+        }
+
+        if !rows.is_empty() {
+            let width = Term::stdout()
+                .size_checked()
+                .map_or(80, |(_, cols)| cols.into());
+            crate::output::term(stat(&rows, width).trim_end());
+        }
+
+        Ok(0)
+    }
+
+    fn exit_code(&mut self) -> usize {
+        let result = self.operate();
+        self.parent_op.finish(result)
+    }
+}
+
+/// Read `path`, treating a missing file as empty.
+pub(crate) fn read(path: &Path) -> Result<Vec<u8>, fs::CopyError> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+
+    Ok(std::fs::read(path)?)
+}
+
+/// Render a unified diff between `old` & `new`.
+pub(crate) fn patch(old: &Path, new: &Path, old_buf: &[u8], new_buf: &[u8]) -> String {
+    let name = |p: &Path, buf: &[u8]| match p.exists() || !buf.is_empty() {
+        true => p.display().to_string(),
+        false => String::from("/dev/null"),
+    };
+    let (old_name, new_name) = (name(old, old_buf), name(new, new_buf));
+
+    let mut out = format!(
+        "{}\n{}\n",
+        style(format!("--- {}", old_name)).bold(),
+        style(format!("+++ {}", new_name)).bold()
+    );
+
+    let (old_text, new_text) = match (std::str::from_utf8(old_buf), std::str::from_utf8(new_buf)) {
+        (Ok(o), Ok(n)) => (o, n),
+        _ => {
+            out.push_str("Binary files differ\n");
+            return out;
+        }
+    };
+
+    let diff = TextDiff::from_lines(old_text, new_text);
+    for hunk in diff.unified_diff().context_radius(3).iter_hunks() {
+        out.push_str(&format!("{}\n", style(hunk.header()).cyan()));
+        for change in hunk.iter_changes() {
+            let line = format!("{}{}", change.tag(), change.value());
+            let line = match change.tag() {
+                ChangeTag::Delete => style(line).red().to_string(),
+                ChangeTag::Insert => style(line).green().to_string(),
+                ChangeTag::Equal => line,
+            };
+            out.push_str(&line);
+            if change.missing_newline() {
+                out.push_str("\n\\ No newline at end of file\n");
+            }
+        }
+    }
+
+    out
+}
+
+/// Count of lines `(inserted, deleted)` going from `old_buf` to `new_buf`,
+/// `None` for binary files.
+pub(crate) fn counts(old_buf: &[u8], new_buf: &[u8]) -> Option<(usize, usize)> {
+    let (old_text, new_text) = match (std::str::from_utf8(old_buf), std::str::from_utf8(new_buf)) {
+        (Ok(o), Ok(n)) => (o, n),
+        _ => return None,
+    };
+
+    let (mut insertions, mut deletions) = (0, 0);
+    for change in TextDiff::from_lines(old_text, new_text).iter_all_changes() {
+        match change.tag() {
+            ChangeTag::Insert => insertions += 1,
+            ChangeTag::Delete => deletions += 1,
+            ChangeTag::Equal => {}
+        }
+    }
+
+    Some((insertions, deletions))
+}
+
+/// Render `--stat` lines for `rows`, each a path with its `(inserted,
+/// deleted)` counts, `None` for binary files. Like `git diff --stat`, names &
+/// counts line up, and bars are scaled down when the largest change wouldn't
+/// fit in `width` columns.
+pub(crate) fn stat(rows: &[(String, Option<(usize, usize)>)], width: usize) -> String {
+    let name_width = rows
+        .iter()
+        .map(|(n, _)| n.chars().count())
+        .max()
+        .unwrap_or(0);
+    let max = rows
+        .iter()
+        .filter_map(|(_, c)| c.map(|(i, d)| i + d))
+        .max()
+        .unwrap_or(0);
+    let count_width = match rows.iter().any(|(_, c)| c.is_none()) {
+        true => max.to_string().len().max("Bin".len()),
+        false => max.to_string().len(),
+    };
+    // Room left after `name | count `, but always some.
+    let graph_width = width.saturating_sub(name_width + count_width + 4).max(10);
+    let scale = |n: usize| match (n, max <= graph_width) {
+        (0, _) => 0,
+        (n, true) => n,
+        (n, false) => 1 + n * (graph_width - 1) / max,
+    };
+
+    let mut out = String::new();
+    for (name, counts) in rows {
+        let (insertions, deletions) = match counts {
+            Some(c) => *c,
+            None => {
+                out.push_str(&format!(
+                    "{:<nw$} | {:>cw$}\n",
+                    name,
+                    "Bin",
+                    nw = name_width,
+                    cw = count_width
+                ));
+                continue;
+            }
+        };
+
+        // The same way git splits a scaled bar, so neither side vanishes.
+        let mut total = scale(insertions + deletions);
+        if total < 2 && insertions > 0 && deletions > 0 {
+            total = 2;
+        }
+        let (plus, minus) = match insertions < deletions {
+            true => (scale(insertions), total - scale(insertions)),
+            false => (total - scale(deletions), scale(deletions)),
+        };
+
+        let line = format!(
+            "{:<nw$} | {:>cw$} {}{}",
+            name,
+            insertions + deletions,
+            style("+".repeat(plus)).green(),
+            style("-".repeat(minus)).red(),
+            nw = name_width,
+            cw = count_width
+        );
+        out.push_str(line.trim_end());
+        out.push('\n');
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{counts, patch, stat};
+    use std::path::Path;
+
+    #[test]
+    /// Expects a single hunk with one line removed & one added.
+    fn unified() {
+        let rendered = patch(
+            Path::new("a"),
+            Path::new("b"),
+            b"one\ntwo\nthree\n",
+            b"one\n2\nthree\n",
+        );
+        assert_eq!(
+            rendered,
+            "--- a\n+++ b\n@@ -1,3 +1,3 @@\n one\n-two\n+2\n three\n"
+        );
+    }
+
+    #[test]
+    /// Expects files that aren't UTF-8 to be reported as binary, not diffed.
+    fn binary() {
+        let rendered = patch(Path::new("a"), Path::new("b"), &[0xff, 0xfe], b"");
+        assert!(rendered.ends_with("Binary files differ\n"));
+    }
+
+    #[test]
+    /// Expects a line per file with names & counts aligned, and bars scaled
+    /// to fit the width like `git diff --stat`, keeping both sides of a small
+    /// change visible.
+    fn stat_counts() {
+        let (i, d) = counts(b"one\ntwo\n", b"one\n2\n3\n").unwrap();
+        assert_eq!(
+            stat(&[(String::from("file"), Some((i, d)))], 80),
+            "file | 3 ++-\n"
+        );
+
+        let rows = [
+            (String::from("a"), Some((100, 0))),
+            (String::from("long/name"), Some((1, 1))),
+            (String::from("bin"), None),
+        ];
+        let rendered = stat(&rows, 40);
+        let lines: Vec<&str> = rendered.lines().collect();
+        assert_eq!(lines[0], format!("a         | 100 {}", "+".repeat(24)));
+        assert_eq!(lines[1], "long/name |   2 +-");
+        assert_eq!(lines[2], "bin       | Bin");
+    }
+}
@@ -0,0 +1,401 @@
+use super::{
+    op::{Operate, Operation, OperationError},
+    pkg::Distro,
+};
+use crate::{
+    config::{Config, ConfigError},
+    output::TerminalLogger,
+};
+use console::style;
+use git2::Repository;
+use nix::unistd::geteuid;
+use serde::Serialize;
+use serde_json::json;
+use std::{
+    ffi::OsStr,
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+/// Outcome of a single `Check`.
+pub(crate) enum CheckState {
+    Pass,
+
+    /// Something nedots may need is missing, but nothing is broken yet.
+    Warn,
+
+    /// nedots will fail until this is fixed.
+    Fail,
+}
+
+impl Display for CheckState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.pad(match self {
+            CheckState::Pass => "pass",
+            CheckState::Warn => "warn",
+            CheckState::Fail => "fail",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+/// A single line of the `doctor` checklist.
+pub(crate) struct Check {
+    pub(crate) name: String,
+
+    pub(crate) state: CheckState,
+
+    /// How to fix a `Warn` or `Fail`.
+    pub(crate) hint: Option<String>,
+}
+
+impl Check {
+    fn pass(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            state: CheckState::Pass,
+            hint: None,
+        }
+    }
+
+    fn warn(name: &str, hint: String) -> Self {
+        Self {
+            name: name.to_string(),
+            state: CheckState::Warn,
+            hint: Some(hint),
+        }
+    }
+
+    fn fail(name: &str, hint: String) -> Self {
+        Self {
+            name: name.to_string(),
+            state: CheckState::Fail,
+            hint: Some(hint),
+        }
+    }
+}
+
+/// Checks everything nedots depends on & reports a checklist.
+pub(crate) struct Doctor<'remote> {
+    pub(crate) parent_op: Operation<TerminalLogger>,
+
+    /// Path of `nedots.json`.
+    pub(crate) config_path: PathBuf,
+
+    /// Repository path passed as an argument, overrides `Config::path`.
+    pub(crate) path: Option<PathBuf>,
+
+    /// The remote that's expected to be configured.
+    pub(crate) remote: &'remote str,
+}
+
+impl<'remote> Doctor<'remote> {
+    pub(crate) fn new(op: Operation<TerminalLogger>) -> Self {
+        Self {
+            parent_op: op,
+            config_path: PathBuf::from(crate::config::CONFIG_FILE),
+            path: None,
+            remote: "origin",
+        }
+    }
+
+    /// Assign `config_path`.
+    pub(crate) fn config_at(mut self, path: &Path) -> Self {
+        self.config_path = path.to_path_buf();
+        self
+    }
+
+    /// Assign `path`.
+    pub(crate) fn of(mut self, path: Option<PathBuf>) -> Self {
+        self.path = path;
+        self
+    }
+
+    /// Assign `remote`.
+    pub(crate) fn with_remote(mut self, remote: &'remote str) -> Self {
+        self.remote = remote;
+        self
+    }
+
+    /// Run every check, stopping early when a failure means later checks
+    /// can't run.
+    pub(crate) fn checks(&self) -> Vec<Check> {
+        let mut checks = Vec::new();
+
+        let config = match Config::read(Some(&self.config_path)) {
+            Ok(c) => c,
+            Err(e) => {
+                checks.push(Check::fail(
+                    &format!("{} parses", self.config_path.display()),
+                    format!(
+                        "{} Compare it with `nedots.example.json`, or run `nedots init`.",
+                        e
+                    ),
+                ));
+                return checks;
+            }
+        };
+        checks.push(Check::pass(&format!(
+            "{} parses",
+            self.config_path.display()
+        )));
+
+        checks.append(&mut self.repo_checks(&config));
+        checks.append(&mut tool_checks(
+            &config,
+            &Distro::current().unwrap_or_default(),
+            &std::env::var_os("PATH").unwrap_or_default(),
+        ));
+
+        let mut bad = Vec::new();
+        if let Err(ConfigError::BadPaths { mut paths }) = config.clone().resolve_root_paths() {
+            bad.append(&mut paths);
+        }
+        if let Err(ConfigError::BadPaths { mut paths }) = config.resolve_user_paths() {
+            bad.append(&mut paths);
+        }
+        checks.push(match bad.is_empty() {
+            true => Check::pass("tracked paths resolve"),
+            false => Check::fail(
+                "tracked paths resolve",
+                format!(
+                    "Create these, or stop tracking them with `nedots untrack`: {}",
+                    bad.iter()
+                        .map(|p| p.display().to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+            ),
+        });
+
+        checks
+    }
+
+    /// Checks on the repository: it exists, has `remote` & can sign commits.
+    fn repo_checks(&self, config: &Config) -> Vec<Check> {
+        let mut checks = Vec::new();
+
+        let path = match &self.path {
+            Some(p) => p.to_path_buf(),
+            None => match config.clone().resolve_path() {
+                Ok(c) => c.path,
+                Err(e) => {
+                    checks.push(Check::fail(
+                        "repository path resolves",
+                        format!("{} Set `path` in nedots.json, or pass --path.", e),
+                    ));
+                    return checks;
+                }
+            },
+        };
+
+        let repo = match Repository::open(&path) {
+            Ok(r) => r,
+            Err(_) => {
+                checks.push(Check::fail(
+                    "repository exists",
+                    format!(
+                        "{} is not a git repository, run `nedots init {}`.",
+                        path.display(),
+                        path.display()
+                    ),
+                ));
+                return checks;
+            }
+        };
+        checks.push(Check::pass("repository exists"));
+
+        checks.push(match repo.find_remote(self.remote) {
+            Ok(_) => Check::pass(&format!("remote `{}` configured", self.remote)),
+            Err(_) => Check::fail(
+                &format!("remote `{}` configured", self.remote),
+                format!(
+                    "Run `git -C {} remote add {} <url>`.",
+                    path.display(),
+                    self.remote
+                ),
+            ),
+        });
+
+        checks.push(match repo.signature() {
+            Ok(_) => Check::pass("git signature configured"),
+            Err(_) => Check::fail(
+                "git signature configured",
+                String::from(
+                    "Run `git config --global user.name <name>` & `git config --global user.email <email>`.",
+                ),
+            ),
+        });
+
+        checks
+    }
+}
+
+impl Operate for Doctor<'_> {
+    fn operate(&mut self) -> Result<usize, OperationError> {
+        let checks = self.checks();
+        for check in &checks {
+            let state = match check.state {
+                CheckState::Pass => style(format!("{:>4}", check.state)).green(),
+                CheckState::Warn => style(format!("{:>4}", check.state)).yellow(),
+                CheckState::Fail => style(format!("{:>4}", check.state)).red(),
+            };
+            crate::output::term(&format!("{}  {}", state, check.name));
+            if let Some(hint) = &check.hint {
+                crate::output::term(&format!("      {}", hint));
+            }
+        }
+        self.parent_op
+            .report()
+            .details
+            .insert("checks", json!(checks));
+
+        match checks.iter().any(|c| c.state == CheckState::Fail) {
+            true => Ok(1),
+            false => Ok(0),
+        }
+    }
+
+    fn exit_code(&mut self) -> usize {
+        let result = self.operate();
+        self.parent_op.finish(result)
+    }
+}
+
+/// Checks for the binaries nedots shells out to on `distro`, found via
+/// `path_var`. Missing binaries are only failures when `config` needs them.
+fn tool_checks(config: &Config, distro: &Distro, path_var: &OsStr) -> Vec<Check> {
+    let pkgs = &config.pkgs;
+    let groups: Vec<_> = pkgs.groups.values().map(|g| &g.pkgs).collect();
+    let needs_sudo = !config.root.is_empty() && !geteuid().is_root();
+
+    let mut tools = vec![(
+        "sudo",
+        needs_sudo,
+        String::from("Install `sudo`, or run as root, to copy `root` paths."),
+    )];
+    let mut checks = Vec::new();
+    match distro.manager() {
+        Ok(manager) => tools.push((
+            manager.program(),
+            groups.iter().any(|g| !g.for_ids(distro.ids()).is_empty()),
+            format!(
+                "Install `{}` to install the packages listed for {}.",
+                manager.program(),
+                distro.id
+            ),
+        )),
+        Err(e) if groups.iter().any(|g| !g.is_empty()) => checks.push(Check::warn(
+            "distribution supported",
+            format!(
+                "{} `install-packages` supports Fedora, openSUSE, Arch, Debian & Ubuntu.",
+                e
+            ),
+        )),
+        Err(_) => {}
+    }
+    tools.push((
+        "flatpak",
+        !pkgs.flatpaks.is_empty(),
+        String::from("Install `flatpak` to install `packages.flatpak`."),
+    ));
+
+    checks.extend(tools.into_iter().map(|(bin, needed, hint)| {
+        let name = format!("`{}` available", bin);
+        match (on_path(bin, path_var), needed) {
+            (true, _) => Check::pass(&name),
+            (false, true) => Check::fail(&name, hint),
+            (false, false) => Check::warn(&name, hint),
+        }
+    }));
+
+    checks
+}
+
+/// Whether `bin` is an executable file in one of the directories of
+/// `path_var`, formatted like $PATH.
+pub(crate) fn on_path(bin: &str, path_var: &OsStr) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    std::env::split_paths(path_var).any(|dir| {
+        dir.join(bin)
+            .metadata()
+            .map(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+            .unwrap_or(false)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{on_path, tool_checks, CheckState, Distro, Doctor};
+    use crate::{
+        config::{Config, PackageGroup},
+        ops::op::Operation,
+        _TESTS_DIR,
+    };
+    use std::{ffi::OsStr, os::unix::fs::PermissionsExt, path::Path};
+
+    #[test]
+    /// Expects only executable files to be found.
+    fn find_on_path() {
+        let dir = Path::new(_TESTS_DIR).join("doctor");
+        std::fs::create_dir_all(&dir).expect("Failed to make doctor dir!");
+        std::fs::write(dir.join("dnf"), "").expect("Failed to write dnf!");
+        std::fs::write(dir.join("flatpak"), "").expect("Failed to write flatpak!");
+        std::fs::set_permissions(dir.join("dnf"), std::fs::Permissions::from_mode(0o755))
+            .expect("Failed to chmod dnf!");
+
+        assert!(on_path("dnf", dir.as_os_str()));
+        assert!(!on_path("flatpak", dir.as_os_str()));
+        assert!(!on_path("sudo", dir.as_os_str()));
+
+        std::fs::remove_dir_all(&dir).expect("Failed to remove doctor dir!");
+    }
+
+    #[test]
+    /// Expects missing binaries to fail only when packages need them.
+    fn tools() {
+        let mut config = Config::default();
+        let mut core = PackageGroup::default();
+        core.pkgs
+            .0
+            .insert(String::from("fedora"), vec![String::from("fish")]);
+        config.pkgs.groups.insert(String::from("core"), core);
+        let fedora = Distro {
+            id: String::from("fedora"),
+            ..Default::default()
+        };
+
+        let checks = tool_checks(&config, &fedora, OsStr::new(""));
+        let state = |name: &str| {
+            checks
+                .iter()
+                .find(|c| c.name.contains(name))
+                .map(|c| c.state)
+        };
+        assert_eq!(state("dnf"), Some(CheckState::Fail));
+        assert_eq!(state("flatpak"), Some(CheckState::Warn));
+
+        // Nothing's listed for Arch, so pacman isn't needed.
+        let arch = Distro {
+            id: String::from("arch"),
+            ..Default::default()
+        };
+        let checks = tool_checks(&config, &arch, OsStr::new(""));
+        assert!(checks
+            .iter()
+            .any(|c| c.name.contains("pacman") && c.state == CheckState::Warn));
+    }
+
+    #[test]
+    /// Expects a missing `nedots.json` to stop the checklist early.
+    fn missing_config() {
+        let checks = Doctor::new(Operation::new())
+            .config_at(&Path::new(_TESTS_DIR).join("not_here.json"))
+            .checks();
+        assert_eq!(checks.len(), 1);
+        assert_eq!(checks[0].state, CheckState::Fail);
+    }
+}
@@ -0,0 +1,275 @@
+use super::{
+    diff,
+    fs::{self, CopyError, CopyOp},
+    git::GitOp,
+    op::{Operate, Operation, OperationError},
+    plan::{self, Step},
+};
+use crate::{config::ConfigError, output::TerminalLogger};
+use dialoguer::{theme::ColorfulTheme, Confirm};
+use nix::unistd::geteuid;
+use std::{path::PathBuf, process::Command};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+/// Errors thrown while editing a tracked file.
+pub(crate) enum EditError {
+    #[error("`{cmd}` exited unsuccessfully, nothing was committed.")]
+    /// The editor failed, or was told to abort.
+    Editor { cmd: String },
+
+    #[error(transparent)]
+    /// A wrapper around IO errors.
+    IoError(#[from] std::io::Error),
+}
+
+/// The user's editor, from `$VISUAL` or `$EDITOR`, falling back to `vi`.
+fn editor() -> String {
+    ["VISUAL", "EDITOR"]
+        .iter()
+        .filter_map(|v| std::env::var(v).ok())
+        .find(|e| !e.trim().is_empty())
+        .unwrap_or_else(|| String::from("vi"))
+}
+
+/// Opens a tracked file in an editor, then commits the change to just that
+/// file.
+pub(crate) struct Edit<'remote> {
+    pub(crate) parent_op: Operation<TerminalLogger>,
+
+    /// Path of the repository.
+    pub(crate) repo: PathBuf,
+
+    /// Tracked paths on this system.
+    pub(crate) tracked: Vec<PathBuf>,
+
+    /// Live path to edit.
+    pub(crate) path: PathBuf,
+
+    /// Push to this remote.
+    pub(crate) remote: &'remote str,
+
+    /// Push changes to remote after committing.
+    pub(crate) push: bool,
+
+    /// Commit without asking.
+    pub(crate) assume_yes: bool,
+}
+
+impl<'remote> Edit<'remote> {
+    pub(crate) fn new(op: Operation<TerminalLogger>) -> Self {
+        Self {
+            parent_op: op,
+            repo: PathBuf::new(),
+            tracked: Vec::new(),
+            path: PathBuf::new(),
+            remote: "origin",
+            push: false,
+            assume_yes: false,
+        }
+    }
+
+    /// Assign `repo`.
+    pub(crate) fn of(mut self, repo: PathBuf) -> Self {
+        self.repo = repo;
+        self
+    }
+
+    /// Accept `paths` as tracked, `paths` without a leading `/` are relative
+    /// to $HOME.
+    pub(crate) fn tracking(mut self, paths: Vec<PathBuf>) -> Self {
+        self.tracked
+            .extend(paths.iter().map(|p| crate::home::dir().join(p)));
+        self
+    }
+
+    /// Assign `path`, relative paths are relative to the current directory.
+    pub(crate) fn at(mut self, path: &str) -> Result<Self, OperationError> {
+        self.path = std::env::current_dir().map_err(CopyError::from)?.join(path);
+        Ok(self)
+    }
+
+    /// Assign `remote`.
+    pub(crate) fn with_remote(mut self, remote: &'remote str) -> Self {
+        self.remote = remote;
+        self
+    }
+
+    /// Assign `push`.
+    pub(crate) fn push(mut self, push: bool) -> Self {
+        self.push = push;
+        self
+    }
+
+    /// Assign `assume_yes`.
+    pub(crate) fn assume_yes(mut self, assume_yes: bool) -> Self {
+        self.assume_yes = assume_yes;
+        self
+    }
+
+    /// Open `path` in the user's editor, via `sudoedit` for files outside of
+    /// $HOME when we aren't root.
+    fn open(&self) -> Result<(), EditError> {
+        let editor = editor();
+        let mut cmd = match self.path.starts_with(crate::home::dir()) || geteuid().is_root() {
+            true => {
+                let mut words = editor.split_whitespace();
+                let mut cmd = Command::new(words.next().unwrap_or("vi"));
+                cmd.args(words);
+                cmd
+            }
+            false => {
+                let mut cmd = Command::new("sudoedit");
+                cmd.env("SUDO_EDITOR", &editor);
+                cmd
+            }
+        };
+
+        if !cmd.arg(&self.path).status()?.success() {
+            return Err(EditError::Editor { cmd: editor });
+        }
+
+        Ok(())
+    }
+}
+
+impl Operate for Edit<'_> {
+    fn operate(&mut self) -> Result<usize, OperationError> {
+        if !self.tracked.iter().any(|t| self.path.starts_with(t)) {
+            return Err(ConfigError::NotTracked {
+                path: self.path.to_path_buf(),
+            }
+            .into());
+        }
+
+        // The editor can change the live file, so a dry run plans from what's
+        // already there instead of opening it.
+        match self.parent_op.is_dry_run() {
+            true => crate::output::term(&format!(
+                "Would open {} in `{}`",
+                self.path.display(),
+                editor()
+            )),
+            false => self.open()?,
+        }
+
+        let in_repo = fs::repo_path(&self.repo, &self.path);
+        let old = match in_repo.exists() {
+            true => std::fs::read(&in_repo).map_err(CopyError::from)?,
+            false => Vec::new(),
+        };
+        let new = std::fs::read(&self.path).map_err(CopyError::from)?;
+        if old == new {
+            crate::output::term(&format!("No changes to {}", self.path.display()));
+            self.parent_op.report().path(&self.path, "unchanged");
+            return Ok(0);
+        }
+        crate::output::term(diff::patch(&in_repo, &self.path, &old, &new).trim_end());
+
+        let rel = in_repo
+            .strip_prefix(&self.repo)
+            .unwrap_or(&in_repo)
+            .to_path_buf();
+        let mut go = GitOp::new().at_path(&self.repo)?.with_remote(self.remote);
+        let branch = go.branch()?;
+        if self.parent_op.is_dry_run() {
+            self.parent_op.record([
+                Step::Copy {
+                    from: self.path.to_path_buf(),
+                    to: in_repo.to_path_buf(),
+                    sudo: false,
+                    hash: plan::hash(&self.path)?,
+                },
+                Step::Commit {
+                    repo: self.repo.to_path_buf(),
+                    branch: branch.to_string(),
+                    paths: vec![rel],
+                },
+            ]);
+            if self.push {
+                self.parent_op.record([Step::Push {
+                    repo: self.repo.to_path_buf(),
+                    remote: self.remote.to_string(),
+                    branch,
+                }]);
+            }
+            return Ok(0);
+        }
+
+        if !self.assume_yes
+            && !Confirm::with_theme(&ColorfulTheme::default())
+                .with_prompt("Commit this change?")
+                .default(true)
+                .interact()
+                .map_err(EditError::from)?
+        {
+            crate::output::term("Not committed, run `nedots add-changes` to commit it later.");
+            self.parent_op.report().path(&self.path, "edited");
+            return Ok(0);
+        }
+
+        CopyOp::new().from(&self.path).to(&in_repo).copy()?;
+        let (go, tree_id) = go.add_these(&[rel])?;
+        let (mut go, oid) = go.commit(tree_id)?;
+        crate::output::term(&format!("Committed {}", oid));
+        self.parent_op.report().path(&self.path, "committed");
+        self.parent_op.report().commits.push(oid.to_string());
+
+        if self.push {
+            go.push(self.remote, &branch)?;
+            crate::output::term(&format!("Pushed to {}/{}", self.remote, branch));
+        }
+
+        Ok(0)
+    }
+
+    fn exit_code(&mut self) -> usize {
+        let result = self.operate();
+        self.parent_op.finish(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{editor, Edit};
+    use crate::{
+        config::ConfigError,
+        ops::op::{Operate, Operation, OperationError},
+        _TESTS_DIR,
+    };
+    use std::path::Path;
+
+    #[test]
+    /// Expects `$VISUAL` to win over `$EDITOR`, and blank values to be
+    /// skipped.
+    fn pick_editor() {
+        std::env::set_var("VISUAL", " ");
+        std::env::set_var("EDITOR", "nano");
+        assert_eq!(editor(), "nano");
+
+        std::env::set_var("VISUAL", "code -w");
+        assert_eq!(editor(), "code -w");
+
+        std::env::remove_var("VISUAL");
+        std::env::remove_var("EDITOR");
+    }
+
+    #[test]
+    /// Expects a path outside of the tracked ones to be refused before the
+    /// editor is opened.
+    fn untracked() {
+        let tracked = Path::new(_TESTS_DIR).join("edit");
+        let mut edit = Edit::new(Operation::new())
+            .tracking(vec![tracked])
+            .at("tests/edit_untracked")
+            .expect("Failed to assign path!");
+
+        match edit.operate() {
+            Err(OperationError::Config(ConfigError::NotTracked { path })) => {
+                assert!(path.ends_with("tests/edit_untracked"))
+            }
+            Err(e) => panic!("{}", e),
+            Ok(_) => panic!("Edited an untracked path!"),
+        }
+    }
+}
@@ -0,0 +1,122 @@
+use glob::{MatchOptions, Pattern};
+use std::path::{Component, Path};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+/// Errors thrown while building a `Filter`.
+pub(crate) enum FilterError {
+    #[error("Bad pattern `{pattern}`: {msg}")]
+    /// Not a valid glob.
+    BadPattern { pattern: String, msg: String },
+}
+
+/// `*` & `?` don't cross `/`, only `**` does.
+const OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+/// Chooses which tracked files to copy, by glob or path prefix.
+///
+/// Patterns are matched against paths relative to $HOME, or absolute paths
+/// for anything outside of it. A pattern without a `/`, e.g. `*.fish` or
+/// `polybar`, matches any single component, and a pattern that matches a
+/// directory matches everything in it.
+#[derive(Debug, Default)]
+pub(crate) struct Filter {
+    /// Keep only paths matching one of these, every path when empty.
+    only: Vec<Pattern>,
+
+    /// Drop paths matching any of these.
+    except: Vec<Pattern>,
+}
+
+impl Filter {
+    pub(crate) fn new(only: &[String], except: &[String]) -> Result<Self, FilterError> {
+        Ok(Self {
+            only: patterns(only)?,
+            except: patterns(except)?,
+        })
+    }
+
+    /// Filters nothing.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.only.is_empty() && self.except.is_empty()
+    }
+
+    /// `live` passes the filter.
+    pub(crate) fn allows(&self, live: &Path) -> bool {
+        let path = live.strip_prefix(crate::home::dir()).unwrap_or(live);
+        let matches = |p: &Pattern| matches(p, path);
+
+        (self.only.is_empty() || self.only.iter().any(matches)) && !self.except.iter().any(matches)
+    }
+}
+
+/// Compile `raw` patterns, `~/` is dropped since paths under $HOME are
+/// matched relative to it.
+fn patterns(raw: &[String]) -> Result<Vec<Pattern>, FilterError> {
+    raw.iter()
+        .map(|r| {
+            let trimmed = r.strip_prefix("~/").unwrap_or(r).trim_end_matches('/');
+            Pattern::new(trimmed).map_err(|e| FilterError::BadPattern {
+                pattern: r.to_string(),
+                msg: e.msg.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// `pattern` matches `path`, a component of it, or a directory it's in.
+fn matches(pattern: &Pattern, path: &Path) -> bool {
+    if !pattern.as_str().contains('/') {
+        return path.components().any(|c| match c {
+            Component::Normal(name) => pattern.matches_with(&name.to_string_lossy(), OPTIONS),
+            _ => false,
+        });
+    }
+
+    path.ancestors()
+        .any(|a| pattern.matches_path_with(a, OPTIONS))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Filter;
+    use std::path::Path;
+
+    fn filter(only: &[&str], except: &[&str]) -> Filter {
+        let s = |v: &[&str]| v.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+        Filter::new(&s(only), &s(except)).expect("Failed to build filter!")
+    }
+
+    #[test]
+    /// Expects globs, prefixes & bare names to match relative to $HOME, and
+    /// `except` to win over `only`.
+    fn allows() {
+        let home = crate::home::dir();
+        let polybar = home.join(".config/polybar/config.ini");
+        let fish = home.join(".config/fish/functions/ls.fish");
+
+        assert!(filter(&[], &[]).is_empty());
+        assert!(filter(&[], &[]).allows(&polybar));
+
+        let only = filter(&[".config/polybar/**"], &[]);
+        assert!(only.allows(&polybar));
+        assert!(!only.allows(&fish));
+
+        assert!(filter(&["~/.config/polybar"], &[]).allows(&polybar));
+        assert!(filter(&["*.fish"], &[]).allows(&fish));
+        assert!(!filter(&[".config/*.fish"], &[]).allows(&fish));
+        assert!(filter(&[".config/**/*.fish"], &[]).allows(&fish));
+        assert!(filter(&["fish"], &[]).allows(&fish));
+        assert!(filter(&["/etc/**"], &[]).allows(Path::new("/etc/hosts")));
+
+        let except = filter(&[".config"], &["functions"]);
+        assert!(except.allows(&polybar));
+        assert!(!except.allows(&fish));
+
+        assert!(Filter::new(&["[".to_string()], &[]).is_err());
+    }
+}
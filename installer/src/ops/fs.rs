@@ -1,4 +1,12 @@
-use std::path::{Path, PathBuf};
+use super::{
+    backup::{self, BackupError},
+    plan::{self, Step},
+};
+use nix::unistd::geteuid;
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+};
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -16,11 +24,27 @@ pub(crate) enum CopyError {
     /// Path was probably a '..' or something other than a valid file name.
     InvalidFileName { path: PathBuf },
 
+    #[error("`sudo` failed to copy {path:?}")]
+    /// Copying with elevated privileges failed, or the password prompt was
+    /// declined.
+    Sudo { path: PathBuf },
+
+    #[error(transparent)]
+    /// Failed to back up a file before overwriting it.
+    Backup(#[from] BackupError),
+
     #[error(transparent)]
     /// A wrapper around IO errors.
     IoError(#[from] std::io::Error),
 }
 
+/// Map a live path, e.g. `/home/me/.bashrc`, to its location inside the
+/// repository at `repo`, e.g. `{repo}/home/me/.bashrc`. Every operation that
+/// moves files between the system & the repository goes through this.
+pub(crate) fn repo_path(repo: &Path, live: &Path) -> PathBuf {
+    repo.join(live.strip_prefix("/").unwrap_or(live))
+}
+
 #[derive(Debug)]
 /// Copies a single file.
 pub(crate) struct CopyOp {
@@ -29,6 +53,13 @@ pub(crate) struct CopyOp {
 
     /// Copy `from` file to this destination.
     pub(crate) to: Option<PathBuf>,
+
+    /// Copy via `sudo`, for destinations owned by root.
+    pub(crate) sudo: bool,
+
+    /// Save anything a `copy` would overwrite into this backup run
+    /// directory first.
+    pub(crate) backup: Option<PathBuf>,
 }
 
 impl CopyOp {
@@ -37,6 +68,8 @@ impl CopyOp {
         Self {
             from: None,
             to: None,
+            sudo: false,
+            backup: None,
         }
     }
 
@@ -52,12 +85,25 @@ impl CopyOp {
         self
     }
 
-    /// Do the copy.
-    pub(crate) fn copy(&self) -> Result<(), CopyError> {
+    /// Copy with `sudo` when not already running as root.
+    pub(crate) fn with_sudo(mut self, sudo: bool) -> Self {
+        self.sudo = sudo;
+        self
+    }
+
+    /// Back up destinations into run directory `dir` before overwriting them.
+    pub(crate) fn backup_to(mut self, dir: &Path) -> Self {
+        self.backup = Some(dir.to_path_buf());
+        self
+    }
+
+    /// Borrow `from` & `to`, checking that both are present and are valid
+    /// file names.
+    fn paths(&self) -> Result<(&Path, &Path), CopyError> {
         let from = match &self.from {
             Some(f) => {
                 // Check the `from` path is a file and not '..' or something odd.
-                if let None = f.file_name() {
+                if f.file_name().is_none() {
                     return Err(CopyError::InvalidFileName { path: f.to_owned() });
                 }
 
@@ -66,34 +112,114 @@ impl CopyOp {
             None => return Err(CopyError::NoFromPath),
         };
 
-        let mut to = match &self.to {
+        let to = match &self.to {
             Some(f) => {
-                if let None = f.file_name() {
+                if f.file_name().is_none() {
                     return Err(CopyError::InvalidFileName { path: f.to_owned() });
                 }
 
-                f.to_owned()
+                f
             }
             None => return Err(CopyError::NoToPath),
         };
 
-        // When copying from a directory, walk the contents and perform a copy
-        // op for each. I pray for you if you've asked me to copy a shit load
-        // of subdirectories, I am not multithreaded and don't intend to be.
+        Ok((from, to))
+    }
+
+    /// Every pair of files this `CopyOp` would copy, `(from, to)`.
+    ///
+    /// When copying from a directory, walk the contents and pair each entry
+    /// with the same name under `to`. I pray for you if you've asked me to
+    /// copy a shit load of subdirectories, I am not multithreaded and don't
+    /// intend to be.
+    pub(crate) fn pairs(&self) -> Result<Vec<(PathBuf, PathBuf)>, CopyError> {
+        let (from, to) = self.paths()?;
+
+        let mut pairs = Vec::new();
         if from.is_dir() {
             for e in std::fs::read_dir(from)? {
-                let cop = CopyOp::new().from(&e?.path()).to(&to);
-                if let Err(e) = cop.copy() {
-                    return Err(e);
-                }
+                let e = e?;
+                let cop = CopyOp::new().from(&e.path()).to(&to.join(e.file_name()));
+                pairs.append(&mut cop.pairs()?);
             }
+        } else if to.is_dir() {
+            pairs.push((from.to_path_buf(), to.join(from.file_name().unwrap())));
         } else {
-            if to.is_dir() {
-                to = to.join(from.file_name().unwrap());
+            pairs.push((from.to_path_buf(), to.to_path_buf()));
+        }
+
+        Ok(pairs)
+    }
+
+    /// Split into a `CopyOp` per file, keeping the `(from, to)` pairs `keep`
+    /// returns true for.
+    pub(crate) fn split(
+        &self,
+        keep: impl Fn(&Path, &Path) -> bool,
+    ) -> Result<Vec<CopyOp>, CopyError> {
+        Ok(self
+            .pairs()?
+            .into_iter()
+            .filter(|(from, to)| keep(from, to))
+            .map(|(from, to)| CopyOp {
+                from: Some(from),
+                to: Some(to),
+                sudo: self.sudo,
+                backup: self.backup.clone(),
+            })
+            .collect())
+    }
+
+    /// Destination files that already exist & differ from their source, in
+    /// other words, the files a `copy` would overwrite with new content. A
+    /// source that doesn't exist has nothing to overwrite with.
+    pub(crate) fn would_overwrite(&self) -> Result<Vec<PathBuf>, CopyError> {
+        let mut paths = Vec::new();
+        for (from, to) in self.pairs()? {
+            if from.exists() && to.exists() && differs(&from, &to)? {
+                paths.push(to);
             }
+        }
 
-            if let Err(e) = std::fs::copy(from.canonicalize()?, to) {
-                return Err(CopyError::IoError(e));
+        Ok(paths)
+    }
+
+    /// The `Step`s a `copy` would take, skipping files that are already
+    /// identical.
+    pub(crate) fn plan(&self) -> Result<Vec<Step>, CopyError> {
+        let mut steps = Vec::new();
+        for (from, to) in self.pairs()? {
+            if !to.exists() || differs(&from, &to)? {
+                steps.push(Step::Copy {
+                    hash: plan::hash(&from)?,
+                    from,
+                    to,
+                    sudo: self.sudo,
+                });
+            }
+        }
+
+        Ok(steps)
+    }
+
+    /// Do the copy.
+    pub(crate) fn copy(&self) -> Result<(), CopyError> {
+        for (from, to) in self.pairs()? {
+            if let Some(dir) = &self.backup {
+                if !to.exists() || differs(&from, &to)? {
+                    backup::save(dir, &to, self.sudo)?;
+                }
+            }
+
+            if self.sudo && !geteuid().is_root() {
+                sudo_copy(&from, &to)?;
+            } else {
+                if let Some(parent) = to.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+
+                std::fs::copy(from.canonicalize()?, &to)?;
+                crate::home::give(&to)?;
             }
         }
 
@@ -101,6 +227,61 @@ impl CopyOp {
     }
 }
 
+/// Compare the contents of two files.
+pub(crate) fn differs(a: &Path, b: &Path) -> Result<bool, std::io::Error> {
+    Ok(std::fs::read(a)? != std::fs::read(b)?)
+}
+
+/// Every file under `path`, relative to `path`. A file yields a single empty
+/// path, and a path that doesn't exist yields nothing.
+pub(crate) fn files(path: &Path) -> Result<Vec<PathBuf>, std::io::Error> {
+    let mut files = Vec::new();
+    if path.is_dir() {
+        for e in std::fs::read_dir(path)? {
+            let name = e?.file_name();
+            for f in self::files(&path.join(&name))? {
+                files.push(join(Path::new(&name), &f));
+            }
+        }
+    } else if path.exists() {
+        files.push(PathBuf::new());
+    }
+
+    Ok(files)
+}
+
+/// Join `rel` onto `path`, without leaving a trailing `/` when `rel` is
+/// empty.
+pub(crate) fn join(path: &Path, rel: &Path) -> PathBuf {
+    if rel.as_os_str().is_empty() {
+        return path.to_path_buf();
+    }
+
+    path.join(rel)
+}
+
+/// Copy a single file with `sudo`, creating parent directories as required.
+fn sudo_copy(from: &Path, to: &Path) -> Result<(), CopyError> {
+    let mut mkdir = Command::new("sudo");
+    mkdir
+        .arg("mkdir")
+        .arg("-p")
+        .arg(to.parent().unwrap_or(Path::new("/")));
+
+    let mut cp = Command::new("sudo");
+    cp.arg("cp").arg(from).arg(to);
+
+    for mut cmd in [mkdir, cp] {
+        if !cmd.status()?.success() {
+            return Err(CopyError::Sudo {
+                path: to.to_path_buf(),
+            });
+        }
+    }
+
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::CopyOp;
@@ -127,7 +308,7 @@ mod tests {
         let dest = Path::new(&base_path).join("COPIED");
 
         if let Err(e) = CopyOp::new().from(&new_file).to(&dest).copy() {
-            assert!(false, "{}", e)
+            panic!("{}", e)
         }
 
         assert!(dest.is_file());
@@ -144,14 +325,14 @@ mod tests {
         if let Err(e) = cop.copy() {
             assert_eq!(e.to_string(), "No path to copy `to` provided.")
         } else {
-            assert!(false, "Hm? {:#?}", cop)
+            panic!("Hm? {:#?}", cop)
         }
 
         let cop = CopyOp::new().to(&Path::new(&base_path).join("COPIED"));
         if let Err(e) = cop.copy() {
             assert_eq!(e.to_string(), "No path to copy `from` provided.")
         } else {
-            assert!(false, "Hm? {:#?}", cop)
+            panic!("Hm? {:#?}", cop)
         }
     }
 
@@ -168,8 +349,7 @@ mod tests {
                 format!("Invalid file name: \"{}/..\"", base_path.display())
             )
         } else {
-            assert!(
-                false,
+            panic!(
                 "Hm? {} -> {}",
                 cop.from.unwrap().display(),
                 cop.to.unwrap().display()
@@ -177,6 +357,28 @@ mod tests {
         }
     }
 
+    #[test]
+    /// Expects only existing destinations that differ from an existing source
+    /// to be overwritten.
+    fn would_overwrite() {
+        let base_path = setup().join("would_overwrite");
+        std::fs::create_dir_all(&base_path).expect("Failed to make would_overwrite dir!");
+        let (from, to) = (base_path.join("from"), base_path.join("to"));
+        std::fs::write(&to, "live").expect("Failed to write to!");
+
+        let op = CopyOp::new().from(&from).to(&to);
+        assert!(op
+            .would_overwrite()
+            .expect("Failed on a missing source!")
+            .is_empty());
+        std::fs::write(&from, "live").expect("Failed to write from!");
+        assert!(op.would_overwrite().unwrap().is_empty());
+        std::fs::write(&from, "repo").expect("Failed to write from!");
+        assert_eq!(op.would_overwrite().unwrap(), [to.to_path_buf()]);
+
+        std::fs::remove_dir_all(&base_path).expect("Failed to remove would_overwrite dir!");
+    }
+
     #[test]
     /// Tests providing a directory as opposed to a file to `CopyOp`.
     /// Expects that `copy` will recurse, by walk the directory and running a
@@ -185,24 +387,20 @@ mod tests {
         let base_path = setup();
 
         let recurse_dir = &base_path.join("recurse");
-        std::fs::create_dir_all(&recurse_dir).expect("Failed to make recurse dir!");
+        std::fs::create_dir_all(recurse_dir).expect("Failed to make recurse dir!");
 
         let recurse_dest_dir = &base_path.join("recurse_dest");
-        std::fs::create_dir_all(&recurse_dest_dir).expect("Failed to make recurse_dest dir!");
+        std::fs::create_dir_all(recurse_dest_dir).expect("Failed to make recurse_dest dir!");
 
         for p in [
             Path::new(&recurse_dir).join("COPY0"),
             Path::new(&recurse_dir).join("COPY1"),
         ] {
-            File::create(&p).expect(&format!("Failed to create {}", p.display()));
+            File::create(&p).unwrap_or_else(|_| panic!("Failed to create {}", p.display()));
         }
 
-        if let Err(e) = CopyOp::new()
-            .from(&recurse_dir)
-            .to(&recurse_dest_dir)
-            .copy()
-        {
-            assert!(false, "{}", e)
+        if let Err(e) = CopyOp::new().from(recurse_dir).to(recurse_dest_dir).copy() {
+            panic!("{}", e)
         }
 
         for p in [
@@ -212,8 +410,8 @@ mod tests {
             assert!(p.is_file())
         }
 
-        std::fs::remove_dir_all(&base_path.join("recurse")).expect("Failed to remove recurse dir!");
-        std::fs::remove_dir_all(&base_path.join("recurse_dest"))
+        std::fs::remove_dir_all(base_path.join("recurse")).expect("Failed to remove recurse dir!");
+        std::fs::remove_dir_all(base_path.join("recurse_dest"))
             .expect("Failed to remove recurse_dest dir!");
     }
 }
@@ -1,6 +1,13 @@
 use chrono::Local;
-use git2::{AnnotatedCommit, IndexAddOption, Oid, Reference, Repository};
-use std::path::{Path, PathBuf};
+use git2::{
+    build::{CheckoutBuilder, RepoBuilder},
+    Cred, CredentialType, FetchOptions, IndexAddOption, ObjectType, Oid, PushOptions,
+    RemoteCallbacks, Repository, Sort, StatusOptions, TreeWalkMode, TreeWalkResult,
+};
+use std::{
+    cell::RefCell,
+    path::{Path, PathBuf},
+};
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -15,8 +22,23 @@ pub(crate) enum GitError {
     /// `Repository`.
     NoRepo,
 
-    #[error("Push was rejected.")]
-    PushRejected,
+    #[error("Push was rejected: {msg}")]
+    /// The remote refused to update a reference.
+    PushRejected { msg: String },
+
+    #[error("Cannot fast-forward `{branch}`, local & remote have diverged.")]
+    /// We only ever fast-forward, anything else is left for the user to
+    /// resolve manually.
+    NotFastForward { branch: String },
+
+    #[error("`{branch}` isn't checked out, only `{local}` can be fast-forwarded.")]
+    /// Asked to update from a branch other than the one checked out, which
+    /// would fast-forward the checked out branch to another branch's commits.
+    NotCheckedOut { branch: String, local: String },
+
+    #[error("{path:?} isn't a file in the repository at {rev}.")]
+    /// Asked for a path that doesn't exist, or is a directory, at a revision.
+    NotInRevision { path: PathBuf, rev: String },
 
     #[error(transparent)]
     /// Errors thrown by the `git2` library.
@@ -59,6 +81,30 @@ impl<'remote> GitOp<'remote> {
         Ok(self)
     }
 
+    /// Assign `path` and open the `Repository` there, initialising a new one
+    /// if there isn't one already.
+    pub(crate) fn init_at_path(mut self, path: &Path) -> Result<Self, GitError> {
+        self.path = Some(path.to_path_buf());
+        self.repo = Some(match Repository::open(path) {
+            Ok(repo) => repo,
+            Err(_) => Repository::init(path)?,
+        });
+
+        Ok(self)
+    }
+
+    /// Clone `url` into `path` and open the clone. `url` may be anything `git`
+    /// understands, including a local path or a `file://` URL.
+    pub(crate) fn clone(mut self, url: &str, path: &Path) -> Result<Self, GitError> {
+        let mut opts = FetchOptions::new();
+        opts.remote_callbacks(callbacks());
+
+        self.path = Some(path.to_path_buf());
+        self.repo = Some(RepoBuilder::new().fetch_options(opts).clone(url, path)?);
+
+        Ok(self)
+    }
+
     /// Assign `remote`.
     pub(crate) fn with_remote(mut self, remote: &'remote str) -> Self {
         self.remote = Some(remote);
@@ -67,10 +113,7 @@ impl<'remote> GitOp<'remote> {
 
     /// Get the `path` of git `Repository`.
     pub(crate) fn path(&self) -> Result<&Path, GitError> {
-        self.path
-            .as_ref()
-            .map(|pb| pb.as_path())
-            .ok_or(GitError::NoPath)
+        self.path.as_deref().ok_or(GitError::NoPath)
     }
 
     /// Get `remote`.
@@ -85,107 +128,392 @@ impl<'remote> GitOp<'remote> {
     /// Borrow the `Repository` - takes a mutable borrow of self so that we can
     /// open it if necessary.
     pub(crate) fn repo(&mut self) -> Result<&Repository, GitError> {
-        Ok(self.repo.get_or_insert(Repository::open(self.path()?)?))
+        if self.repo.is_none() {
+            self.repo = Some(Repository::open(self.path()?)?);
+        }
+
+        self.repo.as_ref().ok_or(GitError::NoRepo)
+    }
+
+    /// Name of the branch `HEAD` points at, e.g. `main`. Works for a freshly
+    /// initialised repository without any commits too.
+    pub(crate) fn branch(&mut self) -> Result<String, GitError> {
+        let repo = self.repo()?;
+        let head = repo.find_reference("HEAD")?;
+        let name = match head.symbolic_target() {
+            Some(s) => s.to_string(),
+            None => return Err(GitError::Unknown),
+        };
+
+        Ok(name.trim_start_matches("refs/heads/").to_string())
     }
 
     /// Adds latest changes from a dirty tree.
     pub(crate) fn add_changes(mut self) -> Result<(Self, Oid), GitError> {
         let mut index = self.repo()?.index()?;
         index.add_all(["*"].iter(), IndexAddOption::DEFAULT, None)?;
+        index.write()?;
 
         Ok((self, index.write_tree()?))
     }
 
+    /// Adds only `paths`, relative to the repository, removing those that no
+    /// longer exist from the index.
+    pub(crate) fn add_these(mut self, paths: &[PathBuf]) -> Result<(Self, Oid), GitError> {
+        let root = self.path()?.to_path_buf();
+        let mut index = self.repo()?.index()?;
+        for p in paths {
+            if root.join(p).exists() {
+                index.add_path(p)?;
+            } else {
+                index.remove_path(p)?;
+            }
+        }
+        index.write()?;
+
+        Ok((self, index.write_tree()?))
+    }
+
+    /// Check whether `tree_id` differs from the tree `HEAD` points at, i.e.
+    /// whether there is anything to commit.
+    pub(crate) fn has_changes(&mut self, tree_id: Oid) -> Result<bool, GitError> {
+        let repo = self.repo()?;
+        match repo.head() {
+            Ok(head) => Ok(head.peel_to_tree()?.id() != tree_id),
+            Err(_) => Ok(true),
+        }
+    }
+
     /// Commit changes - `tree_id` comes from `add_changes`.
     pub(crate) fn commit(mut self, tree_id: Oid) -> Result<(Self, Oid), GitError> {
-        let repo = self.repo()?;
-        let sig = repo.signature()?;
-        let oid = repo.commit(
-            Some("HEAD"),
-            &sig,
-            &sig,
-            &format!("Latest {}", Local::now()),
-            &repo.find_tree(tree_id)?,
-            &[&repo.find_commit(repo.head()?.target().unwrap())?],
-        )?;
+        let oid = {
+            let repo = self.repo()?;
+            let sig = repo.signature()?;
+
+            // A repository without any commits has no `HEAD` to use as a parent.
+            let parent = match repo.head() {
+                Ok(head) => Some(head.peel_to_commit()?),
+                Err(_) => None,
+            };
+
+            repo.commit(
+                Some("HEAD"),
+                &sig,
+                &sig,
+                &format!("Latest {}", Local::now()),
+                &repo.find_tree(tree_id)?,
+                &parent.iter().collect::<Vec<_>>(),
+            )?
+        };
 
         Ok((self, oid))
     }
 
-    /// Push changes to `remote`.
-    pub(crate) fn push(&mut self, remote: &str) -> Result<(), GitError> {
+    /// Push `HEAD` to `branch` on `remote`.
+    pub(crate) fn push(&mut self, remote: &str, branch: &str) -> Result<(), GitError> {
         let repo = self.repo()?;
-        repo.find_remote(remote)?.push::<&str>(&[], None)?;
 
-        Ok(())
+        let rejected = RefCell::new(None);
+        let mut cb = callbacks();
+        cb.push_update_reference(|_, status| {
+            if let Some(msg) = status {
+                rejected.replace(Some(msg.to_string()));
+            }
+
+            Ok(())
+        });
+
+        let mut opts = PushOptions::new();
+        opts.remote_callbacks(cb);
+
+        let refspec = format!("HEAD:refs/heads/{}", branch);
+        repo.find_remote(remote)?
+            .push(&[refspec.as_str()], Some(&mut opts))?;
+        drop(opts);
+
+        match rejected.into_inner() {
+            Some(msg) => Err(GitError::PushRejected { msg }),
+            None => Ok(()),
+        }
     }
 
-    /// Fetch changes from `remote` for `branch`.
-    pub(crate) fn fetch(
-        &mut self,
-        branch: &str,
-        remote: &str,
-    ) -> Result<AnnotatedCommit, GitError> {
+    /// Fetch changes from `remote` for `branch` into its remote-tracking
+    /// reference, returning the commit that `FETCH_HEAD` now points at. Local
+    /// branches aren't moved.
+    pub(crate) fn fetch(&mut self, branch: &str, remote: &str) -> Result<Oid, GitError> {
         let repo = self.repo()?;
-        repo.find_remote(remote)?.fetch(&[branch], None, None)?;
+
+        let mut opts = FetchOptions::new();
+        opts.remote_callbacks(callbacks());
+        let refspec = format!("+refs/heads/{0}:refs/remotes/{1}/{0}", branch, remote);
+        repo.find_remote(remote)?
+            .fetch(&[refspec.as_str()], Some(&mut opts), None)?;
 
         let fetch_head = repo.find_reference("FETCH_HEAD")?;
-        Ok(repo.reference_to_annotated_commit(&fetch_head)?)
+        Ok(repo.reference_to_annotated_commit(&fetch_head)?.id())
     }
 
+    /// Fast-forward `branch` to `target`, returns `false` if `branch` was
+    /// already up to date.
+    ///
     /// NOTE: This code is pretty much just copied & pasted from:
     /// https://github.com/rust-lang/git2-rs/blob/ae256db3b27dd7dedab02fa5c051bd7adedf7de7/examples/pull.rs
     /// It might need some work!
-    pub(crate) fn fast_forward(
-        &mut self,
-        branch: &mut Reference,
-        commit: &AnnotatedCommit,
-    ) -> Result<(), GitError> {
-        let name = branch.name().map_or_else(
-            || String::from_utf8_lossy(branch.name_bytes()).to_string(),
-            |s| s.to_string(),
-        );
+    pub(crate) fn fast_forward(&mut self, branch: &str, target: Oid) -> Result<bool, GitError> {
+        let repo = self.repo()?;
+        let commit = repo.find_annotated_commit(target)?;
+
+        let (analysis, _) = repo.merge_analysis(&[&commit])?;
+        if analysis.is_up_to_date() {
+            return Ok(false);
+        } else if !analysis.is_fast_forward() && !analysis.is_unborn() {
+            return Err(GitError::NotFastForward {
+                branch: branch.to_string(),
+            });
+        }
 
-        branch.set_target(
-            commit.id(),
-            &format!("Fast-Forward: {} -> {}", name, commit.id()),
-        )?;
+        let name = format!("refs/heads/{}", branch);
+        let msg = format!("Fast-Forward: {} -> {}", name, target);
+        match repo.find_reference(&name) {
+            Ok(mut r) => {
+                r.set_target(target, &msg)?;
+            }
+            Err(_) => {
+                repo.reference(&name, target, true, &msg)?;
+            }
+        }
 
-        let repo = self.repo()?;
         repo.set_head(&name)?;
 
         // This line in particular is not a good one to keep as is. The force is
         // apparently overkill, and instead there could be logic to handle dirty
         // working tree state.
-        repo.checkout_head(Some(git2::build::CheckoutBuilder::default().force()))?;
+        repo.checkout_head(Some(CheckoutBuilder::default().force()))?;
+
+        Ok(true)
+    }
+
+    /// Paths, relative to the repository, with changes that haven't been
+    /// committed yet. Includes untracked files.
+    pub(crate) fn uncommitted(&mut self) -> Result<Vec<PathBuf>, GitError> {
+        let repo = self.repo()?;
+        let mut opts = StatusOptions::new();
+        opts.include_untracked(true).recurse_untracked_dirs(true);
+
+        Ok(repo
+            .statuses(Some(&mut opts))?
+            .iter()
+            .filter(|e| !e.status().is_ignored())
+            .filter_map(|e| e.path().map(PathBuf::from))
+            .collect())
+    }
 
-        Ok(())
+    /// The commit `branch` on `remote` pointed at when last fetched, if we've
+    /// ever fetched it.
+    pub(crate) fn upstream(&mut self, remote: &str, branch: &str) -> Result<Option<Oid>, GitError> {
+        let repo = self.repo()?;
+        match repo.find_reference(&format!("refs/remotes/{}/{}", remote, branch)) {
+            Ok(r) => Ok(r.target()),
+            Err(_) => Ok(None),
+        }
     }
 
-    pub(crate) fn rebase(&mut self) -> Result<(), GitError> {
-        todo!()
+    /// The commit `HEAD` points at, `None` for a repository without commits.
+    pub(crate) fn head(&mut self) -> Result<Option<Oid>, GitError> {
+        let repo = self.repo()?;
+        match repo.head() {
+            Ok(head) => Ok(head.target()),
+            Err(_) => Ok(None),
+        }
     }
 
-    pub(crate) fn merge(&mut self) -> Result<(), GitError> {
-        todo!()
+    /// Count of commits `HEAD` is `(ahead, behind)` of `upstream`.
+    pub(crate) fn ahead_behind(&mut self, upstream: Oid) -> Result<(usize, usize), GitError> {
+        let repo = self.repo()?;
+        let head = repo.head()?.target().ok_or(GitError::Unknown)?;
+
+        Ok(repo.graph_ahead_behind(head, upstream)?)
     }
 
-    pub(crate) fn stash(&mut self) -> Result<Oid, GitError> {
-        todo!()
+    /// Blob ids of every version of `path`, relative to the repository,
+    /// committed on `HEAD`, newest first, so the first is `HEAD`'s. Nothing
+    /// if `path` isn't in `HEAD`.
+    pub(crate) fn versions(&mut self, path: &Path) -> Result<Vec<Oid>, GitError> {
+        let repo = self.repo()?;
+        if repo.head().is_err() {
+            return Ok(Vec::new());
+        }
+
+        let mut walk = repo.revwalk()?;
+        walk.push_head()?;
+
+        let mut ids: Vec<Oid> = Vec::new();
+        for oid in walk {
+            match repo.find_commit(oid?)?.tree()?.get_path(path) {
+                Ok(e) if !ids.contains(&e.id()) => ids.push(e.id()),
+                Err(_) if ids.is_empty() => break,
+                _ => {}
+            }
+        }
+
+        Ok(ids)
     }
 
-    pub(crate) fn restore(mut self) -> Result<Self, GitError> {
-        todo!()
+    /// Content & file mode of the file at `path`, relative to the repository,
+    /// as of `rev`, e.g. `HEAD~2` or a commit hash.
+    pub(crate) fn blob_at(&mut self, rev: &str, path: &Path) -> Result<(Vec<u8>, i32), GitError> {
+        let repo = self.repo()?;
+        let tree = repo.revparse_single(rev)?.peel_to_commit()?.tree()?;
+        let not_found = || GitError::NotInRevision {
+            path: path.to_path_buf(),
+            rev: rev.to_string(),
+        };
+
+        let entry = tree.get_path(path).map_err(|_| not_found())?;
+        if entry.kind() != Some(ObjectType::Blob) {
+            return Err(not_found());
+        }
+
+        let blob = entry.to_object(repo)?.peel_to_blob()?;
+        Ok((blob.content().to_vec(), entry.filemode()))
+    }
+
+    /// Files at or under `path`, relative to the repository, as of commit
+    /// `rev`, each with the id of its blob, which is the hash `plan::hash`
+    /// gives its content. Nothing if `path` isn't there.
+    pub(crate) fn blobs_at(
+        &mut self,
+        rev: Oid,
+        path: &Path,
+    ) -> Result<Vec<(PathBuf, Oid)>, GitError> {
+        let repo = self.repo()?;
+        let tree = repo.find_commit(rev)?.tree()?;
+        let entry = match tree.get_path(path) {
+            Ok(e) => e,
+            Err(_) => return Ok(Vec::new()),
+        };
+
+        let mut blobs = Vec::new();
+        match entry.kind() {
+            Some(ObjectType::Blob) => blobs.push((path.to_path_buf(), entry.id())),
+            Some(ObjectType::Tree) => {
+                entry
+                    .to_object(repo)?
+                    .peel_to_tree()?
+                    .walk(TreeWalkMode::PreOrder, |dir, e| {
+                        if let (Some(ObjectType::Blob), Some(name)) = (e.kind(), e.name()) {
+                            blobs.push((path.join(dir).join(name), e.id()));
+                        }
+                        TreeWalkResult::Ok
+                    })?;
+            }
+            _ => {}
+        }
+
+        Ok(blobs)
+    }
+
+    /// Paths, relative to the repository, of files that differ between
+    /// commits `from` & `to`, every file in `to` if there's no `from`.
+    pub(crate) fn changed(&mut self, from: Option<Oid>, to: Oid) -> Result<Vec<PathBuf>, GitError> {
+        let repo = self.repo()?;
+        let old = match from {
+            Some(oid) => Some(repo.find_commit(oid)?.tree()?),
+            None => None,
+        };
+        let new = repo.find_commit(to)?.tree()?;
+        let diff = repo.diff_tree_to_tree(old.as_ref(), Some(&new), None)?;
+
+        Ok(diff
+            .deltas()
+            .filter_map(|d| d.new_file().path().or_else(|| d.old_file().path()))
+            .map(Path::to_path_buf)
+            .collect())
+    }
+
+    /// Commits reachable from `HEAD` that changed `path`, relative to the
+    /// repository, newest first. A commit changed `path` when what's there
+    /// differs from its first parent, so directories work too.
+    pub(crate) fn history(&mut self, path: &Path) -> Result<Vec<Oid>, GitError> {
+        let repo = self.repo()?;
+        if repo.head().is_err() {
+            return Ok(Vec::new());
+        }
+
+        let mut walk = repo.revwalk()?;
+        walk.push_head()?;
+        walk.set_sorting(Sort::TOPOLOGICAL | Sort::TIME)?;
+
+        let entry = |c: &git2::Commit| c.tree().ok()?.get_path(path).ok().map(|e| e.id());
+        let mut oids = Vec::new();
+        for oid in walk {
+            let commit = repo.find_commit(oid?)?;
+            let parent = commit.parents().next();
+            if entry(&commit) != parent.as_ref().and_then(entry) {
+                oids.push(commit.id());
+            }
+        }
+
+        Ok(oids)
     }
 }
 
+/// `RemoteCallbacks` that authenticate via `ssh-agent`, or the credential
+/// helper configured in .gitconfig.
+fn callbacks<'a>() -> RemoteCallbacks<'a> {
+    let mut cb = RemoteCallbacks::new();
+    cb.credentials(|url, username, allowed| {
+        if allowed.contains(CredentialType::SSH_KEY) {
+            Cred::ssh_key_from_agent(username.unwrap_or("git"))
+        } else if allowed.contains(CredentialType::USER_PASS_PLAINTEXT) {
+            Cred::credential_helper(&git2::Config::open_default()?, url, username)
+        } else {
+            Cred::default()
+        }
+    });
+
+    cb
+}
+
 #[cfg(test)]
 mod tests {
     use super::GitOp;
     use crate::_TESTS_DIR;
     use chrono::Local;
-    use git2::{RemoteCallbacks, Repository};
-    use std::{fs::File, path::Path};
+    use git2::Repository;
+    use std::{
+        fs::File,
+        path::{Path, PathBuf},
+    };
+
+    /// Initialise a `Repository` at `path` with a signature configured, so
+    /// tests don't depend on the .gitconfig of whoever is running them.
+    fn init(path: &Path) -> Repository {
+        let repo = Repository::init(path).expect("Failed to init repository!"); // init will make directories.
+        let mut config = repo.config().expect("Failed to get config!");
+        config
+            .set_str("user.name", "nedots")
+            .expect("Failed to set user.name!");
+        config
+            .set_str("user.email", "nedots@localhost")
+            .expect("Failed to set user.email!");
+
+        repo
+    }
+
+    /// Make a repository at `path` containing a single commit of `file`.
+    fn init_with_commit(path: &Path, file: &str) -> GitOp<'static> {
+        init(path);
+        File::create(path.join(file)).expect("Failed to create file!");
+        let (go, tree_id) = GitOp::new()
+            .at_path(path)
+            .expect("Failed to create `GitOp`!")
+            .add_changes()
+            .expect("Failed add_changes!");
+        let (go, _) = go.commit(tree_id).expect("Failed commit!");
+
+        go
+    }
 
     #[test]
     /// Expects to fail because a `GitOp` was made without a `path` to a
@@ -218,7 +546,7 @@ mod tests {
     /// removing the git directory.
     fn add_commit() {
         let path = Path::new(_TESTS_DIR).join("git");
-        Repository::init(&path).expect("Failed to init repository!"); // init will make directories.
+        init(&path);
 
         // Make GitOp & open repo.
         let go = GitOp::new()
@@ -226,7 +554,7 @@ mod tests {
             .expect("Failed to create `GitOp`!");
 
         // Dirty the tree with a new file.
-        File::create(&path.join("README")).expect("Failed to create README file!");
+        File::create(path.join("README")).expect("Failed to create README file!");
 
         // Add changes - mutates `go` so is re-assigned.
         let (mut go, tree_id) = go.add_changes().expect("Failed add_changes README!");
@@ -247,7 +575,7 @@ mod tests {
             .expect("Failed commit README!");
 
         // Dirty the tree with another new file.
-        File::create(&path.join("CONTRIBUTING")).expect("Failed to create CONTRIBUTING file!");
+        File::create(path.join("CONTRIBUTING")).expect("Failed to create CONTRIBUTING file!");
 
         // Add changes once again. Again, mutated so re-assigned.
         let (go, tree_id) = go.add_changes().expect("Failed add_changes CONTRIBUTING!");
@@ -267,24 +595,74 @@ mod tests {
         std::fs::remove_dir_all(&path).expect("Failed to remove git dir!");
     }
 
-    // #[test]
-    /// Tests a `fetch` - don't expect `fetch` to pull in any changes, but we
-    /// expect that this won't fail.
-    ///
-    /// TODO: auth.
-    fn fetch() {
-        let mut cb = RemoteCallbacks::new();
-        cb.credentials(|_user, username_from_url, _allowed_types| todo!());
-
+    #[test]
+    /// Fetches from a local "remote" & fast-forwards. Expects the second
+    /// fast-forward to report that there is nothing to do.
+    fn fetch_fast_forward() {
+        let origin = Path::new(_TESTS_DIR).join("git_fetch_origin");
+        let local = Path::new(_TESTS_DIR).join("git_fetch_local");
+        let mut origin_go = init_with_commit(&origin, "README");
+
+        init(&local)
+            .remote("origin", origin.canonicalize().unwrap().to_str().unwrap())
+            .expect("Failed to add remote!");
         let mut go = GitOp::new()
-            .at_path(
-                std::env::current_dir()
-                    .expect("Failed to get current_dir!")
-                    .parent()
-                    .expect("Failed to get parent of current_dir!"),
-            )
+            .at_path(&local)
             .expect("Failed to create `GitOp`!");
 
-        go.fetch("main", "origin").expect("Failed to fetch!");
+        let branch = origin_go.branch().expect("Failed to get branch!");
+        let oid = go.fetch(&branch, "origin").expect("Failed to fetch!");
+        assert!(go
+            .fast_forward(&branch, oid)
+            .expect("Failed to fast-forward!"));
+        assert!(!go
+            .fast_forward(&branch, oid)
+            .expect("Failed to fast-forward!"));
+        assert!(PathBuf::from(&local).join("README").is_file());
+        assert_eq!(go.ahead_behind(oid).expect("Failed ahead_behind!"), (0, 0));
+        assert!(go.uncommitted().expect("Failed uncommitted!").is_empty());
+
+        std::fs::remove_dir_all(&origin).expect("Failed to remove origin dir!");
+        std::fs::remove_dir_all(&local).expect("Failed to remove local dir!");
+    }
+
+    #[test]
+    /// Expects only the commits that changed a path, newest first.
+    fn history() {
+        let path = Path::new(_TESTS_DIR).join("git_history");
+        let go = init_with_commit(&path, "README");
+        let first = go.repo.as_ref().unwrap().head().unwrap().target().unwrap();
+
+        std::fs::write(path.join("notes"), "a").expect("Failed to write notes!");
+        let (go, tree_id) = go.add_changes().expect("Failed add_changes!");
+        let (go, _) = go.commit(tree_id).expect("Failed commit!");
+        std::fs::write(path.join("README"), "b").expect("Failed to write README!");
+        let (go, tree_id) = go.add_changes().expect("Failed add_changes!");
+        let (mut go, last) = go.commit(tree_id).expect("Failed commit!");
+
+        assert_eq!(
+            go.history(Path::new("README")).expect("Failed history!"),
+            [last, first]
+        );
+        assert_eq!(go.history(Path::new("notes")).unwrap().len(), 1);
+        assert!(go.history(Path::new("missing")).unwrap().is_empty());
+
+        std::fs::remove_dir_all(&path).expect("Failed to remove git dir!");
+    }
+
+    #[test]
+    /// Expects a `file://` URL to clone, with the working tree checked out.
+    fn clone_file_url() {
+        let origin = Path::new(_TESTS_DIR).join("git_clone_origin");
+        let local = Path::new(_TESTS_DIR).join("git_clone_local");
+        init_with_commit(&origin, "README");
+
+        let url = format!("file://{}", origin.canonicalize().unwrap().display());
+        let mut go = GitOp::new().clone(&url, &local).expect("Failed to clone!");
+        assert!(local.join("README").is_file());
+        assert!(go.uncommitted().expect("Failed uncommitted!").is_empty());
+
+        std::fs::remove_dir_all(&origin).expect("Failed to remove origin dir!");
+        std::fs::remove_dir_all(&local).expect("Failed to remove local dir!");
     }
 }
@@ -0,0 +1,206 @@
+use super::{
+    git::GitOp,
+    op::{Operate, Operation, OperationError},
+    plan::Step,
+};
+use crate::{
+    config::{self, Config, ConfigError, CONFIG_FILE},
+    output::TerminalLogger,
+};
+use dialoguer::{theme::ColorfulTheme, MultiSelect};
+use serde_json::json;
+use std::path::{Path, PathBuf};
+
+/// Common dotfiles that live directly in $HOME.
+const HOME_FILES: [&str; 12] = [
+    ".bashrc",
+    ".bash_profile",
+    ".profile",
+    ".zshrc",
+    ".gitconfig",
+    ".nanorc",
+    ".vimrc",
+    ".tmux.conf",
+    ".Xresources",
+    ".xinitrc",
+    ".xprofile",
+    ".xsettingsd",
+];
+
+/// Directories in $HOME whose entries are each offered as a candidate.
+const HOME_DIRS: [&str; 2] = [".config", ".local/bin"];
+
+/// Bootstraps a new dotfiles repository & writes a `nedots.json` for it.
+pub(crate) struct Init {
+    pub(crate) parent_op: Operation<TerminalLogger>,
+
+    /// Path of the repository to create or adopt.
+    pub(crate) path: PathBuf,
+
+    /// Where to write `nedots.json`, defaults to the root of the repository.
+    /// Written as TOML or YAML if it ends in `.toml` or `.yaml`.
+    pub(crate) config_path: Option<PathBuf>,
+
+    /// Track every candidate without prompting.
+    pub(crate) all: bool,
+
+    /// Overwrite an existing `nedots.json`.
+    pub(crate) force: bool,
+}
+
+impl Init {
+    pub(crate) fn new(op: Operation<TerminalLogger>) -> Self {
+        Self {
+            parent_op: op,
+            path: PathBuf::new(),
+            config_path: None,
+            all: false,
+            force: false,
+        }
+    }
+
+    /// Assign `path`, relative paths are relative to $HOME.
+    pub(crate) fn at(mut self, path: &Path) -> Self {
+        self.path = crate::home::dir().join(path);
+        self
+    }
+
+    /// Assign `config_path`.
+    pub(crate) fn config_to(mut self, path: Option<PathBuf>) -> Self {
+        self.config_path = path;
+        self
+    }
+
+    /// Assign `all`.
+    pub(crate) fn all(mut self, all: bool) -> Self {
+        self.all = all;
+        self
+    }
+
+    /// Assign `force`.
+    pub(crate) fn force(mut self, force: bool) -> Self {
+        self.force = force;
+        self
+    }
+}
+
+impl Operate for Init {
+    fn operate(&mut self) -> Result<usize, OperationError> {
+        let config_path = match &self.config_path {
+            Some(p) => p.clone(),
+            None => config::find_in(&self.path).unwrap_or_else(|| self.path.join(CONFIG_FILE)),
+        };
+        if config_path.exists() && !self.force {
+            return Err(ConfigError::Exists { path: config_path }.into());
+        }
+
+        if self.parent_op.is_dry_run() {
+            self.parent_op.record([Step::Init {
+                path: self.path.clone(),
+            }]);
+        } else {
+            GitOp::new().init_at_path(&self.path)?;
+            self.parent_op.report().path(&self.path, "initialized");
+            crate::output::term(&format!("Repository at {}", self.path.display()));
+        }
+
+        let home = crate::home::dir();
+        let found = candidates(home).map_err(ConfigError::from)?;
+        let chosen = if self.all || found.is_empty() {
+            found
+        } else {
+            let items: Vec<String> = found.iter().map(|p| p.display().to_string()).collect();
+            MultiSelect::with_theme(&ColorfulTheme::default())
+                .with_prompt("Track which files? (space to select, enter to confirm)")
+                .items(&items)
+                .interact()
+                .map_err(ConfigError::from)?
+                .into_iter()
+                .map(|i| found[i].clone())
+                .collect()
+        };
+
+        let config = Config {
+            path: self.path.clone(),
+            user: chosen,
+            ..Default::default()
+        };
+        if self.parent_op.is_dry_run() {
+            self.parent_op.record([Step::WriteConfig {
+                path: config_path,
+                config: Box::new(config),
+            }]);
+            return Ok(0);
+        }
+
+        config.write(&config_path)?;
+        for p in &config.user {
+            self.parent_op.report().path(&home.join(p), "tracked");
+        }
+        self.parent_op
+            .report()
+            .details
+            .insert("config", json!(config_path));
+
+        crate::output::term(&format!(
+            "Wrote {} tracking {} path(s), run `nedots add-changes` to copy them into the repository.",
+            config_path.display(),
+            config.user.len()
+        ));
+
+        Ok(0)
+    }
+
+    fn exit_code(&mut self) -> usize {
+        let result = self.operate();
+        self.parent_op.finish(result)
+    }
+}
+
+/// Dotfiles under `home` worth offering to track, relative to `home`.
+pub(crate) fn candidates(home: &Path) -> Result<Vec<PathBuf>, std::io::Error> {
+    let mut found: Vec<PathBuf> = HOME_FILES
+        .iter()
+        .map(PathBuf::from)
+        .filter(|p| home.join(p).exists())
+        .collect();
+
+    for dir in HOME_DIRS {
+        if !home.join(dir).is_dir() {
+            continue;
+        }
+
+        let mut entries = Vec::new();
+        for e in std::fs::read_dir(home.join(dir))? {
+            entries.push(Path::new(dir).join(e?.file_name()));
+        }
+        entries.sort();
+        found.append(&mut entries);
+    }
+
+    Ok(found)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::candidates;
+    use crate::_TESTS_DIR;
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    /// Expects known dotfiles & entries of `.config` to be found, and
+    /// anything else to be ignored.
+    fn find_candidates() {
+        let home = Path::new(_TESTS_DIR).join("init");
+        std::fs::create_dir_all(home.join(".config").join("fish")).expect("Failed to make home!");
+        std::fs::write(home.join(".bashrc"), "").expect("Failed to write .bashrc!");
+        std::fs::write(home.join("notes.txt"), "").expect("Failed to write notes.txt!");
+
+        assert_eq!(
+            candidates(&home).expect("Failed to find candidates!"),
+            [PathBuf::from(".bashrc"), PathBuf::from(".config/fish")]
+        );
+
+        std::fs::remove_dir_all(&home).expect("Failed to remove init dir!");
+    }
+}
@@ -0,0 +1,167 @@
+use super::{
+    fs::{self, CopyError},
+    git::{GitError, GitOp},
+    op::{Operate, Operation, OperationError},
+};
+use crate::output::TerminalLogger;
+use chrono::{FixedOffset, TimeZone};
+use console::style;
+use git2::{Commit, DiffFormat, DiffOptions, DiffStatsFormat, Repository};
+use std::path::{Path, PathBuf};
+
+/// Shows the commits that changed a tracked path.
+pub(crate) struct Log {
+    pub(crate) parent_op: Operation<TerminalLogger>,
+
+    /// Path of the repository.
+    pub(crate) repo: PathBuf,
+
+    /// Live path to show history for.
+    pub(crate) path: PathBuf,
+
+    /// Show the change each commit made too.
+    pub(crate) patch: bool,
+}
+
+impl Log {
+    pub(crate) fn new(op: Operation<TerminalLogger>) -> Self {
+        Self {
+            parent_op: op,
+            repo: PathBuf::new(),
+            path: PathBuf::new(),
+            patch: false,
+        }
+    }
+
+    /// Assign `repo`.
+    pub(crate) fn of(mut self, repo: PathBuf) -> Self {
+        self.repo = repo;
+        self
+    }
+
+    /// Assign `path`, relative paths are relative to the current directory.
+    pub(crate) fn at(mut self, path: &str) -> Result<Self, OperationError> {
+        self.path = std::env::current_dir().map_err(CopyError::from)?.join(path);
+        Ok(self)
+    }
+
+    /// Assign `patch`.
+    pub(crate) fn patch(mut self, patch: bool) -> Self {
+        self.patch = patch;
+        self
+    }
+
+    /// `path` inside the repository, relative to its root.
+    fn rel(&self) -> PathBuf {
+        let in_repo = fs::repo_path(&self.repo, &self.path);
+        in_repo
+            .strip_prefix(&self.repo)
+            .unwrap_or(&in_repo)
+            .to_path_buf()
+    }
+
+    /// Render `commit`, with a stat or patch of what it changed under `rel`.
+    fn render(&self, repo: &Repository, commit: &Commit, rel: &Path) -> Result<String, GitError> {
+        let time = commit.time();
+        let date = FixedOffset::east(time.offset_minutes() * 60).timestamp(time.seconds(), 0);
+
+        let mut out = format!(
+            "{}\nDate:   {}\n",
+            style(format!("commit {}", commit.id())).yellow(),
+            date.format("%a %b %e %T %Y %z")
+        );
+        let message = commit.message().unwrap_or_default();
+        if let Some(host) = host(message) {
+            out.push_str(&format!("Host:   {}\n", host));
+        }
+        out.push('\n');
+        for line in message.lines().filter(|l| !l.starts_with("Host:")) {
+            out.push_str(format!("    {}", line).trim_end());
+            out.push('\n');
+        }
+        out = out.trim_end().to_string() + "\n\n";
+
+        let old = match commit.parents().next() {
+            Some(p) => Some(p.tree()?),
+            None => None,
+        };
+        let diff = repo.diff_tree_to_tree(
+            old.as_ref(),
+            Some(&commit.tree()?),
+            Some(DiffOptions::new().pathspec(rel)),
+        )?;
+
+        if !self.patch {
+            let stats = diff.stats()?.to_buf(DiffStatsFormat::FULL, 80)?;
+            out.push_str(stats.as_str().unwrap_or_default());
+            return Ok(out);
+        }
+
+        diff.print(DiffFormat::Patch, |_, _, line| {
+            let text = String::from_utf8_lossy(line.content());
+            let rendered = match line.origin() {
+                '+' => style(format!("+{}", text)).green().to_string(),
+                '-' => style(format!("-{}", text)).red().to_string(),
+                ' ' => format!(" {}", text),
+                'H' => style(text.to_string()).cyan().to_string(),
+                'F' => style(text.to_string()).bold().to_string(),
+                _ => text.to_string(),
+            };
+            out.push_str(&rendered);
+            true
+        })?;
+
+        Ok(out)
+    }
+}
+
+/// The host a commit was made on, from a `Host:` trailer in its `message`.
+fn host(message: &str) -> Option<&str> {
+    message
+        .lines()
+        .rev()
+        .find_map(|l| l.strip_prefix("Host:"))
+        .map(str::trim)
+}
+
+impl Operate for Log {
+    fn operate(&mut self) -> Result<usize, OperationError> {
+        let rel = self.rel();
+        let mut go = GitOp::new().at_path(&self.repo)?;
+        let oids = go.history(&rel)?;
+        if oids.is_empty() {
+            crate::output::term(&format!("No commits touch {}", self.path.display()));
+            return Ok(0);
+        }
+
+        let repo = go.repo()?;
+        for oid in oids {
+            let commit = repo.find_commit(oid).map_err(GitError::from)?;
+            crate::output::term(self.render(repo, &commit, &rel)?.trim_end());
+            crate::output::term("");
+            self.parent_op.report().commits.push(oid.to_string());
+        }
+
+        Ok(0)
+    }
+
+    fn exit_code(&mut self) -> usize {
+        let result = self.operate();
+        self.parent_op.finish(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::host;
+
+    #[test]
+    /// Expects the last `Host:` trailer to be found, and none otherwise.
+    fn find_host() {
+        assert_eq!(
+            host("Latest 2022-03-01\n\nHost: desktop\n"),
+            Some("desktop")
+        );
+        assert_eq!(host("Latest 2022-03-01"), None);
+    }
+}
@@ -0,0 +1,594 @@
+use super::{
+    backup,
+    fs::{self, CopyOp},
+    op::{Operate, Operation, OperationError},
+    plan::Step,
+};
+use crate::{config::Config, output::TerminalLogger};
+use clap::ArgEnum;
+use git2::{ObjectType, Repository, TreeWalkMode, TreeWalkResult};
+use nix::{sys::utsname::uname, unistd::User};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    os::unix::fs::PermissionsExt,
+    path::{Component, Path, PathBuf},
+};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+/// Errors thrown while reading another tool's layout.
+pub(crate) enum MigrateError {
+    #[error("{path:?} doesn't exist, pass the path to import from.")]
+    /// Nothing to import at `path`.
+    NotFound { path: PathBuf },
+
+    #[error("Stow has no default directory, pass the path to import from.")]
+    /// No path was given, and the tool has no default.
+    NoPath,
+
+    #[error("Failed to read yadm repository {path:?}: {msg}")]
+    /// yadm's bare repository couldn't be read.
+    Repo { path: PathBuf, msg: String },
+
+    #[error(transparent)]
+    /// A wrapper around IO errors.
+    IoError(#[from] std::io::Error),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ArgEnum)]
+/// Tools whose layouts can be imported.
+pub(crate) enum Source {
+    /// GNU Stow, a directory of packages.
+    Stow,
+
+    /// yadm, a bare repository with `##` alternates.
+    Yadm,
+
+    /// chezmoi, a source directory with `dot_` naming.
+    Chezmoi,
+}
+
+impl Source {
+    /// Where the tool keeps its files by default, if it has a default.
+    pub(crate) fn default_path(&self) -> Option<PathBuf> {
+        let share = crate::home::dir().join(".local").join("share");
+        match self {
+            Source::Stow => None,
+            Source::Yadm => Some(share.join("yadm").join("repo.git")),
+            Source::Chezmoi => Some(share.join("chezmoi")),
+        }
+    }
+}
+
+/// A file to import, `content` is written to `rel`, relative to $HOME, with
+/// `mode`.
+#[derive(Debug)]
+struct Entry {
+    rel: PathBuf,
+    content: Vec<u8>,
+    mode: u32,
+}
+
+/// Files to import, and those skipped with the reason why.
+type Found = (Vec<Entry>, Vec<(PathBuf, String)>);
+
+/// Reads a Stow directory, every directory in it is a package laid out like
+/// $HOME. `dot-` prefixes, from `stow --dotfiles`, become `.`.
+fn stow(path: &Path) -> Result<Found, MigrateError> {
+    let mut entries = Vec::new();
+    for package in std::fs::read_dir(path)? {
+        let package = package?.path();
+        let name = package.file_name().unwrap_or_default().to_string_lossy();
+        if !package.is_dir() || name.starts_with('.') {
+            continue;
+        }
+
+        for f in fs::files(&package)? {
+            if f.components().any(|c| c.as_os_str() == ".git")
+                || f.file_name() == Some(".stow-local-ignore".as_ref())
+            {
+                continue;
+            }
+
+            let rel = f
+                .components()
+                .map(|c| {
+                    let c = c.as_os_str().to_string_lossy();
+                    match c.strip_prefix("dot-") {
+                        Some(rest) => format!(".{}", rest),
+                        None => c.to_string(),
+                    }
+                })
+                .collect();
+            entries.push(read(&package.join(&f), rel)?);
+        }
+    }
+
+    Ok((entries, Vec::new()))
+}
+
+/// Facts about this system yadm alternates are chosen by.
+#[derive(Debug, Default)]
+struct System {
+    os: String,
+    hostname: String,
+    user: String,
+    distro: String,
+    arch: String,
+    class: Option<String>,
+}
+
+impl System {
+    fn new(class: Option<String>) -> Self {
+        let uts = uname().ok();
+        let field = |f: fn(&nix::sys::utsname::UtsName) -> &std::ffi::OsStr| {
+            uts.as_ref()
+                .map(|u| f(u).to_string_lossy().to_string())
+                .unwrap_or_default()
+        };
+        let distro = std::fs::read_to_string("/etc/os-release")
+            .unwrap_or_default()
+            .lines()
+            .find_map(|l| l.strip_prefix("ID="))
+            .unwrap_or_default()
+            .trim_matches('"')
+            .to_string();
+
+        Self {
+            os: field(|u| u.sysname()),
+            hostname: field(|u| u.nodename()),
+            user: User::from_uid(nix::unistd::getuid())
+                .ok()
+                .flatten()
+                .map(|u| u.name)
+                .unwrap_or_default(),
+            distro,
+            arch: field(|u| u.machine()),
+            class,
+        }
+    }
+
+    /// Score of a yadm alternate's `conditions`, e.g. `os.Linux,h.laptop`,
+    /// `None` if one doesn't hold. More conditions win, `default` scores
+    /// least, and templates aren't supported so never hold.
+    fn score(&self, conditions: &str) -> Option<usize> {
+        let mut score = 0;
+        for condition in conditions.split(',') {
+            let (key, value) = condition.split_once('.').unwrap_or((condition, ""));
+            let holds = match key {
+                "default" => true,
+                "os" | "o" => value == self.os,
+                "hostname" | "h" => value == self.hostname,
+                "user" | "u" => value == self.user,
+                "distro" | "d" => value == self.distro,
+                "arch" | "a" => value == self.arch,
+                "class" | "c" => Some(value) == self.class.as_deref(),
+                "extension" | "e" => true,
+                _ => false,
+            };
+            if !holds {
+                return None;
+            }
+            if key != "default" && key != "extension" && key != "e" {
+                score += 1;
+            }
+        }
+
+        Some(score)
+    }
+}
+
+/// Reads yadm's bare repository, whose work tree is $HOME. Of a file's `##`
+/// alternates, the one that best fits this system is imported.
+fn yadm(path: &Path) -> Result<Found, MigrateError> {
+    let bad = |e: git2::Error| MigrateError::Repo {
+        path: path.to_path_buf(),
+        msg: e.message().to_string(),
+    };
+    let repo = Repository::open_bare(path).map_err(bad)?;
+    let class = repo.config().and_then(|c| c.get_string("local.class")).ok();
+    let system = System::new(class);
+    let tree = repo.head().and_then(|h| h.peel_to_tree()).map_err(bad)?;
+
+    // Best alternate of each path so far, by score.
+    let mut best: BTreeMap<PathBuf, (usize, git2::Oid, i32)> = BTreeMap::new();
+    let mut skipped = Vec::new();
+    tree.walk(TreeWalkMode::PreOrder, |dir, e| {
+        if e.kind() != Some(ObjectType::Blob) {
+            return TreeWalkResult::Ok;
+        }
+
+        let full = Path::new(dir).join(e.name().unwrap_or_default());
+        let name = full.to_string_lossy().to_string();
+        let (rel, score) = match name.split_once("##") {
+            Some((base, conditions)) => match system.score(conditions) {
+                Some(score) => (PathBuf::from(base), score),
+                None => {
+                    skipped.push((full, String::from("alternate for another system")));
+                    return TreeWalkResult::Ok;
+                }
+            },
+            None => (full, 0),
+        };
+        if !matches!(best.get(&rel), Some((s, ..)) if *s >= score) {
+            best.insert(rel, (score, e.id(), e.filemode()));
+        }
+
+        TreeWalkResult::Ok
+    })
+    .map_err(bad)?;
+
+    let mut entries = Vec::new();
+    for (rel, (_, oid, mode)) in best {
+        let blob = repo.find_blob(oid).map_err(bad)?;
+        entries.push(Entry {
+            rel,
+            content: blob.content().to_vec(),
+            mode: match mode {
+                0o100755 => 0o755,
+                _ => 0o644,
+            },
+        });
+    }
+
+    Ok((entries, skipped))
+}
+
+/// Translate a chezmoi source name into the target name and mode, `Err` with
+/// a reason when nedots can't handle it, e.g. scripts & templates.
+fn chezmoi_name(name: &str, dir: bool) -> Result<(String, u32), String> {
+    if name.ends_with(".tmpl") {
+        return Err(String::from("templates aren't supported"));
+    }
+
+    let mut rest = name;
+    let mut mode = if dir { 0o755 } else { 0o644 };
+    while let Some((prefix, stripped)) = rest.split_once('_') {
+        match prefix {
+            "encrypted" => return Err(String::from("encrypted files aren't supported")),
+            "run" | "modify" | "remove" => return Err(String::from("scripts aren't supported")),
+            "symlink" => return Err(String::from("symlinks aren't supported")),
+            "executable" => mode |= 0o111,
+            "private" => mode &= 0o700,
+            "readonly" => mode &= !0o222,
+            "create" | "empty" | "exact" | "once" | "onchange" | "before" | "after"
+            | "external" => {}
+            "literal" => {
+                rest = stripped;
+                break;
+            }
+            "dot" => {
+                return Ok((
+                    format!(".{}", stripped.strip_suffix(".literal").unwrap_or(stripped)),
+                    mode,
+                ))
+            }
+            _ => break,
+        }
+        rest = stripped;
+    }
+
+    Ok((
+        rest.strip_suffix(".literal").unwrap_or(rest).to_string(),
+        mode,
+    ))
+}
+
+/// Reads chezmoi's source directory, translating `dot_`, `executable_`,
+/// `private_` etc. into names & modes.
+fn chezmoi(path: &Path) -> Result<Found, MigrateError> {
+    let mut entries = Vec::new();
+    let mut skipped = Vec::new();
+    'files: for f in fs::files(path)? {
+        let mut rel = PathBuf::new();
+        let mut mode = 0o644;
+        let count = f.components().count();
+        for (i, c) in f.components().enumerate() {
+            let name = c.as_os_str().to_string_lossy();
+            if name.starts_with(".chezmoi") || name.starts_with(".git") {
+                continue 'files;
+            }
+
+            match chezmoi_name(&name, i + 1 < count) {
+                Ok((name, m)) => {
+                    rel.push(name);
+                    mode = m;
+                }
+                Err(reason) => {
+                    skipped.push((f.to_path_buf(), reason));
+                    continue 'files;
+                }
+            }
+        }
+
+        let mut entry = read(&path.join(&f), rel)?;
+        entry.mode = mode;
+        entries.push(entry);
+    }
+
+    Ok((entries, skipped))
+}
+
+/// Read the file at `path` into an `Entry` for `rel`, keeping its mode.
+fn read(path: &Path, rel: PathBuf) -> Result<Entry, MigrateError> {
+    Ok(Entry {
+        rel,
+        content: std::fs::read(path)?,
+        mode: std::fs::metadata(path)?.permissions().mode() & 0o777,
+    })
+}
+
+/// The fewest `user` entries covering `files`, relative to `home`. A
+/// directory is only tracked whole when it's on this machine & nothing else
+/// lives in it, since tracking it tracks everything in it.
+fn user_entries(home: &Path, files: &BTreeSet<PathBuf>) -> Result<Vec<PathBuf>, MigrateError> {
+    fn cover(
+        home: &Path,
+        prefix: &Path,
+        files: &BTreeSet<PathBuf>,
+        out: &mut Vec<PathBuf>,
+    ) -> Result<(), MigrateError> {
+        let under: BTreeSet<&PathBuf> = files.iter().filter(|f| f.starts_with(prefix)).collect();
+        if prefix.components().next().is_some() {
+            // A directory that isn't here yet would also track whatever is
+            // later put in it, e.g. all of ~/.config on a fresh machine.
+            let live = home.join(prefix);
+            if files.contains(prefix)
+                || (live.exists()
+                    && fs::files(&live)?
+                        .iter()
+                        .all(|f| under.contains(&fs::join(prefix, f))))
+            {
+                out.push(prefix.to_path_buf());
+                return Ok(());
+            }
+        }
+
+        let children: BTreeSet<PathBuf> = under
+            .iter()
+            .filter_map(|f| {
+                f.strip_prefix(prefix)
+                    .ok()?
+                    .components()
+                    .next()
+                    .map(|c| match c {
+                        Component::Normal(name) => prefix.join(name),
+                        _ => prefix.to_path_buf(),
+                    })
+            })
+            .collect();
+        for child in children {
+            cover(home, &child, files, out)?;
+        }
+
+        Ok(())
+    }
+
+    let mut out = Vec::new();
+    cover(home, Path::new(""), files, &mut out)?;
+    Ok(out)
+}
+
+/// Imports files managed by Stow, yadm or chezmoi: they're copied into the
+/// repository where nedots expects them, and tracked in `nedots.json`.
+pub(crate) struct ImportFrom {
+    pub(crate) parent_op: Operation<TerminalLogger>,
+
+    /// Path of the repository.
+    pub(crate) repo: PathBuf,
+
+    /// Path of `nedots.json`.
+    pub(crate) config_path: PathBuf,
+
+    /// Tool to import from.
+    pub(crate) source: Source,
+
+    /// Where the tool keeps its files.
+    pub(crate) path: PathBuf,
+}
+
+impl ImportFrom {
+    pub(crate) fn new(op: Operation<TerminalLogger>) -> Self {
+        Self {
+            parent_op: op,
+            repo: PathBuf::new(),
+            config_path: PathBuf::from(crate::config::CONFIG_FILE),
+            source: Source::Stow,
+            path: PathBuf::new(),
+        }
+    }
+
+    /// Assign `repo`.
+    pub(crate) fn of(mut self, repo: PathBuf) -> Self {
+        self.repo = repo;
+        self
+    }
+
+    /// Assign `config_path`.
+    pub(crate) fn config_at(mut self, path: &Path) -> Self {
+        self.config_path = path.to_path_buf();
+        self
+    }
+
+    /// Import from `source` at `path`, relative to the current directory, or
+    /// wherever `source` keeps its files by default.
+    pub(crate) fn from(
+        mut self,
+        source: Source,
+        path: Option<&str>,
+    ) -> Result<Self, OperationError> {
+        self.source = source;
+        self.path = match (path, source.default_path()) {
+            (Some(p), _) => std::env::current_dir().map_err(MigrateError::from)?.join(p),
+            (None, Some(p)) => p,
+            (None, None) => return Err(MigrateError::NoPath.into()),
+        };
+        if !self.path.exists() {
+            return Err(MigrateError::NotFound {
+                path: self.path.to_path_buf(),
+            }
+            .into());
+        }
+
+        Ok(self)
+    }
+}
+
+impl Operate for ImportFrom {
+    fn operate(&mut self) -> Result<usize, OperationError> {
+        let home = crate::home::dir();
+        let (entries, skipped) = match self.source {
+            Source::Stow => stow(&self.path)?,
+            Source::Yadm => yadm(&self.path)?,
+            Source::Chezmoi => chezmoi(&self.path)?,
+        };
+        for (path, reason) in &skipped {
+            crate::output::term(&format!("Skipped {}, {}", path.display(), reason));
+        }
+
+        // Staged with their final names & modes, so they can be copied, or
+        // planned, like any other file.
+        let staging = backup::state_dir().join("imports").join(backup::run_id());
+        let mut copied = 0;
+        for e in &entries {
+            let staged = staging.join(&e.rel);
+            std::fs::create_dir_all(staged.parent().unwrap_or(&staging))
+                .map_err(MigrateError::from)?;
+            std::fs::write(&staged, &e.content).map_err(MigrateError::from)?;
+            std::fs::set_permissions(&staged, PermissionsExt::from_mode(e.mode))
+                .map_err(MigrateError::from)?;
+
+            let op = CopyOp::new()
+                .from(&staged)
+                .to(&fs::repo_path(&self.repo, &home.join(&e.rel)));
+            match self.parent_op.is_dry_run() {
+                true => self.parent_op.record(op.plan()?),
+                false => op.copy()?,
+            }
+            self.parent_op.report().path(&home.join(&e.rel), "imported");
+            copied += 1;
+        }
+
+        let mut config = Config::read(Some(&self.config_path))?;
+        let rels = entries.iter().map(|e| e.rel.to_path_buf()).collect();
+        for rel in user_entries(home, &rels)? {
+            if config.user.iter().any(|u| rel.starts_with(u)) {
+                continue;
+            }
+            config.track(home, &home.join(&rel));
+            crate::output::term(&format!("Tracking {}", home.join(&rel).display()));
+        }
+
+        if self.parent_op.is_dry_run() {
+            self.parent_op.record([Step::WriteConfig {
+                path: self.config_path.clone(),
+                config: Box::new(config),
+            }]);
+            return Ok(0);
+        }
+
+        config.write(&self.config_path)?;
+        self.parent_op.insert_result("config_write", Ok(()));
+        std::fs::remove_dir_all(&staging).map_err(MigrateError::from)?;
+        crate::output::term(&format!(
+            "Imported {} file(s), skipped {}. Run `nedots update-local` to deploy them, or \
+             `nedots add-changes` to commit.",
+            copied,
+            skipped.len()
+        ));
+
+        Ok(0)
+    }
+
+    fn exit_code(&mut self) -> usize {
+        let result = self.operate();
+        self.parent_op.finish(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{chezmoi_name, user_entries, System};
+    use crate::_TESTS_DIR;
+    use std::{collections::BTreeSet, path::Path};
+
+    #[test]
+    /// Expects chezmoi attributes to become names & modes, and scripts &
+    /// templates to be refused.
+    fn chezmoi_names() {
+        assert_eq!(
+            chezmoi_name("dot_bashrc", false),
+            Ok((String::from(".bashrc"), 0o644))
+        );
+        assert_eq!(
+            chezmoi_name("private_dot_ssh", true),
+            Ok((String::from(".ssh"), 0o700))
+        );
+        assert_eq!(
+            chezmoi_name("executable_run.sh", false),
+            Ok((String::from("run.sh"), 0o755))
+        );
+        assert_eq!(
+            chezmoi_name("literal_dot_x", false),
+            Ok((String::from("dot_x"), 0o644))
+        );
+        assert!(chezmoi_name("dot_gitconfig.tmpl", false).is_err());
+        assert!(chezmoi_name("run_once_install.sh", false).is_err());
+    }
+
+    #[test]
+    /// Expects the most specific alternate that holds to win.
+    fn yadm_alternates() {
+        let system = System {
+            os: String::from("Linux"),
+            hostname: String::from("laptop"),
+            ..Default::default()
+        };
+        assert_eq!(system.score("default"), Some(0));
+        assert_eq!(system.score("os.Linux"), Some(1));
+        assert_eq!(system.score("os.Linux,h.laptop"), Some(2));
+        assert_eq!(system.score("os.Darwin"), None);
+        assert_eq!(system.score("template"), None);
+    }
+
+    #[test]
+    /// Expects directories to be tracked whole, unless something that isn't
+    /// being imported lives in them, or they don't exist yet.
+    fn fewest_entries() {
+        let home = Path::new(_TESTS_DIR).join("migrate");
+        let nvim = home.join(".config").join("nvim");
+        std::fs::create_dir_all(home.join(".config").join("fish")).unwrap();
+        std::fs::create_dir_all(nvim.join("lua")).unwrap();
+        std::fs::write(home.join(".config").join("fish").join("config.fish"), "").unwrap();
+        std::fs::write(home.join(".config").join("other"), "").unwrap();
+        std::fs::write(nvim.join("init.lua"), "").unwrap();
+
+        let files: BTreeSet<_> = [
+            ".bashrc",
+            ".config/fish/config.fish",
+            ".config/nvim/init.lua",
+            ".config/nvim/lua/plugins.lua",
+            ".local/share/fonts/a.ttf",
+            ".local/share/fonts/b.ttf",
+        ]
+        .iter()
+        .map(|f| Path::new(f).to_path_buf())
+        .collect();
+        let entries = user_entries(&home, &files).expect("Failed to find entries!");
+        assert_eq!(
+            entries,
+            [
+                ".bashrc",
+                ".config/fish",
+                ".config/nvim",
+                ".local/share/fonts/a.ttf",
+                ".local/share/fonts/b.ttf"
+            ]
+            .iter()
+            .map(|f| Path::new(f).to_path_buf())
+            .collect::<Vec<_>>()
+        );
+
+        std::fs::remove_dir_all(&home).expect("Failed to remove migrate dir!");
+    }
+}
@@ -1,12 +1,40 @@
+pub(crate) mod archive;
+pub(crate) mod backup;
+pub(crate) mod bootstrap;
+pub(crate) mod convert;
+pub(crate) mod diff;
+pub(crate) mod doctor;
+pub(crate) mod edit;
+pub(crate) mod filter;
 pub(crate) mod fs;
 pub(crate) mod git;
+pub(crate) mod init;
+pub(crate) mod log;
+pub(crate) mod migrate;
 pub(crate) mod op;
+pub(crate) mod pkg;
+pub(crate) mod plan;
+pub(crate) mod reformat;
+pub(crate) mod report;
+pub(crate) mod restore;
+pub(crate) mod skip;
+pub(crate) mod status;
+pub(crate) mod track;
+pub(crate) mod watch;
 
 use self::{
-    git::GitOp,
+    filter::Filter,
+    git::{GitError, GitOp},
     op::{Operation, OperationError},
+    pkg::InstallOp,
+    plan::Step,
+    skip::{SkipError, Skipped},
 };
-use crate::output::TerminalLogger;
+use crate::{
+    config::Packages,
+    output::{logger::Logger, verbosity::MinVerbosity, TerminalLogger},
+};
+use dialoguer::{theme::ColorfulTheme, Confirm, MultiSelect, Select};
 use fs::CopyOp;
 use std::path::{Path, PathBuf};
 
@@ -20,6 +48,16 @@ pub(crate) struct AddChanges<'remote> {
     /// The `CopyOperation`'s that need to run to copy local files to local
     /// repository.
     pub(crate) copy_ops: Vec<CopyOp>,
+
+    /// Push to this branch on the remote, defaults to the current branch.
+    pub(crate) branch: Option<String>,
+
+    /// Push changes to remote after committing.
+    pub(crate) push: bool,
+
+    /// `copy_ops` were filtered, so only their destinations are committed,
+    /// rather than everything that changed in the repository.
+    pub(crate) filtered: bool,
 }
 
 impl<'remote> AddChanges<'remote> {
@@ -28,52 +66,574 @@ impl<'remote> AddChanges<'remote> {
             parent_op: op,
             git_op: None,
             copy_ops: Vec::new(),
+            branch: None,
+            push: false,
+            filtered: false,
         }
     }
 
     pub(crate) fn to(mut self, to: PathBuf) -> Result<Self, OperationError> {
-        if let Some(_) = &self.git_op {
-            self.git_op = Some(self.git_op.unwrap().at_path(&to)?);
+        if let Some(go) = self.git_op {
+            self.git_op = Some(go.at_path(&to)?);
         } else {
             self.git_op = Some(GitOp::new().at_path(&to)?);
         }
         Ok(self)
     }
 
+    /// Copy `paths` into the repository, `paths` without a leading `/` are
+    /// relative to $HOME.
     pub(crate) fn copy_these(mut self, paths: Vec<PathBuf>) -> Result<Self, OperationError> {
         for p in &paths {
+            let live = crate::home::dir().join(p);
             self.copy_ops.push(
                 CopyOp::new()
-                    .from(&Path::new(env!("HOME")).join(&p))
-                    .to(&Path::new(self.git_op.as_ref().unwrap().path()?).join(&p)),
+                    .from(&live)
+                    .to(&fs::repo_path(self.git_op.as_ref().unwrap().path()?, &live)),
             );
         }
 
         Ok(self)
     }
 
-    pub(crate) fn to_remote(mut self, remote: &'remote str) -> Result<Self, OperationError> {
-        if let Some(_) = &self.git_op {
-            self.git_op = Some(self.git_op.unwrap().with_remote(remote));
+    pub(crate) fn with_remote(mut self, remote: &'remote str) -> Result<Self, OperationError> {
+        if let Some(go) = self.git_op {
+            self.git_op = Some(go.with_remote(remote));
         } else {
             self.git_op = Some(GitOp::new().with_remote(remote));
         }
 
         Ok(self)
     }
+
+    /// Assign `branch`.
+    pub(crate) fn on_branch(mut self, branch: Option<String>) -> Self {
+        self.branch = branch;
+        self
+    }
+
+    /// Assign `push`.
+    pub(crate) fn push(mut self, push: bool) -> Self {
+        self.push = push;
+        self
+    }
+
+    /// Only copy & commit the files `filter` allows.
+    pub(crate) fn filter(mut self, filter: &Filter) -> Result<Self, OperationError> {
+        if filter.is_empty() {
+            return Ok(self);
+        }
+
+        let mut ops = Vec::new();
+        for op in &self.copy_ops {
+            ops.append(&mut op.split(|from, _| filter.allows(from))?);
+        }
+        self.copy_ops = ops;
+        self.filtered = true;
+
+        Ok(self)
+    }
+
+    /// Destinations of `copy_ops`, relative to the repository at `repo`.
+    pub(crate) fn destinations(&self, repo: &Path) -> Result<Vec<PathBuf>, OperationError> {
+        let mut paths = Vec::new();
+        for op in &self.copy_ops {
+            for (_, to) in op.pairs()? {
+                paths.push(to.strip_prefix(repo).unwrap_or(&to).to_path_buf());
+            }
+        }
+
+        Ok(paths)
+    }
+
+    /// Record the copies, commit & push this operation would make, without
+    /// touching the repository.
+    pub(crate) fn plan(&mut self) -> Result<usize, OperationError> {
+        let repo = self
+            .git_op
+            .as_ref()
+            .ok_or(GitError::NoRepo)?
+            .path()?
+            .to_path_buf();
+        let destinations = self.destinations(&repo)?;
+        let go = self.git_op.as_mut().ok_or(GitError::NoRepo)?;
+
+        let mut paths = go.uncommitted()?;
+        if self.filtered {
+            paths.retain(|p| destinations.contains(p));
+        }
+        for op in &self.copy_ops {
+            for step in op.plan()? {
+                if let Step::Copy { to, .. } = &step {
+                    let rel = to.strip_prefix(&repo).unwrap_or(to).to_path_buf();
+                    if !paths.contains(&rel) {
+                        paths.push(rel);
+                    }
+                }
+                self.parent_op.record([step]);
+            }
+        }
+
+        if paths.is_empty() {
+            crate::output::term("Nothing to commit.");
+            return Ok(0);
+        }
+
+        let local = go.branch()?;
+        let head = go.head()?;
+        self.parent_op.record([
+            Step::Commit {
+                repo: repo.to_path_buf(),
+                branch: local.to_string(),
+                paths,
+            },
+            Step::MoveRef {
+                repo: repo.to_path_buf(),
+                name: format!("refs/heads/{}", local),
+                from: head,
+                to: None,
+            },
+        ]);
+
+        if self.push {
+            let remote = go.remote().to_string();
+            let branch = self.branch.clone().unwrap_or(local);
+            let upstream = go.upstream(&remote, &branch)?;
+            self.parent_op.record([
+                Step::Push {
+                    repo: repo.to_path_buf(),
+                    remote: remote.to_string(),
+                    branch: branch.to_string(),
+                },
+                Step::MoveRef {
+                    repo,
+                    name: format!("refs/remotes/{}/{}", remote, branch),
+                    from: upstream,
+                    to: None,
+                },
+            ]);
+        }
+
+        Ok(0)
+    }
+}
+
+/// The branch to update from, `branch` if asked for, else the checked out
+/// branch `local`. Only `local` can be fast-forwarded, so any other branch is
+/// refused.
+pub(crate) fn update_branch(branch: Option<&String>, local: String) -> Result<String, GitError> {
+    match branch {
+        Some(branch) if *branch != local => Err(GitError::NotCheckedOut {
+            branch: branch.to_string(),
+            local,
+        }),
+        _ => Ok(local),
+    }
+}
+
+#[derive(Debug)]
+/// A file `update-local --interactive` offers to update.
+pub(crate) struct Proposal {
+    /// The repository copy.
+    pub(crate) from: PathBuf,
+
+    /// The live file.
+    pub(crate) to: PathBuf,
+    pub(crate) sudo: bool,
+
+    /// Hash of `from`, remembered if the update's skipped.
+    pub(crate) hash: String,
+
+    /// `to` has changes of its own, which updating would lose.
+    pub(crate) local: bool,
 }
 
 /// Updates local files after pulling latest changes from remote.
-struct UpdateLocal<'remote> {
+pub(crate) struct UpdateLocal<'remote> {
+    pub(crate) parent_op: Operation<TerminalLogger>,
+
     /// The `GitOperation` responsible for `fetch` & `fast-forward`.
-    git_op: Option<GitOp<'remote>>,
+    pub(crate) git_op: Option<GitOp<'remote>>,
 
     /// The `CopyOperation`'s that need to be run after updating from remote.
-    copy_ops: Option<Vec<()>>, // TODO: `Vec` of `CopyOperation`.
+    pub(crate) copy_ops: Vec<CopyOp>,
+
+    /// Pull this branch from the remote, defaults to the current branch &
+    /// can't be any other, see `update_branch`.
+    pub(crate) branch: Option<String>,
+
+    /// Overwrite local files that differ from the repository.
+    pub(crate) force: bool,
+
+    /// Ask which files to update, instead of updating them all.
+    pub(crate) interactive: bool,
+}
+
+impl<'remote> UpdateLocal<'remote> {
+    pub(crate) fn new(op: Operation<TerminalLogger>) -> Self {
+        Self {
+            parent_op: op,
+            git_op: None,
+            copy_ops: Vec::new(),
+            branch: None,
+            force: false,
+            interactive: false,
+        }
+    }
+
+    pub(crate) fn from(mut self, from: PathBuf) -> Result<Self, OperationError> {
+        if let Some(go) = self.git_op {
+            self.git_op = Some(go.at_path(&from)?);
+        } else {
+            self.git_op = Some(GitOp::new().at_path(&from)?);
+        }
+        Ok(self)
+    }
+
+    pub(crate) fn with_remote(mut self, remote: &'remote str) -> Self {
+        if let Some(go) = self.git_op {
+            self.git_op = Some(go.with_remote(remote));
+        } else {
+            self.git_op = Some(GitOp::new().with_remote(remote));
+        }
+
+        self
+    }
+
+    /// Copy `paths` out of the repository, `paths` without a leading `/` are
+    /// relative to $HOME.
+    pub(crate) fn copy_these(self, paths: Vec<PathBuf>) -> Result<Self, OperationError> {
+        self.copy_these_with_sudo(paths, false)
+    }
+
+    /// Like `copy_these`, but copies with `sudo` since these paths are owned
+    /// by root.
+    pub(crate) fn copy_these_as_root(self, paths: Vec<PathBuf>) -> Result<Self, OperationError> {
+        self.copy_these_with_sudo(paths, true)
+    }
+
+    fn copy_these_with_sudo(
+        mut self,
+        paths: Vec<PathBuf>,
+        sudo: bool,
+    ) -> Result<Self, OperationError> {
+        for p in &paths {
+            let live = crate::home::dir().join(p);
+            self.copy_ops.push(
+                CopyOp::new()
+                    .from(&fs::repo_path(self.git_op.as_ref().unwrap().path()?, &live))
+                    .to(&live)
+                    .with_sudo(sudo)
+                    .backup_to(&backup::run_dir()),
+            );
+        }
+
+        Ok(self)
+    }
+
+    /// Assign `branch`.
+    pub(crate) fn on_branch(mut self, branch: Option<String>) -> Self {
+        self.branch = branch;
+        self
+    }
+
+    /// Assign `force`.
+    pub(crate) fn force(mut self, force: bool) -> Self {
+        self.force = force;
+        self
+    }
+
+    /// Only copy the files `filter` allows.
+    pub(crate) fn filter(mut self, filter: &Filter) -> Result<Self, OperationError> {
+        if filter.is_empty() {
+            return Ok(self);
+        }
+
+        let mut ops = Vec::new();
+        for op in &self.copy_ops {
+            ops.append(&mut op.split(|_, to| filter.allows(to))?);
+        }
+        self.copy_ops = ops;
+
+        Ok(self)
+    }
+
+    /// Assign `interactive`.
+    pub(crate) fn interactive(mut self, interactive: bool) -> Self {
+        self.interactive = interactive;
+        self
+    }
+
+    /// The files `choose` offers: those under `copy_ops` whose repository
+    /// copy is in `changed`, the files a fast-forward changed, that differ
+    /// from what's live & weren't skipped at this version. Files in `local`,
+    /// live files with changes of their own, are marked as such.
+    pub(crate) fn proposals(
+        &self,
+        changed: &[PathBuf],
+        local: &[PathBuf],
+        skipped: &mut Skipped,
+    ) -> Result<Vec<Proposal>, OperationError> {
+        let mut proposed = Vec::new();
+        for op in &self.copy_ops {
+            for (from, to) in op.pairs()? {
+                if !changed.contains(&from) || !from.exists() {
+                    continue;
+                }
+                if to.exists() && !fs::differs(&from, &to).map_err(fs::CopyError::from)? {
+                    skipped.forget(&to);
+                    continue;
+                }
+
+                let hash = plan::hash(&from)?;
+                if skipped.is_skipped(&to, &hash) {
+                    self.parent_op
+                        .log(&format!("Still skipping {}", to.display()))
+                        .ok();
+                    continue;
+                }
+                proposed.push(Proposal {
+                    local: local.contains(&to),
+                    from,
+                    to,
+                    sudo: op.sudo,
+                    hash,
+                });
+            }
+        }
+
+        Ok(proposed)
+    }
+
+    /// Ask which of the `proposals` to update, showing diffs on request, &
+    /// return a `CopyOp` for each one chosen. Files with local changes start
+    /// unselected. Files not chosen are remembered & not proposed again until
+    /// the repository version changes.
+    pub(crate) fn choose(
+        &self,
+        changed: &[PathBuf],
+        local: &[PathBuf],
+    ) -> Result<Vec<CopyOp>, OperationError> {
+        let mut skipped = Skipped::open(&skip::path())?;
+        let proposed = self.proposals(changed, local, &mut skipped)?;
+        if proposed.is_empty() {
+            skipped.write()?;
+            return Ok(Vec::new());
+        }
+
+        let theme = ColorfulTheme::default();
+        let items: Vec<String> = proposed
+            .iter()
+            .map(|p| match p.local {
+                true => format!("{} (changed locally)", p.to.display()),
+                false => p.to.display().to_string(),
+            })
+            .collect();
+        let preview = Confirm::with_theme(&theme)
+            .with_prompt(format!(
+                "{} file(s) would change, preview diffs?",
+                items.len()
+            ))
+            .default(false)
+            .interact()
+            .map_err(SkipError::from)?;
+        if preview {
+            loop {
+                let i = Select::with_theme(&theme)
+                    .with_prompt("Preview which file?")
+                    .items(&items)
+                    .item("Done")
+                    .default(0)
+                    .interact()
+                    .map_err(SkipError::from)?;
+                let p = match proposed.get(i) {
+                    Some(p) => p,
+                    None => break,
+                };
+
+                let (old, new) = (diff::read(&p.to)?, diff::read(&p.from)?);
+                crate::output::term(diff::patch(&p.to, &p.from, &old, &new).trim_end());
+            }
+        }
+
+        let chosen = MultiSelect::with_theme(&theme)
+            .with_prompt("Update which files? (space to toggle, enter to confirm)")
+            .items(&items)
+            .defaults(&proposed.iter().map(|p| !p.local).collect::<Vec<_>>())
+            .interact()
+            .map_err(SkipError::from)?;
+
+        let mut ops = Vec::new();
+        for (i, p) in proposed.into_iter().enumerate() {
+            if chosen.contains(&i) {
+                skipped.forget(&p.to);
+                ops.push(
+                    CopyOp::new()
+                        .from(&p.from)
+                        .to(&p.to)
+                        .with_sudo(p.sudo)
+                        .backup_to(&backup::run_dir()),
+                );
+            } else {
+                skipped.skip(&p.to, &p.hash);
+                crate::output::term(&format!("Skipping {}", p.to.display()));
+            }
+        }
+        skipped.write()?;
+
+        Ok(ops)
+    }
+
+    /// Record the fetch, fast-forward & copies this operation would make.
+    /// `branch` is fetched into its remote-tracking reference, without moving
+    /// the local branch, so copies are planned from the commit a fast-forward
+    /// would check out, & hashed as they are there.
+    pub(crate) fn plan(&mut self) -> Result<usize, OperationError> {
+        let go = self.git_op.as_mut().ok_or(GitError::NoRepo)?;
+        let local = update_branch(self.branch.as_ref(), go.branch()?)?;
+        let remote = go.remote().to_string();
+        let repo = go.path()?.to_path_buf();
+
+        let head = go.head()?;
+        let target = go.fetch(&local, &remote)?;
+        self.parent_op.record([Step::Fetch {
+            repo: repo.to_path_buf(),
+            remote: remote.to_string(),
+            branch: local.to_string(),
+        }]);
+        if Some(target) != head {
+            self.parent_op.record([Step::MoveRef {
+                repo: repo.to_path_buf(),
+                name: format!("refs/heads/{}", local),
+                from: head,
+                to: Some(target),
+            }]);
+        }
+
+        if !self.force {
+            let mut paths = Vec::new();
+            for op in &self.copy_ops {
+                paths.append(&mut op.would_overwrite()?);
+            }
+
+            if !paths.is_empty() {
+                return Err(OperationError::LocalChanges { paths });
+            }
+        }
+
+        for op in &self.copy_ops {
+            let (from, to) = match (&op.from, &op.to) {
+                (Some(from), Some(to)) => (from, to),
+                _ => continue,
+            };
+            let rel = from.strip_prefix(&repo).unwrap_or(from);
+            for (path, blob) in go.blobs_at(target, rel)? {
+                let live = fs::join(to, path.strip_prefix(rel).unwrap_or(&path));
+                let hash = blob.to_string();
+                if live.exists() && plan::hash(&live)? == hash {
+                    continue;
+                }
+
+                self.parent_op.record([Step::Copy {
+                    from: repo.join(path),
+                    to: live,
+                    sudo: op.sudo,
+                    hash,
+                }]);
+            }
+        }
+
+        Ok(0)
+    }
 }
 
 /// Installs a list of packages.
-struct InstallPackages {
-    /// `InstallOperation` that will install a list of packages.
-    install_op: Option<()>,
+pub(crate) struct InstallPackages {
+    pub(crate) parent_op: Operation<TerminalLogger>,
+
+    /// `InstallOperation`'s that will install a list of packages each.
+    pub(crate) install_ops: Vec<InstallOp>,
+}
+
+impl InstallPackages {
+    pub(crate) fn new(op: Operation<TerminalLogger>) -> Self {
+        Self {
+            parent_op: op,
+            install_ops: Vec::new(),
+        }
+    }
+
+    /// Queue an `InstallOp` for each of `groups` of `pkgs`, and the groups
+    /// they need, or the default groups if `groups` is empty.
+    pub(crate) fn install_these(
+        mut self,
+        pkgs: &Packages,
+        groups: &[String],
+        assume_yes: bool,
+    ) -> Result<Self, OperationError> {
+        let quiet = !self.parent_op.verbose_enough();
+        self.install_ops
+            .append(&mut pkg::install_ops(pkgs, groups, assume_yes, quiet)?);
+
+        Ok(self)
+    }
+
+    /// Record the commands this operation would run.
+    pub(crate) fn plan(&mut self) -> Result<usize, OperationError> {
+        for op in &self.install_ops {
+            self.parent_op.record(op.plan());
+        }
+
+        Ok(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{fs::CopyOp, op::Operation, plan, skip::Skipped, UpdateLocal};
+    use crate::{output::TerminalLogger, _TESTS_DIR};
+    use std::path::Path;
+
+    #[test]
+    /// Expects only files the fast-forward changed, that differ from what's
+    /// live & weren't skipped at this version, to be proposed, marking those
+    /// with local changes.
+    fn proposals() {
+        let base_path = Path::new(_TESTS_DIR).join("proposals");
+        let (repo, live) = (base_path.join("repo"), base_path.join("live"));
+        std::fs::create_dir_all(&repo).expect("Failed to create repo dir!");
+        std::fs::create_dir_all(&live).expect("Failed to create live dir!");
+
+        // `same` matches what's live, `old` wasn't changed by the fast-forward.
+        for (name, from, to) in [
+            ("new", "upstream", "base"),
+            ("both", "upstream", "mine"),
+            ("same", "upstream", "upstream"),
+            ("old", "base", "mine"),
+            ("skipped", "upstream", "base"),
+        ] {
+            std::fs::write(repo.join(name), from).expect("Failed to write repo file!");
+            std::fs::write(live.join(name), to).expect("Failed to write live file!");
+        }
+        let changed = ["new", "both", "same", "skipped", "gone"].map(|n| repo.join(n));
+        let local = [live.join("both"), live.join("old")];
+
+        let mut update = UpdateLocal::new(Operation::new().with_logging(TerminalLogger::new()));
+        update.copy_ops.push(CopyOp::new().from(&repo).to(&live));
+        let mut skipped = Skipped::open(&base_path.join("skipped.json")).unwrap();
+        let hash = plan::hash(&repo.join("skipped")).unwrap();
+        skipped.skip(&live.join("skipped"), &hash);
+
+        let mut proposed = update
+            .proposals(&changed, &local, &mut skipped)
+            .expect("Failed to propose!");
+        proposed.sort_by(|a, b| a.to.cmp(&b.to));
+        let names: Vec<(&str, bool)> = proposed
+            .iter()
+            .map(|p| (p.to.file_name().unwrap().to_str().unwrap(), p.local))
+            .collect();
+        assert_eq!(names, [("both", true), ("new", false)]);
+
+        std::fs::remove_dir_all(&base_path).expect("Failed to remove proposals dir!");
+    }
 }
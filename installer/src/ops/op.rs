@@ -1,11 +1,19 @@
-use super::{fs, git};
-use crate::output::{
-    logger::{Logger, Logs, Prints},
-    terminal::Terminal,
-    verbosity::{MinVerbosity, Verbose, Verbosity},
-    TerminalLogger,
+use super::{
+    archive, backup, edit, filter, fs, git, migrate, pkg,
+    plan::{self, Plan, Step},
+    report::{Report, StepReport},
+    skip, watch,
 };
-use std::collections::HashMap;
+use crate::{
+    config::ConfigError,
+    output::{
+        logger::{Logger, Logs, Prints},
+        terminal::Terminal,
+        verbosity::{MinVerbosity, Verbose, Verbosity},
+        TerminalLogger,
+    },
+};
+use std::{collections::HashMap, path::PathBuf};
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -15,14 +23,70 @@ pub(crate) enum OperationError {
 
     #[error(transparent)]
     Copy(#[from] fs::CopyError),
+
+    #[error(transparent)]
+    Install(#[from] pkg::InstallError),
+
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+
+    #[error(transparent)]
+    Plan(#[from] plan::PlanError),
+
+    #[error(transparent)]
+    Backup(#[from] backup::BackupError),
+
+    #[error(transparent)]
+    Watch(#[from] watch::WatchError),
+
+    #[error(transparent)]
+    Edit(#[from] edit::EditError),
+
+    #[error(transparent)]
+    Skip(#[from] skip::SkipError),
+
+    #[error(transparent)]
+    Filter(#[from] filter::FilterError),
+
+    #[error(transparent)]
+    Archive(#[from] archive::ArchiveError),
+
+    #[error(transparent)]
+    Migrate(#[from] migrate::MigrateError),
+
+    #[error("Local changes would be overwritten, use --force to overwrite: {paths:#?}")]
+    /// Local files differ from the repository & would be lost.
+    LocalChanges { paths: Vec<PathBuf> },
+}
+
+impl OperationError {
+    /// Stable name for the kind of error, for `--output json`.
+    pub(crate) fn kind(&self) -> &'static str {
+        match self {
+            OperationError::Git(_) => "git",
+            OperationError::Copy(_) => "copy",
+            OperationError::Install(_) => "install",
+            OperationError::Config(_) => "config",
+            OperationError::Plan(_) => "plan",
+            OperationError::Backup(_) => "backup",
+            OperationError::Watch(_) => "watch",
+            OperationError::Edit(_) => "edit",
+            OperationError::Skip(_) => "skip",
+            OperationError::Filter(_) => "filter",
+            OperationError::Archive(_) => "archive",
+            OperationError::Migrate(_) => "migrate",
+            OperationError::LocalChanges { .. } => "local_changes",
+        }
+    }
 }
 
 pub(crate) trait Operate {
     /// Run the `Operation`.
-    fn operate(&self) -> Result<usize, OperationError>;
+    fn operate(&mut self) -> Result<usize, OperationError>;
 
-    /// Return this exit code so the program can terminate correctly.
-    fn exit_code(&self) -> usize;
+    /// Run the `Operation` & return this exit code so the program can
+    /// terminate correctly.
+    fn exit_code(&mut self) -> usize;
 }
 
 /// `Operation` will store information pertaining to the operation runtime.
@@ -33,6 +97,21 @@ pub(crate) struct Operation<Logger> {
     /// `Vec` of `Result` for this `Operation`, a &str will be used to identify
     /// the key-value pair, e.g. `git_add` or `copy_{path}`.
     results: Option<HashMap<&'static str, Result<(), OperationError>>>,
+
+    /// Record `Step`s in `plan` instead of taking them.
+    dry_run: bool,
+
+    /// `Step`s recorded during a dry run.
+    plan: Vec<Step>,
+
+    /// Write the recorded `plan` here rather than printing it.
+    plan_to: Option<PathBuf>,
+
+    /// Print a JSON `report` when finished instead of human readable output.
+    json: bool,
+
+    /// Paths, commits & packages this `Operation` reports on.
+    report: Report,
 }
 
 impl<Logger> Operation<Logger> {
@@ -40,9 +119,55 @@ impl<Logger> Operation<Logger> {
         Self {
             logger: None,
             results: Some(HashMap::new()),
+            dry_run: false,
+            plan: Vec::new(),
+            plan_to: None,
+            json: false,
+            report: Report::default(),
         }
     }
 
+    /// Assign `dry_run`.
+    pub(crate) fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Plan, writing the plan to `path` when finished.
+    pub(crate) fn plan_to(mut self, path: PathBuf) -> Self {
+        self.dry_run = true;
+        self.plan_to = Some(path);
+        self
+    }
+
+    pub(crate) fn is_dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    /// Assign `json`.
+    pub(crate) fn json(mut self, json: bool) -> Self {
+        self.json = json;
+        self
+    }
+
+    pub(crate) fn is_json(&self) -> bool {
+        self.json
+    }
+
+    /// Borrow the `Report` to add paths, commits, packages or details to.
+    pub(crate) fn report(&mut self) -> &mut Report {
+        &mut self.report
+    }
+
+    pub(crate) fn plan(&self) -> &[Step] {
+        &self.plan
+    }
+
+    /// Record `steps` that would have been taken.
+    pub(crate) fn record(&mut self, steps: impl IntoIterator<Item = Step>) {
+        self.plan.extend(steps);
+    }
+
     pub(crate) fn results(&self) -> &HashMap<&str, Result<(), OperationError>> {
         self.results.as_ref().unwrap()
     }
@@ -61,6 +186,78 @@ impl Operation<TerminalLogger> {
         self.logger = Some(logger);
         self
     }
+
+    /// Report on the `results` of a finished `Operation` & turn `result` into
+    /// an exit code. During a dry run, the recorded plan is printed too.
+    pub(crate) fn finish(&self, result: Result<usize, OperationError>) -> usize {
+        for (key, r) in self.results() {
+            if r.is_ok() {
+                self.log(&format!("{}: Ok", key)).ok();
+            }
+        }
+
+        if self.json {
+            let code = match &result {
+                Ok(code) => *code,
+                Err(_) => 1,
+            };
+            let report = Report {
+                ok: result.is_ok(),
+                exit_code: code,
+                dry_run: self.dry_run,
+                results: self
+                    .results
+                    .iter()
+                    .flatten()
+                    .map(|(key, r)| {
+                        let report = StepReport {
+                            ok: r.is_ok(),
+                            error: r.as_ref().err().map(|e| e.into()),
+                        };
+                        (*key, report)
+                    })
+                    .collect(),
+                plan: self.plan.clone(),
+                error: result.as_ref().err().map(|e| e.into()),
+                ..self.report.clone()
+            };
+            if let Err(e) = report.print() {
+                self.log_error(&e.to_string()).ok();
+            }
+        }
+
+        if let (Some(path), Ok(_)) = (&self.plan_to, &result) {
+            let plan = Plan {
+                steps: self.plan.clone(),
+            };
+            if let Err(e) = plan.write(path) {
+                self.log_error(&e.to_string()).ok();
+                return 1;
+            }
+
+            crate::output::term(&format!(
+                "Wrote {} step(s) to {}, run `nedots apply {}` to take them.",
+                plan.steps.len(),
+                path.display(),
+                path.display()
+            ));
+        } else if self.dry_run && self.plan.is_empty() {
+            crate::output::term("Dry run, nothing to do.");
+        } else if self.dry_run {
+            crate::output::term("Dry run, nothing was changed. Would:");
+            for step in self.plan() {
+                crate::output::term(&format!("  {}", step));
+            }
+        }
+
+        match result {
+            Ok(code) => code,
+            Err(e) => {
+                self.log_error(&e.to_string()).ok();
+                1
+            }
+        }
+    }
 }
 
 impl Logs<TerminalLogger> for Operation<TerminalLogger> {
@@ -84,7 +281,10 @@ impl MinVerbosity for Operation<TerminalLogger> {
 impl Logger<TerminalLogger, dyn Terminal, &str> for Operation<TerminalLogger> {
     fn log(&self, msg: &str) -> std::io::Result<()> {
         if self.verbose_enough() {
-            return self.logger().write_line(msg);
+            return match crate::output::is_machine_readable() {
+                true => self.logger().write_error(msg),
+                false => self.logger().write_line(msg),
+            };
         }
 
         Ok(())
@@ -0,0 +1,487 @@
+use super::{op::OperationError, plan::Step, report::PackageReport};
+use crate::config::Packages;
+use nix::unistd::geteuid;
+use std::{collections::HashMap, process::Command};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+/// Errors thrown during an `InstallOp`.
+pub(crate) enum InstallError {
+    #[error("`{cmd}` exited unsuccessfully ({code:?}).")]
+    /// The package manager returned a non-zero exit code.
+    Failed { cmd: String, code: Option<i32> },
+
+    #[error("Unsupported distribution: {id}")]
+    /// We don't know which package manager to use.
+    UnsupportedDistro { id: String },
+
+    #[error(transparent)]
+    /// A wrapper around IO errors.
+    IoError(#[from] std::io::Error),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Supported package managers.
+pub(crate) enum PackageManager {
+    Dnf,
+    Zypper,
+    Pacman,
+    Apt,
+    Flatpak,
+}
+
+impl PackageManager {
+    /// Binary the package manager is run as.
+    pub(crate) fn program(&self) -> &'static str {
+        match self {
+            Self::Dnf => "dnf",
+            Self::Zypper => "zypper",
+            Self::Pacman => "pacman",
+            Self::Apt => "apt-get",
+            Self::Flatpak => "flatpak",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+/// A distribution, as described by `/etc/os-release`.
+pub(crate) struct Distro {
+    /// `ID`, e.g. `opensuse-tumbleweed`.
+    pub(crate) id: String,
+
+    /// `ID_LIKE`, e.g. `opensuse suse`.
+    pub(crate) like: Vec<String>,
+
+    /// `VERSION_ID`, if it has one.
+    pub(crate) version: String,
+}
+
+impl Distro {
+    /// The distribution we're running on.
+    pub(crate) fn current() -> Result<Self, std::io::Error> {
+        Ok(Self::from_os_release(&os_release()?))
+    }
+
+    fn from_os_release(os: &HashMap<String, String>) -> Self {
+        let field = |key: &str| os.get(key).cloned().unwrap_or_default();
+        Self {
+            id: field("ID"),
+            like: field("ID_LIKE")
+                .split_whitespace()
+                .map(str::to_string)
+                .collect(),
+            version: field("VERSION_ID"),
+        }
+    }
+
+    /// `id`, then each of `like`, most specific first.
+    pub(crate) fn ids(&self) -> impl Iterator<Item = &str> {
+        std::iter::once(self.id.as_str()).chain(self.like.iter().map(String::as_str))
+    }
+
+    /// The package manager this distribution, or the first it's like that
+    /// we know, uses.
+    ///
+    /// ### Errors
+    /// Returns `InstallError::UnsupportedDistro` if we know none of them.
+    pub(crate) fn manager(&self) -> Result<PackageManager, InstallError> {
+        self.ids()
+            .find_map(|id| match id {
+                "fedora" | "rhel" | "centos" => Some(PackageManager::Dnf),
+                "suse" | "opensuse" => Some(PackageManager::Zypper),
+                id if id.starts_with("opensuse") => Some(PackageManager::Zypper),
+                "arch" => Some(PackageManager::Pacman),
+                "debian" | "ubuntu" => Some(PackageManager::Apt),
+                _ => None,
+            })
+            .ok_or_else(|| InstallError::UnsupportedDistro {
+                id: self.id.to_string(),
+            })
+    }
+}
+
+#[derive(Debug)]
+/// Installs a list of packages via a `PackageManager`.
+pub(crate) struct InstallOp {
+    /// Package manager to install with.
+    pub(crate) manager: PackageManager,
+
+    /// Flatpak remote `(name, url)` to add before installing.
+    pub(crate) remote: Option<(String, String)>,
+
+    /// Packages to install.
+    pub(crate) pkgs: Vec<String>,
+
+    /// Answer yes to any prompts.
+    pub(crate) assume_yes: bool,
+
+    /// Pass quiet flags to the package manager.
+    pub(crate) quiet: bool,
+}
+
+impl InstallOp {
+    /// Construct a new `InstallOp`.
+    pub(crate) fn new(manager: PackageManager) -> Self {
+        Self {
+            manager,
+            remote: None,
+            pkgs: Vec::new(),
+            assume_yes: false,
+            quiet: false,
+        }
+    }
+
+    /// Assign Flatpak `remote`, it'll be added if it doesn't exist yet.
+    pub(crate) fn with_remote(mut self, name: &str, url: &str) -> Self {
+        self.remote = Some((name.to_string(), url.to_string()));
+        self
+    }
+
+    /// Assign `pkgs`.
+    pub(crate) fn packages(mut self, pkgs: &[String]) -> Self {
+        self.pkgs = pkgs.to_vec();
+        self
+    }
+
+    /// Assign `assume_yes`.
+    pub(crate) fn assume_yes(mut self, assume_yes: bool) -> Self {
+        self.assume_yes = assume_yes;
+        self
+    }
+
+    /// Assign `quiet`.
+    pub(crate) fn quiet(mut self, quiet: bool) -> Self {
+        self.quiet = quiet;
+        self
+    }
+
+    /// The commands this `InstallOp` will run, in order.
+    pub(crate) fn commands(&self) -> Vec<Command> {
+        let mut cmds = Vec::new();
+        match self.manager {
+            PackageManager::Dnf | PackageManager::Apt => {
+                let mut cmd = privileged(self.manager.program());
+                cmd.arg("install");
+                if self.assume_yes {
+                    cmd.arg("-y");
+                }
+                if self.quiet {
+                    cmd.arg("-q");
+                }
+                cmd.args(&self.pkgs);
+                cmds.push(cmd);
+            }
+            PackageManager::Zypper => {
+                // Global options come before the command.
+                let mut cmd = privileged("zypper");
+                if self.assume_yes {
+                    cmd.arg("--non-interactive");
+                }
+                if self.quiet {
+                    cmd.arg("--quiet");
+                }
+                cmd.arg("install").args(&self.pkgs);
+                cmds.push(cmd);
+            }
+            PackageManager::Pacman => {
+                let mut cmd = privileged("pacman");
+                cmd.args(["-S", "--needed"]);
+                if self.assume_yes {
+                    cmd.arg("--noconfirm");
+                }
+                if self.quiet {
+                    cmd.arg("-q");
+                }
+                cmd.args(&self.pkgs);
+                cmds.push(cmd);
+            }
+            PackageManager::Flatpak => {
+                let remote = match &self.remote {
+                    Some((name, url)) => {
+                        let mut cmd = Command::new("flatpak");
+                        cmd.args(["remote-add", "--if-not-exists", name, url]);
+                        cmds.push(cmd);
+                        Some(name)
+                    }
+                    None => None,
+                };
+
+                let mut cmd = Command::new("flatpak");
+                cmd.arg("install");
+                if self.assume_yes {
+                    cmd.arg("-y");
+                }
+                cmd.args(remote).args(&self.pkgs);
+                cmds.push(cmd);
+            }
+        }
+
+        cmds
+    }
+
+    /// The `Step`s an `install` would take.
+    pub(crate) fn plan(&self) -> Vec<Step> {
+        if self.pkgs.is_empty() {
+            return Vec::new();
+        }
+
+        self.commands()
+            .iter()
+            .map(|cmd| Step::Run {
+                args: std::iter::once(cmd.get_program())
+                    .chain(cmd.get_args())
+                    .map(|s| s.to_string_lossy().to_string())
+                    .collect(),
+            })
+            .collect()
+    }
+
+    /// Report on this operation's packages for `--output json`.
+    pub(crate) fn report(&self, installed: bool) -> PackageReport {
+        PackageReport {
+            manager: format!("{:?}", self.manager).to_lowercase(),
+            packages: self.pkgs.clone(),
+            installed,
+        }
+    }
+
+    /// Do the install.
+    pub(crate) fn install(&self) -> Result<(), InstallError> {
+        if self.pkgs.is_empty() {
+            return Ok(());
+        }
+
+        for mut cmd in self.commands() {
+            let status = cmd.status()?;
+            if !status.success() {
+                return Err(InstallError::Failed {
+                    cmd: display(&cmd),
+                    code: status.code(),
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// An `InstallOp` for each of `groups`, after the groups they need, with
+/// the packages listed for this distribution. The default groups are
+/// installed if `groups` is empty. On Fedora, RPM Fusion is configured before
+/// anything else.
+pub(crate) fn install_ops(
+    pkgs: &Packages,
+    groups: &[String],
+    assume_yes: bool,
+    quiet: bool,
+) -> Result<Vec<InstallOp>, OperationError> {
+    let distro = Distro::current().map_err(InstallError::from)?;
+    install_ops_for(&distro, pkgs, groups, assume_yes, quiet)
+}
+
+/// `install_ops` on `distro`. Its package manager is only needed if a group
+/// lists packages for it, so flatpaks still install on distributions we
+/// don't support.
+fn install_ops_for(
+    distro: &Distro,
+    pkgs: &Packages,
+    groups: &[String],
+    assume_yes: bool,
+    quiet: bool,
+) -> Result<Vec<InstallOp>, OperationError> {
+    let lists: Vec<Vec<String>> = pkgs
+        .resolve(groups)?
+        .into_iter()
+        .map(|(_, group)| group.pkgs.for_ids(distro.ids()).to_vec())
+        .collect();
+
+    let mut ops = Vec::new();
+    if lists.iter().any(|list| !list.is_empty()) {
+        let manager = distro.manager()?;
+        let rpmfusion = (distro.id == "fedora").then(|| rpmfusion_pkgs(&distro.version));
+        for list in rpmfusion.into_iter().chain(lists) {
+            ops.push(
+                InstallOp::new(manager)
+                    .packages(&list)
+                    .assume_yes(assume_yes)
+                    .quiet(quiet),
+            );
+        }
+    }
+
+    for fp in &pkgs.flatpaks {
+        ops.push(
+            InstallOp::new(PackageManager::Flatpak)
+                .with_remote(&fp.remote, &fp.url)
+                .packages(&fp.pkgs)
+                .assume_yes(assume_yes),
+        );
+    }
+
+    Ok(ops)
+}
+
+/// A `Command` that runs `program` via `sudo` when we aren't root already.
+fn privileged(program: &str) -> Command {
+    if geteuid().is_root() {
+        Command::new(program)
+    } else {
+        let mut cmd = Command::new("sudo");
+        cmd.arg(program);
+        cmd
+    }
+}
+
+/// Render `cmd` like it would be typed into a shell.
+pub(crate) fn display(cmd: &Command) -> String {
+    std::iter::once(cmd.get_program())
+        .chain(cmd.get_args())
+        .map(|s| s.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Parse `/etc/os-release` into key-value pairs, with quotes removed.
+pub(crate) fn os_release() -> Result<HashMap<String, String>, std::io::Error> {
+    Ok(parse_os_release(&std::fs::read_to_string(
+        "/etc/os-release",
+    )?))
+}
+
+fn parse_os_release(s: &str) -> HashMap<String, String> {
+    s.lines()
+        .filter_map(|l| l.split_once('='))
+        .map(|(k, v)| (k.to_string(), v.trim_matches('"').to_string()))
+        .collect()
+}
+
+/// RPM Fusion free & nonfree release packages for Fedora `version`.
+pub(crate) fn rpmfusion_pkgs(version: &str) -> Vec<String> {
+    ["free", "nonfree"]
+        .iter()
+        .map(|r| {
+            format!(
+                "https://mirrors.rpmfusion.org/{0}/fedora/rpmfusion-{0}-release-{1}.noarch.rpm",
+                r, version
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{display, install_ops_for, parse_os_release, Distro, InstallOp, PackageManager};
+    use crate::config::{FlatpakRemote, Packages};
+
+    #[test]
+    /// Expects `dnf` flags to be passed through.
+    fn dnf_command() {
+        let op = InstallOp::new(PackageManager::Dnf)
+            .packages(&["fish".to_string(), "git".to_string()])
+            .assume_yes(true)
+            .quiet(true);
+        let cmds = op.commands();
+        assert_eq!(cmds.len(), 1);
+        assert!(display(&cmds[0]).ends_with("dnf install -y -q fish git"));
+    }
+
+    #[test]
+    /// Expects the remote to be added before installing from it.
+    fn flatpak_commands() {
+        let op = InstallOp::new(PackageManager::Flatpak)
+            .with_remote("flathub", "https://flathub.org/repo/flathub.flatpakrepo")
+            .packages(&["org.gimp.GIMP".to_string()]);
+        let cmds: Vec<String> = op.commands().iter().map(display).collect();
+        assert_eq!(
+            cmds,
+            [
+                "flatpak remote-add --if-not-exists flathub https://flathub.org/repo/flathub.flatpakrepo",
+                "flatpak install flathub org.gimp.GIMP"
+            ]
+        );
+    }
+
+    #[test]
+    /// Expects zypper's global options before `install`, and pacman to skip
+    /// what's installed already.
+    fn other_commands() {
+        let pkgs = ["fish".to_string()];
+        let zypper = InstallOp::new(PackageManager::Zypper)
+            .packages(&pkgs)
+            .assume_yes(true)
+            .quiet(true);
+        assert!(display(&zypper.commands()[0])
+            .ends_with("zypper --non-interactive --quiet install fish"));
+
+        let pacman = InstallOp::new(PackageManager::Pacman)
+            .packages(&pkgs)
+            .assume_yes(true);
+        assert!(display(&pacman.commands()[0]).ends_with("pacman -S --needed --noconfirm fish"));
+
+        let apt = InstallOp::new(PackageManager::Apt).packages(&pkgs);
+        assert!(display(&apt.commands()[0]).ends_with("apt-get install fish"));
+    }
+
+    #[test]
+    /// Expects the package manager to be picked by `ID`, then `ID_LIKE`.
+    fn distros() {
+        let distro = |os: &str| Distro::from_os_release(&parse_os_release(os));
+
+        let tumbleweed = distro("ID=\"opensuse-tumbleweed\"\nID_LIKE=\"opensuse suse\"\n");
+        assert_eq!(
+            tumbleweed.ids().collect::<Vec<_>>(),
+            ["opensuse-tumbleweed", "opensuse", "suse"]
+        );
+        assert_eq!(tumbleweed.manager().unwrap(), PackageManager::Zypper);
+        assert_eq!(
+            distro("ID=manjaro\nID_LIKE=arch\n").manager().unwrap(),
+            PackageManager::Pacman
+        );
+        assert_eq!(
+            distro("ID=pop\nID_LIKE=\"ubuntu debian\"\n")
+                .manager()
+                .unwrap(),
+            PackageManager::Apt
+        );
+        assert_eq!(distro("ID=fedora\nVERSION_ID=36\n").version, "36");
+        assert!(distro("ID=gentoo\n").manager().is_err());
+    }
+
+    #[test]
+    /// Expects flatpaks to install on a distribution we don't have a package
+    /// manager for, as long as no group lists packages for it.
+    fn flatpaks_anywhere() {
+        let gentoo = Distro {
+            id: String::from("gentoo"),
+            ..Default::default()
+        };
+        let mut pkgs: Packages = serde_json::from_value(serde_json::json!({
+            "core": { "fedora": ["fish"] },
+            "flatpak": []
+        }))
+        .expect("Failed to deserialize!");
+        pkgs.flatpaks.push(FlatpakRemote {
+            remote: String::from("flathub"),
+            url: String::from("https://flathub.org/repo/flathub.flatpakrepo"),
+            pkgs: vec![String::from("org.gimp.GIMP")],
+        });
+
+        let ops = install_ops_for(&gentoo, &pkgs, &[], false, false).expect("Failed to plan!");
+        assert_eq!(ops.len(), 1);
+        assert_eq!(ops[0].manager, PackageManager::Flatpak);
+
+        let gentoo_pkgs = serde_json::json!({ "core": { "gentoo": ["fish"] }, "flatpak": [] });
+        let pkgs: Packages = serde_json::from_value(gentoo_pkgs).unwrap();
+        assert!(install_ops_for(&gentoo, &pkgs, &[], false, false).is_err());
+    }
+
+    #[test]
+    /// Expects `/etc/os-release` style lines to be split into keys & values,
+    /// with quotes removed.
+    fn os_release() {
+        let map = parse_os_release("NAME=\"Fedora Linux\"\nID=fedora\nVERSION_ID=36\n");
+        assert_eq!(map.get("ID").unwrap(), "fedora");
+        assert_eq!(map.get("NAME").unwrap(), "Fedora Linux");
+        assert_eq!(map.get("VERSION_ID").unwrap(), "36");
+    }
+}
@@ -0,0 +1,528 @@
+use super::{
+    archive, backup,
+    fs::{CopyError, CopyOp},
+    git::GitOp,
+    op::{Operate, Operation, OperationError},
+    pkg::InstallError,
+};
+use crate::{
+    config::Config,
+    output::{logger::Logger, TerminalLogger},
+};
+use git2::{ObjectType, Oid};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::{
+    fmt::Display,
+    fs::File,
+    path::{Path, PathBuf},
+    process::Command,
+};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+/// Errors thrown while reading, writing or applying a `Plan`.
+pub(crate) enum PlanError {
+    #[error("Failed to deserialize plan, is it badly formatted?")]
+    /// Serde error.
+    DeserializeError,
+
+    #[error("Failed to serialize plan.")]
+    /// Serde error.
+    SerializeError,
+
+    #[error("Files changed since planning, plan again: {paths:#?}")]
+    /// A file the plan copies no longer has the content it was planned with.
+    Changed { paths: Vec<PathBuf> },
+
+    #[error("`{name}` moved since planning, plan again.")]
+    /// A branch the plan moves no longer points where it did.
+    Moved { name: String },
+
+    #[error("A `run` step has no program, plan again.")]
+    /// A `Step::Run` with empty `args`, e.g. from an edited plan file.
+    NoProgram,
+
+    #[error(transparent)]
+    /// A wrapper around IO errors.
+    IoError(#[from] std::io::Error),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+/// A single action an `Operation` would take, recorded instead of being
+/// taken during a dry run.
+pub(crate) enum Step {
+    /// Copy `from` to `to`, via `sudo` when `sudo` is set. `hash` is the
+    /// content of `from` when planned.
+    Copy {
+        from: PathBuf,
+        to: PathBuf,
+        sudo: bool,
+        hash: String,
+    },
+
+    /// Remove `path` from the repository.
+    Remove { path: PathBuf },
+
+    /// Initialise a repository at `path`.
+    Init { path: PathBuf },
+
+    /// Clone `url` into `path`.
+    Clone { url: String, path: PathBuf },
+
+    /// Write `config` to `path`.
+    WriteConfig { path: PathBuf, config: Box<Config> },
+
+    /// Write `content` to `path`.
+    WriteFile { path: PathBuf, content: String },
+
+    /// Write `files`, relative to `repo`, & `config` to an archive at `path`.
+    Archive {
+        repo: PathBuf,
+        files: Vec<PathBuf>,
+        config: Box<Config>,
+        path: PathBuf,
+    },
+
+    /// Commit `paths`, relative to `repo`, on `branch`.
+    Commit {
+        repo: PathBuf,
+        branch: String,
+        paths: Vec<PathBuf>,
+    },
+
+    /// Fetch `branch` from `remote`.
+    Fetch {
+        repo: PathBuf,
+        remote: String,
+        branch: String,
+    },
+
+    /// Push `branch` to `remote`.
+    Push {
+        repo: PathBuf,
+        remote: String,
+        branch: String,
+    },
+
+    /// Move reference `name` from `from` to `to`. `None` for `from` means the
+    /// reference doesn't exist yet, and for `to` means the commit this plan
+    /// creates.
+    MoveRef {
+        repo: PathBuf,
+        name: String,
+        #[serde(with = "oid")]
+        from: Option<Oid>,
+        #[serde(with = "oid")]
+        to: Option<Oid>,
+    },
+
+    /// Run `args`, the first being the program.
+    Run { args: Vec<String> },
+}
+
+impl Display for Step {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let short = |oid: &Option<Oid>, none: &str| match oid {
+            Some(oid) => oid.to_string()[..7].to_string(),
+            None => none.to_string(),
+        };
+
+        match self {
+            Step::Copy { from, to, sudo, .. } => write!(
+                f,
+                "copy    {} -> {}{}",
+                from.display(),
+                to.display(),
+                if *sudo { " (sudo)" } else { "" }
+            ),
+            Step::Remove { path } => write!(f, "remove  {}", path.display()),
+            Step::Init { path } => write!(f, "init    {}", path.display()),
+            Step::Clone { url, path } => write!(f, "clone   {} -> {}", url, path.display()),
+            Step::WriteConfig { path, .. } => write!(f, "write   {}", path.display()),
+            Step::WriteFile { path, .. } => write!(f, "write   {}", path.display()),
+            Step::Archive { files, path, .. } => {
+                write!(f, "archive {} file(s) -> {}", files.len(), path.display())
+            }
+            Step::Commit { branch, paths, .. } => write!(
+                f,
+                "commit  {} file(s) on {}: {}",
+                paths.len(),
+                branch,
+                paths
+                    .iter()
+                    .map(|p| p.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Step::Fetch { remote, branch, .. } => write!(f, "fetch   {}/{}", remote, branch),
+            Step::Push { remote, branch, .. } => write!(f, "push    {}/{}", remote, branch),
+            Step::MoveRef { name, from, to, .. } => write!(
+                f,
+                "ref     {} {} -> {}",
+                name,
+                short(from, "(none)"),
+                short(to, "(new commit)")
+            ),
+            Step::Run { args } => write!(f, "run     {}", args.join(" ")),
+        }
+    }
+}
+
+/// `Option<Oid>` as an optional hex string.
+mod oid {
+    use git2::Oid;
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub(super) fn serialize<S: Serializer>(oid: &Option<Oid>, s: S) -> Result<S::Ok, S::Error> {
+        match oid {
+            Some(oid) => s.serialize_some(&oid.to_string()),
+            None => s.serialize_none(),
+        }
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Option<Oid>, D::Error> {
+        match Option::<String>::deserialize(d)? {
+            Some(s) => Oid::from_str(&s).map(Some).map_err(D::Error::custom),
+            None => Ok(None),
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+/// Every `Step` an operation would take, written by `nedots plan` & read by
+/// `nedots apply`.
+pub(crate) struct Plan {
+    pub(crate) steps: Vec<Step>,
+}
+
+impl Plan {
+    /// Read & deserialize a plan from `path`.
+    pub(crate) fn read(path: &Path) -> Result<Self, PlanError> {
+        serde_json::from_reader(File::open(path)?).or(Err(PlanError::DeserializeError))
+    }
+
+    /// Serialize & write the plan to `path`.
+    pub(crate) fn write(&self, path: &Path) -> Result<(), PlanError> {
+        let json = serde_json::to_string_pretty(self).or(Err(PlanError::SerializeError))?;
+        Ok(std::fs::write(path, json + "\n")?)
+    }
+
+    /// Check the plan still describes the system: every file to copy has the
+    /// content it was planned with, and every branch is where it was. Files
+    /// copied after a branch moves were planned from where it moves to, so
+    /// they're checked by `Apply` as they're copied instead.
+    pub(crate) fn verify(&self) -> Result<(), OperationError> {
+        let mut changed = Vec::new();
+        let mut moved = false;
+        for step in &self.steps {
+            match step {
+                Step::Copy { from, hash: h, .. } if !moved && !unchanged(from, h)? => {
+                    changed.push(from.to_path_buf());
+                }
+                Step::Run { args } if args.is_empty() => return Err(PlanError::NoProgram.into()),
+                Step::MoveRef {
+                    repo, name, from, ..
+                } if name.starts_with("refs/heads/") => {
+                    moved = true;
+                    let mut go = GitOp::new().at_path(repo)?;
+                    let current = go
+                        .repo()?
+                        .find_reference(name)
+                        .ok()
+                        .and_then(|r| r.target());
+                    if &current != from {
+                        return Err(PlanError::Moved {
+                            name: name.to_string(),
+                        }
+                        .into());
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if !changed.is_empty() {
+            return Err(PlanError::Changed { paths: changed }.into());
+        }
+
+        Ok(())
+    }
+}
+
+/// Whether `path` still has the content hashed as `h`.
+fn unchanged(path: &Path, h: &str) -> Result<bool, CopyError> {
+    Ok(path.exists() && hash(path)? == h)
+}
+
+/// Hash of the content of `path`, the same hash `git` would give it.
+pub(crate) fn hash(path: &Path) -> Result<String, CopyError> {
+    Oid::hash_object(ObjectType::Blob, &std::fs::read(path)?)
+        .map(|oid| oid.to_string())
+        .map_err(|e| CopyError::IoError(std::io::Error::other(e)))
+}
+
+/// Takes exactly the `Step`s of a `Plan`.
+pub(crate) struct Apply {
+    pub(crate) parent_op: Operation<TerminalLogger>,
+
+    pub(crate) plan: Plan,
+}
+
+impl Apply {
+    pub(crate) fn new(op: Operation<TerminalLogger>) -> Self {
+        Self {
+            parent_op: op,
+            plan: Plan::default(),
+        }
+    }
+
+    /// Read the plan at `path`.
+    pub(crate) fn from(mut self, path: &Path) -> Result<Self, OperationError> {
+        self.plan = Plan::read(path)?;
+        Ok(self)
+    }
+
+    /// Take a single `step`.
+    fn take(&mut self, step: &Step) -> Result<(), OperationError> {
+        match step {
+            Step::Copy {
+                from,
+                to,
+                sudo,
+                hash,
+            } => {
+                if !unchanged(from, hash)? {
+                    return Err(PlanError::Changed {
+                        paths: vec![from.to_path_buf()],
+                    }
+                    .into());
+                }
+
+                CopyOp::new()
+                    .from(from)
+                    .to(to)
+                    .with_sudo(*sudo)
+                    .backup_to(&backup::run_dir())
+                    .copy()?
+            }
+            Step::Remove { path } if path.is_dir() => {
+                std::fs::remove_dir_all(path).map_err(CopyError::from)?
+            }
+            Step::Remove { path } => std::fs::remove_file(path).map_err(CopyError::from)?,
+            Step::Init { path } => {
+                GitOp::new().init_at_path(path)?;
+            }
+            Step::Clone { url, path } => {
+                GitOp::new().clone(url, path)?;
+            }
+            Step::WriteConfig { path, config } => config.write(path)?,
+            Step::WriteFile { path, content } => {
+                std::fs::write(path, content).map_err(CopyError::from)?
+            }
+            Step::Archive {
+                repo,
+                files,
+                config,
+                path,
+            } => archive::pack(repo, config, files, crate::home::dir(), path)?,
+            Step::Commit { repo, paths, .. } => {
+                let (go, tree_id) = GitOp::new().at_path(repo)?.add_these(paths)?;
+                let (_, oid) = go.commit(tree_id)?;
+                self.parent_op.log(&format!("Committed {}", oid)).ok();
+            }
+            Step::Fetch {
+                repo,
+                remote,
+                branch,
+            } => {
+                GitOp::new().at_path(repo)?.fetch(branch, remote)?;
+            }
+            Step::Push {
+                repo,
+                remote,
+                branch,
+            } => GitOp::new().at_path(repo)?.push(remote, branch)?,
+            Step::MoveRef {
+                repo,
+                name,
+                to: Some(to),
+                ..
+            } if name.starts_with("refs/heads/") => {
+                GitOp::new()
+                    .at_path(repo)?
+                    .fast_forward(name.trim_start_matches("refs/heads/"), *to)?;
+            }
+            // Moved by the commit, push or fetch before it.
+            Step::MoveRef { .. } => {}
+            Step::Run { args } => {
+                let (program, rest) = args.split_first().ok_or(PlanError::NoProgram)?;
+                let mut cmd = Command::new(program);
+                cmd.args(rest);
+                let status = cmd.status().map_err(InstallError::from)?;
+                if !status.success() {
+                    return Err(InstallError::Failed {
+                        cmd: args.join(" "),
+                        code: status.code(),
+                    }
+                    .into());
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Operate for Apply {
+    fn operate(&mut self) -> Result<usize, OperationError> {
+        self.plan.verify()?;
+
+        let steps = std::mem::take(&mut self.plan.steps);
+        for step in &steps {
+            if self.parent_op.is_dry_run() {
+                self.parent_op.record([step.clone()]);
+                continue;
+            }
+
+            crate::output::term(&step.to_string());
+            self.take(step)?;
+        }
+        self.parent_op.insert_result("apply", Ok(()));
+        if !self.parent_op.is_dry_run() {
+            self.parent_op
+                .report()
+                .details
+                .insert("applied", json!(steps));
+        }
+        backup::report();
+
+        Ok(0)
+    }
+
+    fn exit_code(&mut self) -> usize {
+        let result = self.operate();
+        self.parent_op.finish(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{hash, Apply, Plan, Step};
+    use crate::{ops::op::Operation, _TESTS_DIR};
+    use git2::{Oid, Repository, Signature};
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    /// Expects refs to be abbreviated & a missing target to read as the new
+    /// commit.
+    fn display() {
+        let step = Step::MoveRef {
+            repo: PathBuf::from("/repo"),
+            name: String::from("refs/heads/main"),
+            from: Some(Oid::from_str("0123456789abcdef0123456789abcdef01234567").unwrap()),
+            to: None,
+        };
+        assert_eq!(
+            step.to_string(),
+            "ref     refs/heads/main 0123456 -> (new commit)"
+        );
+
+        let step = Step::Copy {
+            from: PathBuf::from("/a"),
+            to: PathBuf::from("/b"),
+            sudo: true,
+            hash: String::new(),
+        };
+        assert_eq!(step.to_string(), "copy    /a -> /b (sudo)");
+    }
+
+    #[test]
+    /// Expects a plan to survive a round trip through JSON, and to be refused
+    /// once a file it copies has changed, or it runs nothing.
+    fn round_trip_and_verify() {
+        let base_path = Path::new(_TESTS_DIR).join("plan");
+        std::fs::create_dir_all(&base_path).expect("Failed to make plan dir!");
+        let from = base_path.join("from");
+        std::fs::write(&from, "planned").expect("Failed to write from!");
+
+        let plan = Plan {
+            steps: vec![
+                Step::Copy {
+                    from: from.clone(),
+                    to: base_path.join("to"),
+                    sudo: false,
+                    hash: hash(&from).expect("Failed to hash!"),
+                },
+                Step::Run {
+                    args: vec![String::from("true")],
+                },
+            ],
+        };
+        let path = base_path.join("plan.json");
+        plan.write(&path).expect("Failed to write plan!");
+        let plan = Plan::read(&path).expect("Failed to read plan!");
+
+        assert_eq!(plan.steps.len(), 2);
+        assert!(plan.verify().is_ok());
+
+        std::fs::write(&from, "changed").expect("Failed to change from!");
+        assert!(plan.verify().is_err());
+
+        // Refused before taking any step, rather than panicking at it.
+        let empty = Plan {
+            steps: vec![Step::Run { args: Vec::new() }],
+        };
+        assert_eq!(
+            empty.verify().unwrap_err().to_string(),
+            "A `run` step has no program, plan again."
+        );
+
+        std::fs::remove_dir_all(&base_path).expect("Failed to remove plan dir!");
+    }
+
+    #[test]
+    /// Expects files copied after a branch moves to be checked as they're
+    /// copied rather than up front, since they were planned from where the
+    /// branch moves to.
+    fn copies_after_move() {
+        let base_path = Path::new(_TESTS_DIR).join("plan_move");
+        let repo = Repository::init(&base_path).expect("Failed to init repository!");
+        std::fs::write(base_path.join("a"), "old").expect("Failed to write a!");
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("a")).unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let sig = Signature::now("nedots", "nedots@localhost").unwrap();
+        let head = repo
+            .commit(Some("HEAD"), &sig, &sig, "a", &tree, &[])
+            .expect("Failed to commit!");
+        let branch = repo.head().unwrap().name().unwrap().to_string();
+
+        std::fs::write(base_path.join("new"), "new").unwrap();
+        let copy = Step::Copy {
+            from: base_path.join("a"),
+            to: base_path.join("to"),
+            sudo: false,
+            hash: hash(&base_path.join("new")).unwrap(),
+        };
+        let plan = Plan {
+            steps: vec![
+                Step::MoveRef {
+                    repo: base_path.to_path_buf(),
+                    name: branch,
+                    from: Some(head),
+                    to: Some(head),
+                },
+                copy.clone(),
+            ],
+        };
+        assert!(plan.verify().is_ok());
+
+        let mut apply = Apply::new(Operation::new());
+        assert!(apply.take(&copy).is_err());
+        assert!(!base_path.join("to").exists());
+
+        std::fs::remove_dir_all(&base_path).expect("Failed to remove plan dir!");
+    }
+}
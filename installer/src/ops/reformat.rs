@@ -0,0 +1,134 @@
+use super::{
+    op::{Operate, Operation, OperationError},
+    plan::Step,
+};
+use crate::{
+    config::{Config, ConfigError, Format},
+    output::TerminalLogger,
+};
+use std::path::{Path, PathBuf};
+
+/// Rewrites the config in another `Format`, e.g. `nedots.json` as
+/// `nedots.toml` so it can be commented, replacing the original.
+pub(crate) struct Reformat {
+    pub(crate) parent_op: Operation<TerminalLogger>,
+
+    /// Path of the config to rewrite.
+    pub(crate) config_path: PathBuf,
+
+    /// Format to rewrite it in.
+    pub(crate) format: Format,
+
+    /// Write here instead of next to `config_path`.
+    pub(crate) out: Option<PathBuf>,
+
+    /// Keep `config_path` rather than removing it.
+    pub(crate) keep: bool,
+
+    /// Overwrite whatever's at the destination.
+    pub(crate) force: bool,
+}
+
+impl Reformat {
+    pub(crate) fn new(op: Operation<TerminalLogger>) -> Self {
+        Self {
+            parent_op: op,
+            config_path: PathBuf::from(crate::config::CONFIG_FILE),
+            format: Format::Toml,
+            out: None,
+            keep: false,
+            force: false,
+        }
+    }
+
+    /// Assign `config_path`.
+    pub(crate) fn config_at(mut self, path: &Path) -> Self {
+        self.config_path = path.to_path_buf();
+        self
+    }
+
+    /// Assign `format`.
+    pub(crate) fn to(mut self, format: Format) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Assign `out`.
+    pub(crate) fn write_to(mut self, out: Option<PathBuf>) -> Self {
+        self.out = out;
+        self
+    }
+
+    /// Assign `keep`.
+    pub(crate) fn keep(mut self, keep: bool) -> Self {
+        self.keep = keep;
+        self
+    }
+
+    /// Assign `force`.
+    pub(crate) fn force(mut self, force: bool) -> Self {
+        self.force = force;
+        self
+    }
+
+    /// Where to write the config, `config_path` with the extension of
+    /// `format` unless `out` is set.
+    fn destination(&self) -> PathBuf {
+        match &self.out {
+            Some(out) => out.to_path_buf(),
+            None => self.config_path.with_extension(self.format.extension()),
+        }
+    }
+}
+
+impl Operate for Reformat {
+    fn operate(&mut self) -> Result<usize, OperationError> {
+        let config = Config::read(Some(&self.config_path))?;
+
+        let out = self.destination();
+        let replaced = out != self.config_path;
+        if replaced && out.exists() && !self.force {
+            return Err(ConfigError::Exists { path: out }.into());
+        }
+        // Rewritten in place, comments in it are kept or it's refused.
+        let content = match replaced {
+            true => config.to_string_as(self.format)?,
+            false => config.render(&out, self.format)?,
+        };
+        let remove = replaced && !self.keep;
+
+        if self.parent_op.is_dry_run() {
+            self.parent_op
+                .record([Step::WriteFile { path: out, content }]);
+            if remove {
+                self.parent_op.record([Step::Remove {
+                    path: self.config_path.to_path_buf(),
+                }]);
+            }
+            return Ok(0);
+        }
+
+        std::fs::write(&out, content).map_err(ConfigError::from)?;
+        self.parent_op.insert_result("config_write", Ok(()));
+        if remove {
+            std::fs::remove_file(&self.config_path).map_err(ConfigError::from)?;
+            self.parent_op.insert_result("config_remove", Ok(()));
+        }
+
+        crate::output::term(&match remove {
+            true => format!(
+                "Wrote {}, replacing {}",
+                out.display(),
+                self.config_path.display()
+            ),
+            false => format!("Wrote {}", out.display()),
+        });
+
+        Ok(0)
+    }
+
+    fn exit_code(&mut self) -> usize {
+        let result = self.operate();
+        self.parent_op.finish(result)
+    }
+}
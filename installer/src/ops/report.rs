@@ -0,0 +1,146 @@
+use super::{op::OperationError, plan::Step};
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug, Clone, Serialize)]
+/// An error, with a stable `kind` scripts can match on.
+pub(crate) struct ErrorReport {
+    pub(crate) kind: &'static str,
+    pub(crate) message: String,
+
+    /// Paths the error concerns, e.g. local changes that would be lost.
+    pub(crate) paths: Vec<PathBuf>,
+}
+
+impl From<&OperationError> for ErrorReport {
+    fn from(e: &OperationError) -> Self {
+        Self {
+            kind: e.kind(),
+            message: e.to_string(),
+            paths: match e {
+                OperationError::LocalChanges { paths } => paths.to_vec(),
+                _ => Vec::new(),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+/// Whether a single step of an operation, e.g. `git_commit`, succeeded.
+pub(crate) struct StepReport {
+    pub(crate) ok: bool,
+    pub(crate) error: Option<ErrorReport>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+/// What happened to a single path.
+pub(crate) struct PathReport {
+    pub(crate) path: PathBuf,
+
+    /// e.g. `copied`, `updated` or a `FileState`.
+    pub(crate) outcome: String,
+
+    /// Lines inserted, when a diff was taken.
+    pub(crate) insertions: Option<usize>,
+
+    /// Lines deleted, when a diff was taken.
+    pub(crate) deletions: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+/// Packages handed to a package manager in one go.
+pub(crate) struct PackageReport {
+    pub(crate) manager: String,
+    pub(crate) packages: Vec<String>,
+    pub(crate) installed: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+/// Everything an operation did, printed as a single line of JSON with
+/// `--output json`. Every field is always present so the shape is stable.
+pub(crate) struct Report {
+    pub(crate) ok: bool,
+    pub(crate) exit_code: usize,
+    pub(crate) dry_run: bool,
+    pub(crate) results: BTreeMap<&'static str, StepReport>,
+    pub(crate) paths: Vec<PathReport>,
+    pub(crate) commits: Vec<String>,
+    pub(crate) packages: Vec<PackageReport>,
+
+    /// Operation specific details, e.g. `ahead` & `behind` for `status`.
+    pub(crate) details: BTreeMap<&'static str, serde_json::Value>,
+
+    /// `Step`s recorded during a dry run.
+    pub(crate) plan: Vec<Step>,
+    pub(crate) error: Option<ErrorReport>,
+}
+
+impl Report {
+    /// A report for an operation that failed before it could run.
+    pub(crate) fn failed(e: &OperationError) -> Self {
+        Self {
+            exit_code: 1,
+            error: Some(e.into()),
+            ..Default::default()
+        }
+    }
+
+    /// Add `path` with `outcome`.
+    pub(crate) fn path(&mut self, path: &Path, outcome: &str) -> &mut PathReport {
+        self.paths.push(PathReport {
+            path: path.to_path_buf(),
+            outcome: outcome.to_string(),
+            insertions: None,
+            deletions: None,
+        });
+        self.paths.last_mut().unwrap()
+    }
+
+    /// Write the report to stdout as a single line of JSON.
+    pub(crate) fn print(&self) -> Result<(), std::io::Error> {
+        let json = serde_json::to_string(self)?;
+        writeln!(std::io::stdout(), "{}", json)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Report;
+    use crate::ops::{op::OperationError, report::ErrorReport};
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    /// Expects every field to be present, even when empty, & errors to carry
+    /// their kind & paths.
+    fn stable_shape() {
+        let e = OperationError::LocalChanges {
+            paths: vec![PathBuf::from("/etc/hosts")],
+        };
+        let mut report = Report::failed(&e);
+        report.path(Path::new("/etc/hosts"), "modified locally");
+
+        let json = serde_json::to_value(&report).expect("Failed to serialize report!");
+        for key in [
+            "ok",
+            "exit_code",
+            "dry_run",
+            "results",
+            "paths",
+            "commits",
+            "packages",
+            "details",
+            "plan",
+            "error",
+        ] {
+            assert!(json.get(key).is_some(), "Missing `{}`", key);
+        }
+        assert_eq!(json["error"]["kind"], "local_changes");
+        assert_eq!(json["error"]["paths"][0], "/etc/hosts");
+        assert_eq!(json["paths"][0]["insertions"], serde_json::Value::Null);
+        assert_eq!(ErrorReport::from(&e).kind, "local_changes");
+    }
+}
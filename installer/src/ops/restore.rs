@@ -0,0 +1,181 @@
+use super::{
+    backup,
+    fs::{self, CopyError, CopyOp},
+    git::{GitError, GitOp},
+    op::{Operate, Operation, OperationError},
+};
+use crate::output::TerminalLogger;
+use std::{
+    io::Write,
+    os::unix::fs::OpenOptionsExt,
+    path::{Path, PathBuf},
+};
+
+/// Writes an older version of a tracked file from the repository to its live
+/// location.
+pub(crate) struct Restore {
+    pub(crate) parent_op: Operation<TerminalLogger>,
+
+    /// Path of the repository.
+    pub(crate) repo: PathBuf,
+
+    /// Live path to restore.
+    pub(crate) path: PathBuf,
+
+    /// Revision to restore from, e.g. `HEAD~2` or a commit hash.
+    pub(crate) rev: String,
+
+    /// Restore the version this many changes to `path` ago instead of `rev`.
+    pub(crate) back: Option<usize>,
+}
+
+impl Restore {
+    pub(crate) fn new(op: Operation<TerminalLogger>) -> Self {
+        Self {
+            parent_op: op,
+            repo: PathBuf::new(),
+            path: PathBuf::new(),
+            rev: String::from("HEAD"),
+            back: None,
+        }
+    }
+
+    /// Assign `repo`.
+    pub(crate) fn of(mut self, repo: PathBuf) -> Self {
+        self.repo = repo;
+        self
+    }
+
+    /// Assign `path`, relative paths are relative to the current directory.
+    pub(crate) fn at(mut self, path: &str) -> Result<Self, OperationError> {
+        self.path = std::env::current_dir().map_err(CopyError::from)?.join(path);
+        Ok(self)
+    }
+
+    /// Assign `rev`.
+    pub(crate) fn at_rev(mut self, rev: &str) -> Self {
+        self.rev = rev.to_string();
+        self
+    }
+
+    /// Assign `back`.
+    pub(crate) fn back(mut self, back: Option<usize>) -> Self {
+        self.back = back;
+        self
+    }
+
+    /// The revision to read `rel` from, resolving `back` against the commits
+    /// that changed it.
+    fn resolve(&self, go: &mut GitOp, rel: &Path) -> Result<String, GitError> {
+        let back = match self.back {
+            Some(back) => back,
+            None => return Ok(self.rev.to_string()),
+        };
+
+        go.history(rel)?
+            .get(back)
+            .map(|oid| oid.to_string())
+            .ok_or(GitError::NotInRevision {
+                path: rel.to_path_buf(),
+                rev: format!("{} change(s) back", back),
+            })
+    }
+}
+
+impl Operate for Restore {
+    fn operate(&mut self) -> Result<usize, OperationError> {
+        let in_repo = fs::repo_path(&self.repo, &self.path);
+        let rel = in_repo.strip_prefix(&self.repo).unwrap_or(&in_repo);
+
+        let mut go = GitOp::new().at_path(&self.repo)?;
+        let rev = self.resolve(&mut go, rel)?;
+        let (content, mode) = go.blob_at(&rev, rel)?;
+
+        // `CopyOp` copies between files, so the old version is staged with
+        // the mode it was committed with. Next to backups, rather than in a
+        // temporary directory, so a plan of the restore can still be applied
+        // later.
+        let staged = backup::state_dir()
+            .join("imports")
+            .join(backup::run_id())
+            .join(rel);
+        std::fs::create_dir_all(staged.parent().unwrap_or(&staged)).map_err(CopyError::from)?;
+        std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(mode as u32 & 0o777)
+            .open(&staged)
+            .and_then(|mut f| f.write_all(&content))
+            .map_err(CopyError::from)?;
+
+        let op = CopyOp::new()
+            .from(&staged)
+            .to(&self.path)
+            .with_sudo(!self.path.starts_with(crate::home::dir()))
+            .backup_to(&backup::run_dir());
+        if self.parent_op.is_dry_run() {
+            self.parent_op.record(op.plan()?);
+            return Ok(0);
+        }
+
+        let result = op.copy();
+        std::fs::remove_file(&staged).ok();
+        result?;
+
+        crate::output::term(&format!("Restored {} from {}", self.path.display(), rev));
+        self.parent_op.report().path(&self.path, "restored");
+        backup::report();
+
+        Ok(0)
+    }
+
+    fn exit_code(&mut self) -> usize {
+        let result = self.operate();
+        self.parent_op.finish(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Restore;
+    use crate::{
+        ops::{git::GitOp, op::Operation},
+        _TESTS_DIR,
+    };
+    use git2::Repository;
+    use std::path::Path;
+
+    #[test]
+    /// Expects `back` to count changes to the path, skipping commits that
+    /// didn't touch it, & the old content to be read back.
+    fn resolve_back() {
+        let path = Path::new(_TESTS_DIR).join("restore");
+        let repo = Repository::init(&path).expect("Failed to init repository!");
+        let mut config = repo.config().expect("Failed to get config!");
+        config.set_str("user.name", "nedots").unwrap();
+        config.set_str("user.email", "nedots@localhost").unwrap();
+
+        let mut go = GitOp::new()
+            .at_path(&path)
+            .expect("Failed to create `GitOp`!");
+        for (file, content) in [("rc", "one"), ("other", "x"), ("rc", "two")] {
+            std::fs::write(path.join(file), content).expect("Failed to write file!");
+            let (g, tree_id) = go.add_changes().expect("Failed add_changes!");
+            go = g.commit(tree_id).expect("Failed commit!").0;
+        }
+
+        let restore = Restore::new(Operation::new()).back(Some(1));
+        let rev = restore
+            .resolve(&mut go, Path::new("rc"))
+            .expect("Failed to resolve!");
+        let (content, _) = go.blob_at(&rev, Path::new("rc")).expect("Failed blob_at!");
+        assert_eq!(content, b"one");
+        assert!(restore
+            .back(Some(2))
+            .resolve(&mut go, Path::new("rc"))
+            .is_err());
+        assert!(go.blob_at("HEAD", Path::new("missing")).is_err());
+
+        std::fs::remove_dir_all(&path).expect("Failed to remove restore dir!");
+    }
+}
@@ -0,0 +1,114 @@
+use super::backup;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+/// Name of the file skipped updates are remembered in.
+const SKIPPED: &str = "skipped.json";
+
+#[derive(Debug, Error)]
+#[allow(clippy::enum_variant_names)]
+/// Errors thrown while remembering skipped updates.
+pub(crate) enum SkipError {
+    #[error("Failed to deserialize {path:?}, is it badly formatted?")]
+    /// Serde error.
+    DeserializeError { path: PathBuf },
+
+    #[error("Failed to serialize skipped updates.")]
+    /// Serde error.
+    SerializeError,
+
+    #[error(transparent)]
+    /// A wrapper around IO errors, including failed prompts.
+    IoError(#[from] std::io::Error),
+}
+
+/// Where skipped updates are remembered, next to backups.
+pub(crate) fn path() -> PathBuf {
+    backup::state_dir().join(SKIPPED)
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+/// Updates the user chose not to take. Each live path maps to the hash of
+/// the repository version that was skipped, so it's proposed again once that
+/// changes.
+pub(crate) struct Skipped {
+    #[serde(skip)]
+    path: PathBuf,
+
+    entries: BTreeMap<PathBuf, String>,
+}
+
+impl Skipped {
+    /// Read skipped updates from `path`, none if it doesn't exist yet.
+    pub(crate) fn open(path: &Path) -> Result<Self, SkipError> {
+        let mut skipped = match path.exists() {
+            true => {
+                serde_json::from_reader(File::open(path)?).or(Err(SkipError::DeserializeError {
+                    path: path.to_path_buf(),
+                }))?
+            }
+            false => Self::default(),
+        };
+        skipped.path = path.to_path_buf();
+
+        Ok(skipped)
+    }
+
+    /// Write skipped updates back to where they were read from.
+    pub(crate) fn write(&self) -> Result<(), SkipError> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let json = serde_json::to_string_pretty(self).or(Err(SkipError::SerializeError))?;
+        Ok(std::fs::write(&self.path, json + "\n")?)
+    }
+
+    /// The update of `live` to the version hashed `hash` was skipped.
+    pub(crate) fn is_skipped(&self, live: &Path, hash: &str) -> bool {
+        self.entries.get(live).map(String::as_str) == Some(hash)
+    }
+
+    /// Remember that the update of `live` to `hash` was skipped.
+    pub(crate) fn skip(&mut self, live: &Path, hash: &str) {
+        self.entries.insert(live.to_path_buf(), hash.to_string());
+    }
+
+    /// Stop remembering `live`.
+    pub(crate) fn forget(&mut self, live: &Path) {
+        self.entries.remove(live);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Skipped;
+    use crate::_TESTS_DIR;
+    use std::path::Path;
+
+    #[test]
+    /// Expects a skip to only hold for the version that was skipped, & to
+    /// survive a round trip through disk.
+    fn skip_until_changed() {
+        let path = Path::new(_TESTS_DIR).join("skip").join("skipped.json");
+        let live = Path::new("/home/me/.bashrc");
+
+        let mut skipped = Skipped::open(&path).expect("Failed to open skipped!");
+        skipped.skip(live, "abc");
+        skipped.write().expect("Failed to write skipped!");
+
+        let mut skipped = Skipped::open(&path).expect("Failed to reopen skipped!");
+        assert!(skipped.is_skipped(live, "abc"));
+        assert!(!skipped.is_skipped(live, "def"));
+
+        skipped.forget(live);
+        assert!(!skipped.is_skipped(live, "abc"));
+
+        std::fs::remove_dir_all(path.parent().unwrap()).expect("Failed to remove skip dir!");
+    }
+}
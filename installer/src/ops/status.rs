@@ -0,0 +1,380 @@
+use super::{
+    fs::{self, CopyError},
+    git::{GitError, GitOp},
+    op::{Operate, Operation, OperationError},
+};
+use crate::output::{logger::Logger, TerminalLogger};
+use console::style;
+use git2::{ObjectType, Oid};
+use serde_json::json;
+use std::{
+    collections::BTreeSet,
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// How a live file compares to its copy in the repository.
+pub(crate) enum FileState {
+    /// Live file & repository copy are identical.
+    Unchanged,
+
+    /// Live file was changed since it was last committed, `add-changes` would
+    /// pick it up.
+    ModifiedLocally,
+
+    /// Repository copy was changed, or committed again, since the live file
+    /// was last synced, `update-local` would overwrite the live file.
+    ModifiedInRepo,
+
+    /// Both sides were changed since the last commit.
+    ModifiedBoth,
+
+    /// Only exists in the repository.
+    MissingLocally,
+
+    /// Only exists on this system.
+    MissingInRepo,
+
+    /// Exists on neither side.
+    Missing,
+}
+
+impl Display for FileState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.pad(match self {
+            FileState::Unchanged => "unchanged",
+            FileState::ModifiedLocally => "modified locally",
+            FileState::ModifiedInRepo => "modified in repo",
+            FileState::ModifiedBoth => "modified on both",
+            FileState::MissingLocally => "missing locally",
+            FileState::MissingInRepo => "missing in repo",
+            FileState::Missing => "missing",
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+/// The `FileState` of a single tracked file.
+pub(crate) struct FileStatus {
+    /// Path on this system.
+    pub(crate) live: PathBuf,
+
+    /// Path inside the repository.
+    pub(crate) repo: PathBuf,
+
+    pub(crate) state: FileState,
+}
+
+impl FileStatus {
+    /// Compare `live` with `repo` & classify. `committed` gives the blob id
+    /// of every version of `repo` committed on `HEAD`, newest first, and is
+    /// only called when the two differ.
+    pub(crate) fn new(
+        live: &Path,
+        repo: &Path,
+        committed: impl FnOnce() -> Vec<Oid>,
+    ) -> Result<Self, CopyError> {
+        let state = match (live.exists(), repo.exists()) {
+            (true, false) => FileState::MissingInRepo,
+            (false, true) => FileState::MissingLocally,
+            (false, false) => FileState::Missing,
+            (true, true) if !fs::differs(live, repo)? => FileState::Unchanged,
+            (true, true) => {
+                // Modification times don't survive a clone, checkout or copy,
+                // so the side that changed is the one that differs from what
+                // was committed.
+                let committed = committed();
+                let (live_id, repo_id) = (blob_id(live)?, blob_id(repo)?);
+                match committed.split_first() {
+                    None => FileState::ModifiedLocally,
+                    Some((head, older)) => {
+                        let local = !committed.contains(&live_id);
+                        let in_repo = repo_id != *head || older.contains(&live_id);
+                        match (local, in_repo) {
+                            (true, true) => FileState::ModifiedBoth,
+                            (false, _) => FileState::ModifiedInRepo,
+                            (true, false) => FileState::ModifiedLocally,
+                        }
+                    }
+                }
+            }
+        };
+
+        Ok(Self {
+            live: live.to_path_buf(),
+            repo: repo.to_path_buf(),
+            state,
+        })
+    }
+
+    /// Compare every file under `live` & `repo`, files that only exist on
+    /// one side are included. `committed` gives the versions of a file under
+    /// `repo`, as `new` expects.
+    pub(crate) fn walk(
+        live: &Path,
+        repo: &Path,
+        mut committed: impl FnMut(&Path) -> Vec<Oid>,
+    ) -> Result<Vec<Self>, CopyError> {
+        let mut rels = BTreeSet::new();
+        rels.extend(fs::files(live)?);
+        rels.extend(fs::files(repo)?);
+
+        // Neither side exists, report the tracked path itself.
+        if rels.is_empty() {
+            rels.insert(PathBuf::new());
+        }
+
+        rels.iter()
+            .map(|rel| {
+                let repo = fs::join(repo, rel);
+                Self::new(&fs::join(live, rel), &repo, || committed(&repo))
+            })
+            .collect()
+    }
+}
+
+/// Id `git` gives the content of `path`.
+fn blob_id(path: &Path) -> Result<Oid, std::io::Error> {
+    Oid::hash_file(ObjectType::Blob, path).map_err(std::io::Error::other)
+}
+
+/// Reports drift between tracked files & the repository, along with the state
+/// of the repository itself.
+pub(crate) struct Status<'remote> {
+    pub(crate) parent_op: Operation<TerminalLogger>,
+
+    /// The `GitOperation` used to inspect the repository.
+    pub(crate) git_op: Option<GitOp<'remote>>,
+
+    /// Tracked paths on this system.
+    pub(crate) paths: Vec<PathBuf>,
+
+    /// Compare against this branch on the remote, defaults to the current
+    /// branch.
+    pub(crate) branch: Option<String>,
+
+    /// Fetch from the remote before comparing.
+    pub(crate) fetch: bool,
+}
+
+impl<'remote> Status<'remote> {
+    pub(crate) fn new(op: Operation<TerminalLogger>) -> Self {
+        Self {
+            parent_op: op,
+            git_op: None,
+            paths: Vec::new(),
+            branch: None,
+            fetch: false,
+        }
+    }
+
+    pub(crate) fn of(mut self, path: PathBuf) -> Result<Self, OperationError> {
+        self.git_op = Some(GitOp::new().at_path(&path)?);
+        Ok(self)
+    }
+
+    pub(crate) fn with_remote(mut self, remote: &'remote str) -> Self {
+        self.git_op = self.git_op.map(|go| go.with_remote(remote));
+        self
+    }
+
+    /// Compare `paths`, `paths` without a leading `/` are relative to $HOME.
+    pub(crate) fn compare_these(mut self, paths: Vec<PathBuf>) -> Self {
+        self.paths
+            .extend(paths.iter().map(|p| crate::home::dir().join(p)));
+        self
+    }
+
+    /// Assign `branch`.
+    pub(crate) fn on_branch(mut self, branch: Option<String>) -> Self {
+        self.branch = branch;
+        self
+    }
+
+    /// Assign `fetch`.
+    pub(crate) fn fetch(mut self, fetch: bool) -> Self {
+        self.fetch = fetch;
+        self
+    }
+
+    /// `FileStatus` of every tracked file.
+    pub(crate) fn files(&mut self) -> Result<Vec<FileStatus>, OperationError> {
+        let go = self.git_op.as_mut().ok_or(GitError::NoRepo)?;
+        let root = go.path()?.to_path_buf();
+        let mut statuses = Vec::new();
+        for live in &self.paths {
+            statuses.append(&mut FileStatus::walk(
+                live,
+                &fs::repo_path(&root, live),
+                |repo| committed(go, &root, repo),
+            )?);
+        }
+
+        Ok(statuses)
+    }
+}
+
+/// Versions of `path`, inside the repository at `root`, committed on `HEAD`.
+/// Nothing when they can't be read, e.g. before the first commit.
+pub(crate) fn committed(go: &mut GitOp, root: &Path, path: &Path) -> Vec<Oid> {
+    let rel = path.strip_prefix(root).unwrap_or(path);
+    go.versions(rel).unwrap_or_default()
+}
+
+impl Operate for Status<'_> {
+    fn operate(&mut self) -> Result<usize, OperationError> {
+        for fs in self.files()? {
+            self.parent_op
+                .log(&format!("{} <-> {}", fs.live.display(), fs.repo.display()))
+                .ok();
+            self.parent_op
+                .report()
+                .path(&fs.live, &fs.state.to_string());
+
+            let state = match fs.state {
+                FileState::Unchanged => continue,
+                FileState::ModifiedLocally | FileState::MissingInRepo => {
+                    style(format!("{:>16}", fs.state)).green()
+                }
+                FileState::ModifiedInRepo | FileState::MissingLocally => {
+                    style(format!("{:>16}", fs.state)).yellow()
+                }
+                FileState::ModifiedBoth | FileState::Missing => {
+                    style(format!("{:>16}", fs.state)).red()
+                }
+            };
+            crate::output::term(&format!("{}  {}", state, fs.live.display()));
+        }
+
+        let go = self.git_op.as_mut().ok_or(GitError::NoRepo)?;
+        let uncommitted = go.uncommitted()?;
+        self.parent_op
+            .report()
+            .details
+            .insert("uncommitted", json!(uncommitted));
+        if !uncommitted.is_empty() {
+            crate::output::term(&format!(
+                "\n{} uncommitted change(s) in {}:",
+                uncommitted.len(),
+                go.path()?.display()
+            ));
+            for p in uncommitted {
+                crate::output::term(&format!("  {}", p.display()));
+            }
+        }
+
+        let remote = go.remote().to_string();
+        let branch = match &self.branch {
+            Some(b) => b.to_string(),
+            None => go.branch()?,
+        };
+        self.parent_op
+            .report()
+            .details
+            .insert("upstream", json!(format!("{}/{}", remote, branch)));
+        let upstream = if self.fetch {
+            Some(go.fetch(&branch, &remote)?)
+        } else {
+            go.upstream(&remote, &branch)?
+        };
+
+        match upstream {
+            Some(oid) => {
+                let (ahead, behind) = go.ahead_behind(oid)?;
+                let details = &mut self.parent_op.report().details;
+                details.insert("ahead", json!(ahead));
+                details.insert("behind", json!(behind));
+                crate::output::term(&format!(
+                    "\n{} ahead, {} behind {}/{}",
+                    ahead, behind, remote, branch
+                ));
+            }
+            None => crate::output::term(&format!(
+                "\nNo record of {}/{}, try --fetch",
+                remote, branch
+            )),
+        }
+
+        Ok(0)
+    }
+
+    fn exit_code(&mut self) -> usize {
+        let result = self.operate();
+        self.parent_op.finish(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FileState, FileStatus};
+    use crate::_TESTS_DIR;
+    use git2::{ObjectType, Oid};
+    use std::path::Path;
+
+    #[test]
+    /// Expects files that only exist on one side to be reported, alongside
+    /// identical & differing files.
+    fn walk() {
+        let base_path = Path::new(_TESTS_DIR).join("status");
+        let live = base_path.join("live");
+        let repo = base_path.join("repo");
+        std::fs::create_dir_all(live.join("sub")).expect("Failed to make live dir!");
+        std::fs::create_dir_all(&repo).expect("Failed to make repo dir!");
+
+        std::fs::write(live.join("same"), "same").expect("Failed to write same!");
+        std::fs::write(repo.join("same"), "same").expect("Failed to write same!");
+        std::fs::write(repo.join("differs"), "old").expect("Failed to write differs!");
+        std::fs::write(live.join("differs"), "new").expect("Failed to write differs!");
+        std::fs::write(live.join("sub").join("local"), "").expect("Failed to write local!");
+        std::fs::write(repo.join("remote"), "").expect("Failed to write remote!");
+
+        let statuses = FileStatus::walk(&live, &repo, |_| Vec::new()).expect("Failed to walk!");
+        let state = |name: &str| {
+            statuses
+                .iter()
+                .find(|s| s.live == live.join(name))
+                .map(|s| s.state)
+        };
+
+        assert_eq!(statuses.len(), 4);
+        assert_eq!(state("same"), Some(FileState::Unchanged));
+        assert_eq!(state("differs"), Some(FileState::ModifiedLocally));
+        assert_eq!(state("sub/local"), Some(FileState::MissingInRepo));
+        assert_eq!(state("remote"), Some(FileState::MissingLocally));
+
+        let gone = base_path.join("gone");
+        let statuses = FileStatus::walk(&gone, &gone, |_| Vec::new()).expect("Failed to walk!");
+        assert_eq!(statuses[0].state, FileState::Missing);
+
+        std::fs::remove_dir_all(&base_path).expect("Failed to remove status dir!");
+    }
+
+    #[test]
+    /// Expects the side that differs from what was committed to be the one
+    /// reported as changed, whatever the modification times say.
+    fn committed_side() {
+        let base_path = Path::new(_TESTS_DIR).join("status_committed");
+        std::fs::create_dir_all(&base_path).expect("Failed to make status dir!");
+        let (live, repo) = (base_path.join("live"), base_path.join("repo"));
+        let id = |s: &str| Oid::hash_object(ObjectType::Blob, s.as_bytes()).unwrap();
+        // Newest first, as `GitOp::versions` gives them.
+        let committed = || vec![id("two"), id("one")];
+
+        let state = |live_content: &str, repo_content: &str| {
+            std::fs::write(&live, live_content).expect("Failed to write live!");
+            std::fs::write(&repo, repo_content).expect("Failed to write repo!");
+            FileStatus::new(&live, &repo, committed)
+                .expect("Failed to compare!")
+                .state
+        };
+
+        assert_eq!(state("three", "two"), FileState::ModifiedLocally);
+        assert_eq!(state("two", "three"), FileState::ModifiedInRepo);
+        // Committed again since the live file was synced, e.g. after a pull.
+        assert_eq!(state("one", "two"), FileState::ModifiedInRepo);
+        assert_eq!(state("three", "four"), FileState::ModifiedBoth);
+
+        std::fs::remove_dir_all(&base_path).expect("Failed to remove status dir!");
+    }
+}
@@ -0,0 +1,215 @@
+use super::{
+    fs::{self, CopyError, CopyOp},
+    op::{Operate, Operation, OperationError},
+    plan::Step,
+};
+use crate::{
+    config::{Config, ConfigError},
+    output::TerminalLogger,
+};
+use std::path::{Component, Path, PathBuf};
+
+/// `path` without `.` or `..` components, worked out from the path alone so
+/// that symlinks stay as they are & paths that no longer exist can still be
+/// untracked.
+fn normalize(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for c in path.components() {
+        match c {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                out.pop();
+            }
+            c => out.push(c),
+        }
+    }
+
+    out
+}
+
+/// Adds or removes tracked paths in `nedots.json`.
+pub(crate) struct Track {
+    pub(crate) parent_op: Operation<TerminalLogger>,
+
+    /// Path of the repository.
+    pub(crate) repo: PathBuf,
+
+    /// Path of `nedots.json`.
+    pub(crate) config_path: PathBuf,
+
+    /// Absolute paths to track or untrack.
+    pub(crate) paths: Vec<PathBuf>,
+
+    /// Untrack `paths` instead of tracking them.
+    pub(crate) untrack: bool,
+
+    /// When untracking, also remove the copy in the repository.
+    pub(crate) remove: bool,
+}
+
+impl Track {
+    pub(crate) fn new(op: Operation<TerminalLogger>) -> Self {
+        Self {
+            parent_op: op,
+            repo: PathBuf::new(),
+            config_path: PathBuf::from(crate::config::CONFIG_FILE),
+            paths: Vec::new(),
+            untrack: false,
+            remove: false,
+        }
+    }
+
+    /// Assign `repo`.
+    pub(crate) fn of(mut self, repo: PathBuf) -> Self {
+        self.repo = repo;
+        self
+    }
+
+    /// Assign `config_path`.
+    pub(crate) fn config_at(mut self, path: &Path) -> Self {
+        self.config_path = path.to_path_buf();
+        self
+    }
+
+    /// Track or untrack `paths`, relative paths are relative to the current
+    /// directory.
+    pub(crate) fn these(mut self, paths: &[String]) -> Result<Self, OperationError> {
+        let cwd = std::env::current_dir().map_err(CopyError::from)?;
+        self.paths
+            .extend(paths.iter().map(|p| normalize(&cwd.join(p))));
+        Ok(self)
+    }
+
+    /// Assign `untrack`.
+    pub(crate) fn untrack(mut self, untrack: bool) -> Self {
+        self.untrack = untrack;
+        self
+    }
+
+    /// Assign `remove`.
+    pub(crate) fn remove(mut self, remove: bool) -> Self {
+        self.remove = remove;
+        self
+    }
+}
+
+impl Operate for Track {
+    fn operate(&mut self) -> Result<usize, OperationError> {
+        let home = crate::home::dir();
+        let mut config = Config::read(Some(&self.config_path))?;
+
+        for live in &self.paths {
+            let repo = fs::repo_path(&self.repo, live);
+            if self.untrack {
+                config.untrack(home, live)?;
+                crate::output::term(&format!("Untracked {}", live.display()));
+                self.parent_op.report().path(live, "untracked");
+
+                if !self.remove || !repo.exists() {
+                    continue;
+                }
+
+                if self.parent_op.is_dry_run() {
+                    self.parent_op.record([Step::Remove { path: repo }]);
+                    continue;
+                } else if repo.is_dir() {
+                    std::fs::remove_dir_all(&repo).map_err(CopyError::from)?;
+                } else {
+                    std::fs::remove_file(&repo).map_err(CopyError::from)?;
+                }
+                self.parent_op.report().path(&repo, "removed");
+            } else {
+                if !live.exists() {
+                    return Err(ConfigError::BadPath { path: live.clone() }.into());
+                }
+
+                if !config.track(home, live) {
+                    crate::output::term(&format!("Already tracking {}", live.display()));
+                    self.parent_op.report().path(live, "already tracked");
+                    continue;
+                }
+
+                let op = CopyOp::new().from(live).to(&repo);
+                if self.parent_op.is_dry_run() {
+                    self.parent_op.record(op.plan()?);
+                } else {
+                    op.copy()?;
+                }
+                crate::output::term(&format!("Tracking {}", live.display()));
+                self.parent_op.report().path(live, "tracked");
+            }
+        }
+
+        if self.parent_op.is_dry_run() {
+            self.parent_op.record([Step::WriteConfig {
+                path: self.config_path.clone(),
+                config: Box::new(config),
+            }]);
+            return Ok(0);
+        }
+
+        config.write(&self.config_path)?;
+        self.parent_op.insert_result("config_write", Ok(()));
+        crate::output::term("Run `nedots add-changes` to commit.");
+
+        Ok(0)
+    }
+
+    fn exit_code(&mut self) -> usize {
+        let result = self.operate();
+        self.parent_op.finish(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{normalize, Track};
+    use crate::{
+        config::Config,
+        ops::{fs, op::Operate, op::Operation},
+        _TESTS_DIR,
+    };
+    use std::path::Path;
+
+    #[test]
+    /// Expects `.` & `..` to be worked out of tracked paths before they're
+    /// written to the config, and the file to be copied into the repository.
+    fn relative_paths() {
+        assert_eq!(normalize(Path::new("/a/./b/../c")), Path::new("/a/c"));
+        assert_eq!(normalize(Path::new("/../a")), Path::new("/a"));
+
+        let base_path = std::env::current_dir()
+            .unwrap()
+            .join(_TESTS_DIR)
+            .join("track");
+        let (repo, live) = (base_path.join("repo"), base_path.join("bashrc"));
+        std::fs::create_dir_all(&repo).expect("Failed to create track dir!");
+        std::fs::write(&live, "set -o vi\n").expect("Failed to write bashrc!");
+        let config_path = base_path.join("nedots.json");
+        Config::default()
+            .write(&config_path)
+            .expect("Failed to write config!");
+
+        let relative = Path::new(_TESTS_DIR).join("track/../track/./bashrc");
+        let mut track = Track::new(Operation::new())
+            .of(repo.to_path_buf())
+            .config_at(&config_path)
+            .these(&[relative.display().to_string()])
+            .expect("Failed to resolve paths!");
+        assert_eq!(track.paths, [live.to_path_buf()]);
+        track.operate().expect("Failed to track!");
+
+        let config = Config::read(Some(&config_path)).expect("Failed to read config!");
+        let tracked: Vec<&Path> = config
+            .user
+            .iter()
+            .chain(&config.root)
+            .map(|p| p.as_path())
+            .collect();
+        let home = crate::home::dir();
+        assert_eq!(tracked, [live.strip_prefix(home).unwrap_or(&live)]);
+        assert!(fs::repo_path(&repo, &live).is_file());
+
+        std::fs::remove_dir_all(&base_path).expect("Failed to remove track dir!");
+    }
+}
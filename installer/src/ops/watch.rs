@@ -0,0 +1,418 @@
+use super::{
+    op::{Operate, Operation, OperationError},
+    AddChanges,
+};
+use crate::output::{logger::Logger, logger::Logs, TerminalLogger};
+use chrono::Local;
+use nix::{
+    errno::Errno,
+    poll::{poll, PollFd, PollFlags},
+    sys::{
+        inotify::{AddWatchFlags, InitFlags, Inotify, InotifyEvent, WatchDescriptor},
+        signal::{sigaction, SaFlags, SigAction, SigHandler, SigSet, Signal},
+    },
+};
+use std::{
+    collections::HashMap,
+    fs::OpenOptions,
+    io::Write,
+    os::unix::io::AsRawFd,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+use thiserror::Error;
+
+/// Set by `SIGTERM` & `SIGINT`, the watch loop stops once it sees this.
+static STOP: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn stop(_: nix::libc::c_int) {
+    STOP.store(true, Ordering::SeqCst);
+}
+
+#[derive(Debug, Error)]
+/// Errors thrown while watching tracked files.
+pub(crate) enum WatchError {
+    #[error("Failed to watch {path:?}: {errno}")]
+    /// `inotify_add_watch` failed, usually `max_user_watches` was hit.
+    AddWatch { path: PathBuf, errno: Errno },
+
+    #[error("Nothing to watch, none of the tracked paths exist.")]
+    /// Every tracked path is missing.
+    NothingToWatch,
+
+    #[error(transparent)]
+    /// `inotify`, `poll` or `sigaction` failed.
+    Sys(#[from] Errno),
+
+    #[error(transparent)]
+    /// A wrapper around IO errors.
+    IoError(#[from] std::io::Error),
+}
+
+/// Events that mean a tracked file changed.
+fn mask() -> AddWatchFlags {
+    AddWatchFlags::IN_CLOSE_WRITE
+        | AddWatchFlags::IN_CREATE
+        | AddWatchFlags::IN_DELETE
+        | AddWatchFlags::IN_MOVED_FROM
+        | AddWatchFlags::IN_MOVED_TO
+}
+
+/// Watches tracked files & commits changes to them once writes settle.
+pub(crate) struct Watch<'remote> {
+    pub(crate) parent_op: Operation<TerminalLogger>,
+
+    /// Path of the repository.
+    pub(crate) path: PathBuf,
+
+    /// Push to this remote.
+    pub(crate) remote: &'remote str,
+
+    /// Push to this branch on the remote, defaults to the current branch.
+    pub(crate) branch: Option<String>,
+
+    /// Push changes to remote after committing.
+    pub(crate) push: bool,
+
+    /// Tracked paths, relative to $HOME.
+    pub(crate) paths: Vec<PathBuf>,
+
+    /// Wait this long after the last change before committing.
+    pub(crate) debounce: Duration,
+
+    /// Append to this file instead of writing to the terminal.
+    pub(crate) log_file: Option<PathBuf>,
+}
+
+impl<'remote> Watch<'remote> {
+    pub(crate) fn new(op: Operation<TerminalLogger>) -> Self {
+        Self {
+            parent_op: op,
+            path: PathBuf::new(),
+            remote: "origin",
+            branch: None,
+            push: false,
+            paths: Vec::new(),
+            debounce: Duration::from_secs(2),
+            log_file: None,
+        }
+    }
+
+    /// Assign `path`.
+    pub(crate) fn of(mut self, path: PathBuf) -> Self {
+        self.path = path;
+        self
+    }
+
+    /// Assign `remote`.
+    pub(crate) fn with_remote(mut self, remote: &'remote str) -> Self {
+        self.remote = remote;
+        self
+    }
+
+    /// Assign `branch`.
+    pub(crate) fn on_branch(mut self, branch: Option<String>) -> Self {
+        self.branch = branch;
+        self
+    }
+
+    /// Assign `push`.
+    pub(crate) fn push(mut self, push: bool) -> Self {
+        self.push = push;
+        self
+    }
+
+    /// Watch `paths`, relative to $HOME.
+    pub(crate) fn watch_these(mut self, paths: Vec<PathBuf>) -> Self {
+        self.paths.extend(paths);
+        self
+    }
+
+    /// Assign `debounce`.
+    pub(crate) fn debounce(mut self, debounce: Duration) -> Self {
+        self.debounce = debounce;
+        self
+    }
+
+    /// Assign `log_file`.
+    pub(crate) fn log_to(mut self, path: Option<PathBuf>) -> Self {
+        self.log_file = path;
+        self
+    }
+
+    /// Write `msg` to `log_file` with a timestamp, or log it to the terminal.
+    fn note(&self, msg: &str) {
+        match &self.log_file {
+            Some(path) => {
+                let line = format!("{} {}\n", Local::now().to_rfc3339(), msg);
+                if let Err(e) = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .and_then(|mut f| f.write_all(line.as_bytes()))
+                {
+                    self.parent_op.log_error(&e.to_string()).ok();
+                }
+            }
+            None => {
+                self.parent_op.log(msg).ok();
+            }
+        }
+    }
+
+    /// Copy, commit & optionally push, like `add-changes`. Failures are
+    /// noted rather than returned so that the watch carries on, & what each
+    /// sync did is added to this operation's report, which is only finished
+    /// once the watch stops.
+    fn sync(&mut self) {
+        let op = Operation::new()
+            .with_logging(*self.parent_op.logger())
+            .dry_run(self.parent_op.is_dry_run())
+            .json(self.parent_op.is_json());
+        let built = AddChanges::new(op)
+            .to(self.path.to_path_buf())
+            .and_then(|a| a.with_remote(self.remote))
+            .map(|a| a.on_branch(self.branch.clone()).push(self.push))
+            .and_then(|a| a.copy_these(self.paths.clone()));
+
+        let (add, result) = match built {
+            Ok(mut add) => {
+                let result = add.operate();
+                (Some(add), result)
+            }
+            Err(e) => (None, Err(e)),
+        };
+
+        if let Some(mut add) = add {
+            for (key, r) in add.parent_op.results() {
+                if r.is_ok() {
+                    self.parent_op.log(&format!("{}: Ok", key)).ok();
+                }
+            }
+            self.parent_op.record(add.parent_op.plan().to_vec());
+            let synced = std::mem::take(add.parent_op.report());
+            let report = self.parent_op.report();
+            report.paths.extend(synced.paths);
+            report.commits.extend(synced.commits);
+        }
+
+        let key = match &result {
+            Ok(_) => {
+                self.note("Synced tracked files");
+                "syncs"
+            }
+            Err(e) => {
+                self.note(&format!("Failed to sync: {}", e));
+                "failed_syncs"
+            }
+        };
+        let count = &mut self.parent_op.report().details;
+        let n = count.get(key).and_then(|n| n.as_u64()).unwrap_or(0);
+        count.insert(key, (n + 1).into());
+        self.parent_op.insert_result("sync", result.map(|_| ()));
+    }
+}
+
+/// Keeps track of every `inotify` watch & the directory it's on.
+struct Watches {
+    inotify: Inotify,
+
+    /// Directory each watch is on.
+    dirs: HashMap<WatchDescriptor, PathBuf>,
+
+    /// Tracked paths, absolute.
+    tracked: Vec<PathBuf>,
+}
+
+impl Watches {
+    fn new(tracked: Vec<PathBuf>) -> Result<Self, WatchError> {
+        Ok(Self {
+            inotify: Inotify::init(InitFlags::IN_NONBLOCK | InitFlags::IN_CLOEXEC)?,
+            dirs: HashMap::new(),
+            tracked,
+        })
+    }
+
+    /// Watch `dir`, and every directory under it when `recurse` is set.
+    fn add(&mut self, dir: &Path, recurse: bool) -> Result<(), WatchError> {
+        if self.dirs.values().any(|d| d == dir) {
+            return Ok(());
+        }
+
+        let wd = self
+            .inotify
+            .add_watch(dir, mask())
+            .map_err(|errno| WatchError::AddWatch {
+                path: dir.to_path_buf(),
+                errno,
+            })?;
+        self.dirs.insert(wd, dir.to_path_buf());
+
+        if recurse {
+            for e in std::fs::read_dir(dir)? {
+                let e = e?;
+                if e.file_type()?.is_dir() {
+                    self.add(&e.path(), true)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Watch every tracked path. Files are watched through their parent, so
+    /// that editors replacing a file rather than writing to it are noticed.
+    /// Returns the tracked paths that don't exist.
+    fn add_tracked(&mut self) -> Result<Vec<PathBuf>, WatchError> {
+        let mut missing = Vec::new();
+        for p in self.tracked.clone() {
+            if p.is_dir() {
+                self.add(&p, true)?;
+            } else if let (true, Some(parent)) = (p.exists(), p.parent()) {
+                self.add(parent, false)?;
+            } else {
+                missing.push(p);
+            }
+        }
+
+        if self.dirs.is_empty() {
+            return Err(WatchError::NothingToWatch);
+        }
+
+        Ok(missing)
+    }
+
+    /// The tracked path `event` touched, if it touched one at all. New
+    /// directories in a tracked directory are watched too.
+    fn touched(&mut self, event: &InotifyEvent) -> Result<Option<PathBuf>, WatchError> {
+        let path = match (self.dirs.get(&event.wd), &event.name) {
+            (Some(dir), Some(name)) => dir.join(name),
+            (Some(dir), None) => dir.to_path_buf(),
+            _ => return Ok(None),
+        };
+        if !self.tracked.iter().any(|t| path.starts_with(t)) {
+            return Ok(None);
+        }
+
+        let created = AddWatchFlags::IN_CREATE | AddWatchFlags::IN_MOVED_TO;
+        if event.mask.intersects(created) && event.mask.contains(AddWatchFlags::IN_ISDIR) {
+            self.add(&path, true)?;
+        }
+
+        Ok(Some(path))
+    }
+}
+
+impl Operate for Watch<'_> {
+    fn operate(&mut self) -> Result<usize, OperationError> {
+        let handler = SigAction::new(SigHandler::Handler(stop), SaFlags::empty(), SigSet::empty());
+        // SAFETY: `stop` only stores to an atomic, which is signal safe.
+        unsafe {
+            sigaction(Signal::SIGTERM, &handler).map_err(WatchError::from)?;
+            sigaction(Signal::SIGINT, &handler).map_err(WatchError::from)?;
+        }
+
+        let home = crate::home::dir();
+        let mut watches = Watches::new(self.paths.iter().map(|p| home.join(p)).collect())?;
+        let missing = watches.add_tracked()?;
+        for p in &missing {
+            self.note(&format!("{} doesn't exist, not watching it", p.display()));
+        }
+        self.note(&format!(
+            "Watching {} tracked path(s) in {} directories",
+            self.paths.len() - missing.len(),
+            watches.dirs.len()
+        ));
+
+        let debounce: i32 = self.debounce.as_millis().try_into().unwrap_or(i32::MAX);
+        let mut pending = false;
+        while !STOP.load(Ordering::SeqCst) {
+            // Block until something changes, then wait for writes to settle.
+            let timeout = if pending { debounce } else { -1 };
+            let mut fds = [PollFd::new(watches.inotify.as_raw_fd(), PollFlags::POLLIN)];
+            match poll(&mut fds, timeout) {
+                Ok(0) => {
+                    self.sync();
+                    pending = false;
+                    continue;
+                }
+                Ok(_) => {}
+                Err(Errno::EINTR) => continue,
+                Err(e) => return Err(WatchError::from(e).into()),
+            }
+
+            let events = match watches.inotify.read_events() {
+                Ok(events) => events,
+                Err(Errno::EAGAIN) => continue,
+                Err(e) => return Err(WatchError::from(e).into()),
+            };
+            for event in &events {
+                if let Some(path) = watches.touched(event)? {
+                    self.parent_op
+                        .log(&format!("{:?} {}", event.mask, path.display()))
+                        .ok();
+                    pending = true;
+                }
+            }
+        }
+
+        if pending {
+            self.sync();
+        }
+        self.note("Stopped watching");
+
+        Ok(0)
+    }
+
+    fn exit_code(&mut self) -> usize {
+        let result = self.operate();
+        self.parent_op.finish(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Watches;
+    use crate::_TESTS_DIR;
+    use std::path::Path;
+
+    #[test]
+    /// Expects tracked directories to be watched recursively, tracked files
+    /// through their parent, and only changes to tracked paths to count.
+    fn touched() {
+        let base_path = Path::new(_TESTS_DIR).join("watch");
+        std::fs::create_dir_all(base_path.join("fish").join("functions"))
+            .expect("Failed to make watch dir!");
+        let base_path = base_path.canonicalize().unwrap();
+        let rc = base_path.join(".bashrc");
+        std::fs::write(&rc, "").expect("Failed to write .bashrc!");
+
+        let mut watches = Watches::new(vec![
+            base_path.join("fish"),
+            rc.to_path_buf(),
+            base_path.join("missing"),
+        ])
+        .expect("Failed to init inotify!");
+        let missing = watches.add_tracked().expect("Failed to add watches!");
+        assert_eq!(missing, [base_path.join("missing")]);
+        assert_eq!(watches.dirs.len(), 3);
+
+        std::fs::write(&rc, "echo").expect("Failed to write .bashrc!");
+        std::fs::write(base_path.join("notes.txt"), "").expect("Failed to write notes!");
+        std::fs::create_dir(base_path.join("fish").join("conf.d")).expect("Failed to mkdir!");
+
+        let mut touched = Vec::new();
+        for event in watches.inotify.read_events().expect("No events!") {
+            if let Some(p) = watches.touched(&event).expect("Failed to handle event!") {
+                touched.push(p);
+            }
+        }
+        assert!(touched.contains(&rc));
+        assert!(touched.contains(&base_path.join("fish").join("conf.d")));
+        assert!(!touched.contains(&base_path.join("notes.txt")));
+        assert_eq!(watches.dirs.len(), 4);
+
+        std::fs::remove_dir_all(&base_path).expect("Failed to remove watch dir!");
+    }
+}
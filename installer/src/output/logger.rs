@@ -115,7 +115,7 @@ mod tests {
     fn prints_str_stdout() {
         let logger = Logger {};
         if let Err(e) = logger.write_line("Testing prints &str to stdout!") {
-            assert!(false, "{}", e)
+            panic!("{}", e)
         }
     }
 
@@ -123,7 +123,7 @@ mod tests {
     fn prints_str_stderr() {
         let logger = Logger {};
         if let Err(e) = logger.write_line("Testing prints &str to stderr!") {
-            assert!(false, "{}", e)
+            panic!("{}", e)
         }
     }
 
@@ -131,7 +131,7 @@ mod tests {
     fn prints_buf_stdout() {
         let logger = Logger {};
         if let Err(e) = logger.write_line(b"Testing prints &[u8] to stdout!".as_slice()) {
-            assert!(false, "{}", e)
+            panic!("{}", e)
         }
     }
 
@@ -139,7 +139,7 @@ mod tests {
     fn prints_buf_stderr() {
         let logger = Logger {};
         if let Err(e) = logger.write_line(b"Testing prints &[u8] to stderr!".as_slice()) {
-            assert!(false, "{}", e)
+            panic!("{}", e)
         }
     }
 }
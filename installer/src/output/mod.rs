@@ -9,7 +9,24 @@ use self::{
     terminal::Terminal,
     verbosity::{MinVerbosity, Verbose, Verbosity},
 };
-use std::io::Write;
+use std::{
+    io::Write,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+/// Set when output is machine readable, `term` & `term_buf` then write
+/// nothing so stdout only carries what the machine reads.
+static MACHINE_READABLE: AtomicBool = AtomicBool::new(false);
+
+/// Silence `term` & `term_buf`, for when stdout must only carry JSON.
+pub fn machine_readable(on: bool) {
+    MACHINE_READABLE.store(on, Ordering::SeqCst);
+}
+
+/// Whether stdout must only carry JSON.
+pub fn is_machine_readable() -> bool {
+    MACHINE_READABLE.load(Ordering::SeqCst)
+}
 
 #[derive(Debug, Clone, Copy)]
 /// Holds `Verbosity`, and implements `Terminal`.
@@ -98,7 +115,12 @@ impl Logs<TerminalLogger> for TerminalLogger {
 impl Logger<TerminalLogger, dyn Terminal, &str> for TerminalLogger {
     fn log(&self, msg: &str) -> std::io::Result<()> {
         if self.verbose_enough() {
-            return self.write_line(msg);
+            // Verbose lines still reach the terminal with `--output json`,
+            // but on stderr so stdout stays parseable.
+            return match is_machine_readable() {
+                true => self.write_error(msg),
+                false => self.write_line(msg),
+            };
         }
 
         Ok(())
@@ -114,6 +136,10 @@ impl Logger<TerminalLogger, dyn Terminal, &str> for TerminalLogger {
 /// ### Panics
 /// If `write_line` fails to write to stdout.
 pub fn term(msg: &str) {
+    if is_machine_readable() {
+        return;
+    }
+
     TerminalLogger::default()
         .write_line(msg)
         .expect("Failed to write `msg: &str` to stdout!")
@@ -133,7 +159,12 @@ pub fn error(msg: &str) {
 ///
 /// ### Panics
 /// If `write_line` fails to write to stdout.
+#[allow(dead_code)]
 pub fn term_buf(buf: &[u8]) {
+    if is_machine_readable() {
+        return;
+    }
+
     TerminalLogger::default()
         .write_line(buf)
         .expect("Failed to write `buf: &[u8]` to stdout!")
@@ -143,6 +174,7 @@ pub fn term_buf(buf: &[u8]) {
 ///
 /// ### Panics
 /// If `write_error` fails to write to stderr.
+#[allow(dead_code)]
 pub fn error_buf(buf: &[u8]) {
     TerminalLogger::default()
         .write_error(buf)
@@ -175,7 +207,7 @@ mod tests {
     fn term_log_ok() {
         let term = TerminalLogger::default();
         if let Err(e) = term.log("Testing term logs!") {
-            assert!(false, "{}", e)
+            panic!("{}", e)
         }
     }
 
@@ -183,6 +215,6 @@ mod tests {
     fn term_log_err() {
         let term = TerminalLogger::new().with_verbosity(Some(Verbosity::High));
         assert_ne!(term.verbosity(), Some(Verbosity::Debug));
-        assert_eq!(term.verbose_enough(), false);
+        assert!(!term.verbose_enough());
     }
 }
@@ -0,0 +1,208 @@
+use crate::config::{ConfigError, CONFIG_FILES};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+};
+
+/// Name of the user settings file, in the user config directory.
+pub(crate) const SETTINGS_FILE: &str = "settings.json";
+
+/// `$XDG_CONFIG_HOME/nedots`, falling back to `~/.config/nedots`.
+pub(crate) fn config_dir() -> PathBuf {
+    let config = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(c) if !c.is_empty() => PathBuf::from(c),
+        _ => crate::home::dir().join(".config"),
+    };
+
+    config.join("nedots")
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+/// Settings that can come from CLI flags, environment variables or the user
+/// settings file, each overriding the next, with defaults underneath.
+pub(crate) struct Settings {
+    /// Path of `nedots.json`, or `.toml` or `.yaml`, `$NEDOTS_CONFIG`.
+    pub(crate) config: Option<PathBuf>,
+
+    /// Path of the repository, overrides `Config::path`, `$NEDOTS_PATH`.
+    pub(crate) path: Option<PathBuf>,
+
+    /// Remote to push to, pull & compare with, `$NEDOTS_REMOTE`.
+    pub(crate) remote: Option<String>,
+
+    /// Branch to use instead of the default in .gitconfig, `$NEDOTS_BRANCH`.
+    pub(crate) branch: Option<String>,
+
+    /// Level of verbosity, as if `-v` was passed this many times,
+    /// `$NEDOTS_VERBOSE`.
+    pub(crate) verbose: Option<usize>,
+}
+
+impl Settings {
+    /// Settings used when nothing else is set.
+    pub(crate) fn defaults() -> Self {
+        Self {
+            remote: Some(String::from("origin")),
+            ..Default::default()
+        }
+    }
+
+    /// Read the user settings file at `path`, nothing if it doesn't exist.
+    ///
+    /// ### Errors
+    /// Returns `ConfigError::BadSettings` if `serde` fails to deserialize.
+    pub(crate) fn read(path: &Path) -> Result<Self, ConfigError> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        serde_json::from_reader(File::open(path)?).or(Err(ConfigError::BadSettings {
+            path: path.to_path_buf(),
+        }))
+    }
+
+    /// Settings from `NEDOTS_*` environment variables, `var` looks them up.
+    pub(crate) fn from_env(var: impl Fn(&str) -> Option<String>) -> Self {
+        let var = |name: &str| var(name).filter(|v| !v.is_empty());
+        Self {
+            config: var("NEDOTS_CONFIG").map(PathBuf::from),
+            path: var("NEDOTS_PATH").map(PathBuf::from),
+            remote: var("NEDOTS_REMOTE"),
+            branch: var("NEDOTS_BRANCH"),
+            verbose: var("NEDOTS_VERBOSE").and_then(|v| v.parse().ok()),
+        }
+    }
+
+    /// Layer `over` on top, taking whatever it sets.
+    pub(crate) fn layer(self, over: Self) -> Self {
+        Self {
+            config: over.config.or(self.config),
+            path: over.path.or(self.path),
+            remote: over.remote.or(self.remote),
+            branch: over.branch.or(self.branch),
+            verbose: over.verbose.or(self.verbose),
+        }
+    }
+
+    /// The user settings file, then the environment, layered over defaults.
+    pub(crate) fn load() -> Result<Self, ConfigError> {
+        Ok(Self::defaults()
+            .layer(Self::read(&config_dir().join(SETTINGS_FILE))?)
+            .layer(Self::from_env(|name| std::env::var(name).ok())))
+    }
+
+    /// Where the config could be, in the order they're searched: `config`,
+    /// the user config directory, the root of the repository the current
+    /// directory is in, the default repository, then the current directory.
+    /// Each directory is searched for `nedots.json`, `.toml`, `.yaml` &
+    /// `.yml`.
+    pub(crate) fn candidates(&self) -> Vec<PathBuf> {
+        let mut candidates = Vec::new();
+        if let Some(config) = &self.config {
+            candidates.push(config.to_path_buf());
+        }
+
+        let mut dirs = vec![config_dir()];
+        if let Some(root) = std::env::current_dir()
+            .ok()
+            .and_then(|cwd| git2::Repository::discover(cwd).ok())
+            .and_then(|repo| repo.workdir().map(Path::to_path_buf))
+        {
+            dirs.push(root);
+        }
+        dirs.push(crate::home::dir().join(".nedots"));
+        dirs.push(PathBuf::new());
+        for dir in dirs {
+            candidates.extend(CONFIG_FILES.iter().map(|name| dir.join(name)));
+        }
+
+        candidates
+    }
+
+    /// Find the config. A path set explicitly is used even if it doesn't
+    /// exist, so the error names it.
+    ///
+    /// ### Errors
+    /// Returns `ConfigError::NotFound` listing where was searched.
+    pub(crate) fn discover(&self) -> Result<PathBuf, ConfigError> {
+        if let Some(config) = &self.config {
+            return match config.exists() {
+                true => Ok(config.to_path_buf()),
+                false => Err(ConfigError::NotFound {
+                    searched: vec![config.to_path_buf()],
+                }),
+            };
+        }
+
+        let candidates = self.candidates();
+        match candidates.iter().find(|c| c.exists()) {
+            Some(found) => Ok(found.to_path_buf()),
+            None => Err(ConfigError::NotFound {
+                searched: candidates,
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Settings;
+    use crate::_TESTS_DIR;
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    /// Expects each layer to override only what it sets.
+    fn layering() {
+        let file = Settings {
+            remote: Some(String::from("upstream")),
+            branch: Some(String::from("main")),
+            verbose: Some(1),
+            ..Default::default()
+        };
+        let env = Settings::from_env(|name| match name {
+            "NEDOTS_BRANCH" => Some(String::from("laptop")),
+            "NEDOTS_VERBOSE" => Some(String::from("")),
+            _ => None,
+        });
+        let cli = Settings {
+            verbose: Some(3),
+            ..Default::default()
+        };
+
+        let settings = Settings::defaults().layer(file).layer(env).layer(cli);
+        assert_eq!(settings.remote.as_deref(), Some("upstream"));
+        assert_eq!(settings.branch.as_deref(), Some("laptop"));
+        assert_eq!(settings.verbose, Some(3));
+        assert_eq!(
+            Settings::defaults().layer(Settings::default()).remote,
+            Some(String::from("origin"))
+        );
+    }
+
+    #[test]
+    /// Expects an explicit path to be used as is, & everywhere searched to be
+    /// listed when nothing is found.
+    fn discovery() {
+        let dir = Path::new(_TESTS_DIR).join("settings");
+        std::fs::create_dir_all(&dir).expect("Failed to create settings dir!");
+        let config = dir.join("nedots.json");
+        std::fs::write(&config, "{}").expect("Failed to write config!");
+
+        let explicit = Settings {
+            config: Some(config.to_path_buf()),
+            ..Default::default()
+        };
+        assert_eq!(explicit.discover().unwrap(), config);
+        assert_eq!(explicit.candidates()[0], config);
+
+        let missing = Settings {
+            config: Some(PathBuf::from("/nonexistent/nedots.json")),
+            ..Default::default()
+        };
+        assert!(missing.discover().is_err());
+
+        std::fs::remove_dir_all(&dir).expect("Failed to remove settings dir!");
+    }
+}